@@ -0,0 +1,24 @@
+#[cfg(all(unix, feature = "std"))]
+use fslock::LockFile;
+
+#[cfg(all(unix, feature = "std"))]
+fn main() -> Result<(), fslock::Error> {
+    let Some(lockfile) = LockFile::inherit_from_env()? else {
+        println!("NOTHING");
+        return Ok(());
+    };
+    let mut other = LockFile::open(lockfile.path())?;
+
+    if lockfile.owns_lock() && !other.try_lock()? {
+        println!("INHERITED");
+    } else {
+        println!("UNLOCKED");
+    }
+    // Unlocking would release the lock of the parent too.
+    lockfile.disown();
+
+    Ok(())
+}
+
+#[cfg(not(all(unix, feature = "std")))]
+fn main() {}
@@ -1,19 +1,23 @@
+#[cfg(feature = "std")]
 use fslock::LockFile;
+#[cfg(feature = "std")]
 use std::{
     fs::File,
     io::{Read, Write},
 };
 
+#[cfg(feature = "std")]
 fn main() -> Result<(), fslock::Error> {
     fslock::lockfile_truncate(false); // turn off truncation
     {
         let mut lock = LockFile::open("testfiles/preserved.lock")?;
         lock.lock()?;
+        #[cfg(unix)]
         unsafe {
             assert!(lock.raw() != -1);
         }
         let mut file: File = (&mut lock).into();
-        file.write(b"the \xF0\x9F\x90\xAE says moo")?;
+        file.write_all(b"the \xF0\x9F\x90\xAE says moo")?;
         file.sync_all()?;
     } // drop the lock and the writable file
       // open a readable file
@@ -22,3 +26,6 @@ fn main() -> Result<(), fslock::Error> {
     assert_eq!(s, "the 🐮 says moo");
     Ok(())
 }
+
+#[cfg(not(feature = "std"))]
+fn main() {}
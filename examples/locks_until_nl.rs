@@ -17,7 +17,7 @@ fn main() -> Result<(), fslock::Error> {
     };
     let mut lockfile = LockFile::open(&path)?;
     lockfile.lock()?;
-    io::stdin().read(&mut [0; 1])?;
+    let _ = io::stdin().read(&mut [0; 1])?;
 
     Ok(())
 }
@@ -0,0 +1,53 @@
+#[cfg(all(unix, feature = "std"))]
+use fslock::LockFile;
+#[cfg(all(unix, feature = "std"))]
+use std::{
+    env,
+    io,
+    io::Read,
+    process,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Duration,
+};
+
+/// Whether `SIGTERM` was received.
+#[cfg(all(unix, feature = "std"))]
+static TERMINATED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(all(unix, feature = "std"))]
+extern "C" fn handle_sigterm(_signal: libc::c_int) {
+    TERMINATED.store(true, Ordering::Release);
+}
+
+#[cfg(all(unix, feature = "std"))]
+fn main() -> Result<(), fslock::Error> {
+    let mut args = env::args();
+    args.next();
+
+    let path = match args.next() {
+        Some(arg) if args.next().is_none() => arg,
+        _ => {
+            eprintln!("Expected one argument");
+            process::exit(1);
+        },
+    };
+    let handler: extern "C" fn(libc::c_int) = handle_sigterm;
+    unsafe { libc::signal(libc::SIGTERM, handler as libc::sighandler_t) };
+
+    let mut lockfile = LockFile::open(&path)?;
+    lockfile.remove_on_signal(&path)?;
+    lockfile.lock_with_pid()?;
+    println!("LOCKED");
+    while !TERMINATED.load(Ordering::Acquire) {
+        thread::sleep(Duration::from_millis(10));
+    }
+    println!("HANDLED");
+    let _ = io::stdin().read(&mut [0; 1])?;
+    lockfile.unlock()?;
+
+    Ok(())
+}
+
+#[cfg(not(all(unix, feature = "std")))]
+fn main() {}
@@ -0,0 +1,28 @@
+#[cfg(all(unix, feature = "signals"))]
+use fslock::LockFileOptions;
+#[cfg(all(unix, feature = "signals"))]
+use std::{env, io, io::Read, process};
+
+#[cfg(all(unix, feature = "signals"))]
+fn main() -> Result<(), fslock::Error> {
+    let mut args = env::args();
+    args.next();
+
+    let path = match args.next() {
+        Some(arg) if args.next().is_none() => arg,
+        _ => {
+            eprintln!("Expected one argument");
+            process::exit(1);
+        },
+    };
+    let mut lockfile =
+        LockFileOptions::new().remove_on_unlock(true).open(&path)?;
+    lockfile.lock_with_pid()?;
+    println!("LOCKED");
+    let _ = io::stdin().read(&mut [0; 1])?;
+
+    Ok(())
+}
+
+#[cfg(not(all(unix, feature = "signals")))]
+fn main() {}
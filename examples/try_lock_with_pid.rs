@@ -21,7 +21,7 @@ fn main() -> Result<(), fslock::Error> {
     if lockfile.try_lock_with_pid()? {
         let content_a = read_to_string(&path)?;
         let content_b = read_to_string(&path)?;
-        assert!(content_a.trim().len() > 0);
+        assert!(!content_a.trim().is_empty());
         assert!(content_a.trim().chars().all(|ch| ch.is_ascii_digit()));
         assert_eq!(content_a, content_b);
 
@@ -0,0 +1,71 @@
+//! This module implements appending to files shared by multiple writers, such
+//! as logs and journals.
+
+use crate::{sys, Error, LockFile, ToOsStr};
+
+/// Appends the given bytes to the file at the given path, creating it if
+/// needed. The file is exclusively locked while appending, so concurrent
+/// writers using this function never interleave their records, and it is
+/// unlocked before returning. Blocks while someone else owns the lock.
+///
+/// # Panics
+/// Panics if the path contains a nul-byte in a place other than the end.
+///
+/// # Example
+///
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// fslock::append_locked("testfiles/journal.log", b"started\n")?;
+/// fslock::append_locked("testfiles/journal.log", b"stopped\n")?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn append_locked<P>(path: &P, bytes: &[u8]) -> Result<(), Error>
+where
+    P: ToOsStr + ?Sized,
+{
+    append(path, bytes, false)
+}
+
+/// Like [`append_locked`], but also flushes the file to the disk before
+/// unlocking it.
+///
+/// # Panics
+/// Panics if the path contains a nul-byte in a place other than the end.
+///
+/// # Example
+///
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// fslock::append_locked_synced("testfiles/synced.log", b"committed\n")?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn append_locked_synced<P>(path: &P, bytes: &[u8]) -> Result<(), Error>
+where
+    P: ToOsStr + ?Sized,
+{
+    append(path, bytes, true)
+}
+
+/// Locks the file, appends to it, optionally flushes it, and unlocks it,
+/// reporting the first error.
+fn append<P>(path: &P, bytes: &[u8], sync: bool) -> Result<(), Error>
+where
+    P: ToOsStr + ?Sized,
+{
+    let mut file = LockFile::open(path)?;
+    file.truncate_on_close = false;
+    file.lock()?;
+
+    let mut result = sys::seek_end(file.desc);
+    if result.is_ok() {
+        result = sys::write(file.desc, bytes);
+    }
+    if result.is_ok() && sync {
+        result = sys::fsync(file.desc);
+    }
+
+    let unlocked = file.unlock();
+    result.and(unlocked)
+}
@@ -0,0 +1,144 @@
+//! This module implements the asynchronous lock file shared by the runtime
+//! integrations, which only differ in how blocking calls are offloaded.
+
+use std::sync::{Mutex, MutexGuard};
+
+/// Defines an asynchronous `LockFile` wrapping [`crate::LockFile`] in the
+/// invoking module, which must define an `async fn blocking` running the given
+/// function on a blocking pool and resuming its panics.
+macro_rules! async_lock_file {
+    () => {
+        /// An asynchronous handle to a file that is lockable, wrapping a
+        /// [`crate::LockFile`]. If a future of this handle is dropped before
+        /// completing, the underlying operation, once started, still runs to
+        /// completion on the blocking pool, and its outcome (such as owning the
+        /// lock) is kept by the handle.
+        #[derive(Debug)]
+        pub struct LockFile {
+            inner: ::std::sync::Arc<::std::sync::Mutex<$crate::LockFile>>,
+            /// Whether the synchronous handle owns the lock, updated after
+            /// every operation, so it is known while one is running.
+            locked: ::std::sync::Arc<::std::sync::atomic::AtomicBool>,
+        }
+
+        impl LockFile {
+            /// Opens a file for locking, like [`crate::LockFile::open`].
+            ///
+            /// # Panics
+            /// Panics if the path contains a nul-byte in a place other than the
+            /// end.
+            pub async fn open<P>(path: P) -> Result<Self, $crate::Error>
+            where
+                P: AsRef<::std::path::Path>,
+            {
+                let path = path.as_ref().to_path_buf();
+                let file =
+                    blocking(move || $crate::LockFile::open(path.as_os_str()))
+                        .await?;
+                Ok(Self::from(file))
+            }
+
+            /// Locks this file, like [`crate::LockFile::lock`], without
+            /// blocking the runtime while waiting.
+            ///
+            /// # Panics
+            /// Panics if this handle already owns the file.
+            pub async fn lock(&mut self) -> Result<(), $crate::Error> {
+                self.run($crate::LockFile::lock).await
+            }
+
+            /// Locks this file and writes this process's PID into it, like
+            /// [`crate::LockFile::lock_with_pid`], without blocking the runtime
+            /// while waiting.
+            ///
+            /// # Panics
+            /// Panics if this handle already owns the file.
+            pub async fn lock_with_pid(&mut self) -> Result<(), $crate::Error> {
+                self.run($crate::LockFile::lock_with_pid).await
+            }
+
+            /// Locks this file if possible, like [`crate::LockFile::try_lock`].
+            ///
+            /// # Panics
+            /// Panics if this handle already owns the file.
+            pub async fn try_lock(&mut self) -> Result<bool, $crate::Error> {
+                self.run($crate::LockFile::try_lock).await
+            }
+
+            /// Locks this file, like [`crate::LockFile::lock_timeout`], without
+            /// blocking the runtime while waiting.
+            ///
+            /// # Panics
+            /// Panics if this handle already owns the file.
+            pub async fn lock_timeout(
+                &mut self,
+                timeout: ::std::time::Duration,
+            ) -> Result<bool, $crate::Error> {
+                self.run(move |file| file.lock_timeout(timeout)).await
+            }
+
+            /// Returns whether this file handle owns the lock, without waiting
+            /// for operations still running on the blocking pool, such as
+            /// those of dropped futures.
+            pub fn owns_lock(&self) -> bool {
+                self.locked.load(::std::sync::atomic::Ordering::Acquire)
+            }
+
+            /// Unlocks this file, like [`crate::LockFile::unlock`].
+            ///
+            /// # Panics
+            /// Panics if this handle does not own the file.
+            pub async fn unlock(&mut self) -> Result<(), $crate::Error> {
+                self.run($crate::LockFile::unlock).await
+            }
+
+            /// Runs the given operation over the synchronous handle on the
+            /// blocking pool.
+            async fn run<F, T>(
+                &mut self,
+                operation: F,
+            ) -> Result<T, $crate::Error>
+            where
+                F: FnOnce(&mut $crate::LockFile) -> Result<T, $crate::Error>
+                    + Send
+                    + 'static,
+                T: Send + 'static,
+            {
+                let inner = self.inner.clone();
+                let locked = self.locked.clone();
+                blocking(move || {
+                    let mut file = $crate::async_file::lock_inner(&inner);
+                    let result = operation(&mut file);
+                    locked.store(
+                        file.owns_lock(),
+                        ::std::sync::atomic::Ordering::Release,
+                    );
+                    result
+                })
+                .await
+            }
+        }
+
+        impl From<$crate::LockFile> for LockFile {
+            fn from(file: $crate::LockFile) -> Self {
+                let locked =
+                    ::std::sync::atomic::AtomicBool::new(file.owns_lock());
+                Self {
+                    inner: ::std::sync::Arc::new(::std::sync::Mutex::new(file)),
+                    locked: ::std::sync::Arc::new(locked),
+                }
+            }
+        }
+    };
+}
+
+pub(crate) use async_lock_file;
+
+/// Locks the mutex around a synchronous handle. Panics while holding it are
+/// reported to the caller, and leave the handle consistent, so poisoning is
+/// ignored.
+pub(crate) fn lock_inner(
+    inner: &Mutex<crate::LockFile>,
+) -> MutexGuard<'_, crate::LockFile> {
+    inner.lock().unwrap_or_else(|error| error.into_inner())
+}
@@ -0,0 +1,202 @@
+//! This module implements lock files which are replaced or removed while in
+//! use: publishing PIDs atomically, by writing them into a temporary file
+//! which is then renamed over the lock file (see
+//! [`crate::LockFileOptions::atomic_pid`]), and removing lock files on unlock
+//! (see [`crate::LockFileOptions::remove_on_unlock`]). A `linkat` of an
+//! `O_TMPFILE` would avoid the temporary name, but cannot replace an existing
+//! file.
+//!
+//! Either way, a handle may acquire a lock on a file which is no longer at its
+//! path, so handles check the path after locking, and reopen it on mismatch.
+//! Handles not configured so can check and reopen explicitly, such as when
+//! other tools remove lock files (see [`LockFile::validate`]).
+
+#[cfg(all(unix, feature = "std"))]
+use crate::IntoOsString;
+#[cfg(feature = "std")]
+use crate::{signal, sys};
+use crate::{Error, LockFile};
+
+impl LockFile {
+    /// Whether the file just locked by this handle, before recording the lock
+    /// as owned, was replaced at its path since this handle was opened, in
+    /// which case it is unlocked and reopened on the new file, and the caller
+    /// should lock again. The lock is also released if checking fails.
+    #[cfg(all(unix, feature = "std"))]
+    pub(crate) fn reopen_if_replaced(&mut self) -> Result<bool, Error> {
+        if !(self.atomic_pid || self.remove_on_unlock) {
+            return Ok(false);
+        }
+        let same = sys::same_file(self.desc, self.path());
+        if let Ok(true) = same {
+            return Ok(false);
+        }
+        if self.unclaim() {
+            sys::unlock(self.desc, self.backend)?;
+        }
+        same?;
+        let file = self.same_options().open(self.path())?;
+        self.replace_with(file);
+        Ok(true)
+    }
+
+    /// Writes this process's PID into the file, replacing it with a new file
+    /// holding the PID if configured to.
+    #[cfg(all(unix, feature = "std"))]
+    pub(crate) fn publish_pid(&mut self) -> Result<(), Error> {
+        if !self.atomic_pid {
+            return self.write_pid();
+        }
+        let mut temp = self.path.to_path_buf().into_os_string();
+        temp.push(format!(".{}.tmp", sys::pid()));
+        let temp = IntoOsString::into_os_string(temp)?;
+        let mut file =
+            self.same_options().create(true).audit_log(false).open(&temp)?;
+        let result = (|| {
+            sys::truncate(file.desc)?;
+            if !file.try_lock()? {
+                return Err(Error::new(
+                    std::io::ErrorKind::WouldBlock,
+                    "temporary lock file is locked",
+                ));
+            }
+            file.write_pid()?;
+            std::fs::rename(temp.to_path_buf(), self.path.to_path_buf())?;
+            if self.sync {
+                sys::sync_parent(self.path())?;
+            }
+            Ok(())
+        })();
+        if let Err(error) = result {
+            let _ = std::fs::remove_file(temp.to_path_buf());
+            return Err(error);
+        }
+        file.path = self.path.clone();
+        self.replace_with(file);
+        Ok(())
+    }
+
+    /// Removes the file if configured to and this handle, which owns the lock,
+    /// is its last holder. Shared holders first upgrade their lock, which
+    /// fails if anyone else holds one.
+    #[cfg(all(unix, feature = "std"))]
+    pub(crate) fn remove_if_last(&mut self) -> Result<(), Error> {
+        if !self.remove_on_unlock {
+            return Ok(());
+        }
+        if self.shared
+            && (!self.sole_claim() || !sys::try_lock(self.desc, self.backend)?)
+        {
+            return Ok(());
+        }
+        if sys::same_file(self.desc, self.path())? {
+            sys::remove(self.path())?;
+        }
+        Ok(())
+    }
+
+    /// Returns whether this handle still refers to the file at its path, which
+    /// is not the case if the file was removed, or removed and recreated, such
+    /// as by a tool cleaning up lock files while it was locked. Locks held on
+    /// such files no longer exclude handles opening the path.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/validate.lock")?;
+    /// file.lock()?;
+    /// assert!(file.validate()?);
+    /// std::fs::remove_file("testfiles/validate.lock")?;
+    /// assert!(!file.validate()?);
+    /// file.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn validate(&self) -> Result<bool, Error> {
+        sys::same_file(self.desc, self.path())
+    }
+
+    /// Reopens the path of this handle if it no longer refers to the file
+    /// there (see [`LockFile::validate`]), creating it if needed, and locks the
+    /// new file like the old one was locked, if it was, blocking while not
+    /// possible. Returns whether this handle was reopened.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/relock_fresh.lock")?;
+    /// file.lock()?;
+    /// std::fs::remove_file("testfiles/relock_fresh.lock")?;
+    /// assert!(file.relock_fresh()?);
+    /// assert!(file.validate()?);
+    /// assert!(file.owns_lock());
+    /// file.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn relock_fresh(&mut self) -> Result<bool, Error> {
+        if self.validate()? {
+            return Ok(false);
+        }
+        let (locked, shared) = (self.locked, self.shared);
+        if locked {
+            self.unlock()?;
+        }
+        let file = self.same_options().open(self.path())?;
+        self.replace_with(file);
+        match (locked, shared) {
+            (true, true) => self.lock_shared()?,
+            (true, false) => self.lock()?,
+            _ => (),
+        }
+        Ok(true)
+    }
+
+    /// Replaces this handle with the given one for the same path, keeping the
+    /// heartbeat and the signal registration of this handle.
+    #[cfg(feature = "std")]
+    pub(crate) fn replace_with(&mut self, mut file: LockFile) {
+        file.heartbeat = self.heartbeat.take();
+        file.keep_locked = core::mem::take(&mut self.keep_locked);
+        file.poison = self.poison;
+        file.metrics = core::mem::take(&mut self.metrics);
+        file.audit = core::mem::take(&mut self.audit);
+        if let Some(index) = self.signal_slot.take() {
+            if let Some(previous) = file.signal_slot.replace(index) {
+                signal::unregister(previous);
+            }
+            signal::set_desc(index, file.desc);
+        }
+        let locked = file.locked;
+        *self = file;
+        self.set_locked(locked);
+    }
+
+    /// Files are never replaced here.
+    #[cfg(not(all(unix, feature = "std")))]
+    pub(crate) fn reopen_if_replaced(&mut self) -> Result<bool, Error> {
+        Ok(false)
+    }
+
+    /// Files are never removed here.
+    #[cfg(not(all(unix, feature = "std")))]
+    pub(crate) fn remove_if_last(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// PIDs are always written in place here.
+    #[cfg(not(all(unix, feature = "std")))]
+    pub(crate) fn publish_pid(&mut self) -> Result<(), Error> {
+        self.write_pid()
+    }
+}
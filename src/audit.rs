@@ -0,0 +1,51 @@
+//! This module implements audit trails of lock files: a line is appended to
+//! `<lock>.history` whenever a handle configured to (see
+//! [`crate::LockFileOptions::audit_log`]) acquires or releases its lock, while
+//! it still owns the lock.
+
+use crate::{info::iso_timestamp, sys, Error, LockFile};
+use std::{fs::OpenOptions, io::Write, time::SystemTime};
+
+/// Suffix appended to the path of lock files to name their history.
+const SUFFIX: &str = ".history";
+
+impl LockFile {
+    /// Appends a line recording that this handle acquired or released its
+    /// lock to the history of the file, if configured to. Failures are only
+    /// reported through the features `tracing` and `log`, since the lock was
+    /// already acquired, or is being released anyway.
+    pub(crate) fn audit(&self, locked: bool) {
+        if !self.audit {
+            return;
+        }
+        let action = match (locked, self.shared) {
+            (true, false) => "acquire-exclusive",
+            (true, true) => "acquire-shared",
+            (false, _) => "release",
+        };
+        if let Err(error) = self.append_history(action) {
+            self.trace_error("audit", error);
+        }
+    }
+
+    /// Appends a line recording the given action to the history of the file.
+    fn append_history(&self, action: &str) -> Result<(), Error> {
+        let line = format!(
+            "{} pid={} host={} action={}\n",
+            iso_timestamp(SystemTime::now()),
+            sys::pid(),
+            sys::hostname()?,
+            action,
+        );
+        let mut path = self.path.to_path_buf().into_os_string();
+        path.push(SUFFIX);
+        let mut history =
+            OpenOptions::new().create(true).append(true).open(path)?;
+        // A single write appends the whole line, even next to shared holders.
+        history.write_all(line.as_bytes())?;
+        if self.sync {
+            history.sync_data()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,102 @@
+//! This module implements lock acquisition which other threads may cancel.
+
+use crate::{retry::Backoff, Error, LockFile, RetryPolicy};
+use std::{
+    sync::{Arc, Condvar, Mutex},
+    time::Duration,
+};
+
+/// A token through which other threads cancel blocking acquisitions, such as
+/// from the "Cancel" button of a dialog waiting for a lock. Clones share the
+/// same state, and a canceled token stays canceled.
+///
+/// # Example
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// use fslock::{CancelToken, LockFile};
+///
+/// let token = CancelToken::new();
+/// let button = token.clone();
+/// std::thread::spawn(move || {
+///     wait_for_click();
+///     button.cancel();
+/// });
+///
+/// let mut file = LockFile::open("testfiles/cancel.lock")?;
+/// if file.lock_cancelable(&token)? {
+///     do_stuff();
+///     file.unlock()?;
+/// }
+///
+/// # Ok(())
+/// # }
+/// # fn wait_for_click() {}
+/// # fn do_stuff() {
+/// #    // doing stuff here.
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    /// Whether the token was canceled, notified when it is.
+    shared: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl CancelToken {
+    /// Creates a token which is not canceled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels acquisitions waiting on this token, or on any of its clones,
+    /// now or later.
+    pub fn cancel(&self) {
+        let (canceled, changed) = &*self.shared;
+        *canceled.lock().unwrap_or_else(|error| error.into_inner()) = true;
+        changed.notify_all();
+    }
+
+    /// Returns whether this token was canceled.
+    pub fn is_canceled(&self) -> bool {
+        *self.shared.0.lock().unwrap_or_else(|error| error.into_inner())
+    }
+
+    /// Blocks until the timeout elapses, or earlier if this token is
+    /// canceled. Returns whether it was canceled.
+    fn wait(&self, timeout: Duration) -> bool {
+        let (canceled, changed) = &*self.shared;
+        let canceled =
+            canceled.lock().unwrap_or_else(|error| error.into_inner());
+        *changed
+            .wait_timeout_while(canceled, timeout, |canceled| !*canceled)
+            .unwrap_or_else(|error| error.into_inner())
+            .0
+    }
+}
+
+impl LockFile {
+    /// Locks this file, blocking while it is not possible to lock (i.e.
+    /// someone else already owns a lock), until the given token is canceled.
+    /// Returns whether the lock was acquired; it is not if the token was
+    /// already canceled. While waiting, attempts are retried every few
+    /// milliseconds, and cancellation is noticed immediately.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns the file.
+    pub fn lock_cancelable(
+        &mut self,
+        token: &CancelToken,
+    ) -> Result<bool, Error> {
+        self.waiting(|file| {
+            let policy = RetryPolicy::default();
+            let mut backoff = Backoff::new(&policy);
+            while !token.is_canceled() {
+                if file.try_lock()? {
+                    return Ok(true);
+                }
+                let delay = backoff.next_delay().unwrap_or(policy.max_delay);
+                token.wait(delay);
+            }
+            Ok(false)
+        })
+    }
+}
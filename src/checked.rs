@@ -0,0 +1,149 @@
+//! This module implements non-panicking variants of the locking methods,
+//! reporting misuse as errors.
+
+use crate::{Error, LockFile};
+use core::fmt;
+
+/// An error of the checked locking methods, such as [`LockFile::checked_lock`].
+#[derive(Debug)]
+pub enum LockError {
+    /// This handle already owns the lock.
+    AlreadyLocked,
+    /// This handle does not own the lock.
+    NotLocked,
+    /// This handle is an observer (see [`LockFile::try_clone`]), which never
+    /// locks.
+    Observer,
+    /// The operating system reported an error.
+    Io(Error),
+}
+
+impl From<Error> for LockError {
+    fn from(error: Error) -> Self {
+        LockError::Io(error)
+    }
+}
+
+impl fmt::Display for LockError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LockError::AlreadyLocked => {
+                write!(fmt, "lock file handle already owns the lock")
+            },
+            LockError::NotLocked => {
+                write!(fmt, "lock file handle does not own the lock")
+            },
+            LockError::Observer => {
+                write!(fmt, "lock file handle is an observer and cannot lock")
+            },
+            LockError::Io(error) => write!(fmt, "{}", error),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for LockError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LockError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<LockError> for std::io::Error {
+    fn from(error: LockError) -> Self {
+        match error {
+            LockError::Io(error) => error,
+            other => std::io::Error::other(other),
+        }
+    }
+}
+
+impl LockFile {
+    /// Locks this file like [`LockFile::lock`], but returns
+    /// [`LockError::AlreadyLocked`] instead of panicking if this handle
+    /// already owns the file, and [`LockError::Observer`] if it is an observer.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::LockError> {
+    /// use fslock::{LockError, LockFile};
+    ///
+    /// let mut file = LockFile::open("testfiles/checked_lock.lock")?;
+    /// file.checked_lock()?;
+    /// assert!(matches!(file.checked_lock(), Err(LockError::AlreadyLocked)));
+    /// file.checked_unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn checked_lock(&mut self) -> Result<(), LockError> {
+        if self.owns_lock() {
+            return Err(LockError::AlreadyLocked);
+        }
+        if self.observer {
+            return Err(LockError::Observer);
+        }
+        self.lock()?;
+        Ok(())
+    }
+
+    /// Locks this file like [`LockFile::try_lock`], but returns
+    /// [`LockError::AlreadyLocked`] instead of panicking if this handle
+    /// already owns the file, and [`LockError::Observer`] if it is an observer.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::LockError> {
+    /// use fslock::{LockError, LockFile};
+    ///
+    /// let mut file = LockFile::open("testfiles/checked_try_lock.lock")?;
+    /// if file.checked_try_lock()? {
+    ///     assert!(matches!(
+    ///         file.checked_try_lock(),
+    ///         Err(LockError::AlreadyLocked)
+    ///     ));
+    ///     file.checked_unlock()?;
+    /// }
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn checked_try_lock(&mut self) -> Result<bool, LockError> {
+        if self.owns_lock() {
+            return Err(LockError::AlreadyLocked);
+        }
+        if self.observer {
+            return Err(LockError::Observer);
+        }
+        Ok(self.try_lock()?)
+    }
+
+    /// Unlocks this file like [`LockFile::unlock`], but returns
+    /// [`LockError::NotLocked`] instead of panicking if this handle does not
+    /// own the file.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::LockError> {
+    /// use fslock::{LockError, LockFile};
+    ///
+    /// let mut file = LockFile::open("testfiles/checked_unlock.lock")?;
+    /// assert!(matches!(file.checked_unlock(), Err(LockError::NotLocked)));
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn checked_unlock(&mut self) -> Result<(), LockError> {
+        if !self.owns_lock() {
+            return Err(LockError::NotLocked);
+        }
+        self.unlock()?;
+        Ok(())
+    }
+}
@@ -1,30 +1,84 @@
-use core::cell::RefCell;
+use core::sync::atomic::{AtomicBool, Ordering};
 
 // A constant controlling whether the ``LockFile::open`` function truncates
-// files when it is closed/unlocked. On `no_std`, mutation is unsafe.
+// files when it is closed/unlocked, shared by every thread.
+static DEFAULT_LOCKFILE_TRUNCATE: AtomicBool = AtomicBool::new(true);
+
+// An override of the constant above for the current thread, set by
+// ``TruncateGuard``.
 #[cfg(feature = "std")]
 std::thread_local!(
-pub static DEFAULT_LOCKFILE_TRUNCATE: RefCell<bool> = RefCell::new(true)
+static TRUNCATE_OVERRIDE: core::cell::Cell<Option<bool>> =
+    const { core::cell::Cell::new(None) }
 );
-#[cfg(not(feature = "std"))]
-pub static mut DEFAULT_LOCKFILE_TRUNCATE: RefCell<bool> = RefCell::new(true);
 
-#[cfg(feature = "std")]
 pub(crate) fn default_lockfile_truncate_state() -> bool {
-    DEFAULT_LOCKFILE_TRUNCATE.with(|b| *b.borrow())
-}
-#[cfg(not(feature = "std"))]
-pub(crate) unsafe fn default_lockfile_truncate_state() -> bool {
-    *DEFAULT_LOCKFILE_TRUNCATE.borrow()
+    #[cfg(feature = "std")]
+    if let Some(truncate) = TRUNCATE_OVERRIDE.with(|cell| cell.get()) {
+        return truncate;
+    }
+    DEFAULT_LOCKFILE_TRUNCATE.load(Ordering::Relaxed)
 }
 
-/// Change the state of default file truncation (default true). On `#[no_std]`,
-/// mutation is unsafe!
-#[cfg(feature = "std")]
+/// Change the state of default file truncation (default true) for every
+/// thread. Threads with a [`TruncateGuard`] alive keep their override.
 pub fn lockfile_truncate(dlft: bool) {
-    DEFAULT_LOCKFILE_TRUNCATE.with(|b| *b.borrow_mut() = dlft)
+    DEFAULT_LOCKFILE_TRUNCATE.store(dlft, Ordering::Relaxed);
 }
-#[cfg(not(feature = "std"))]
-pub unsafe fn lockfile_truncate(dlft: bool) {
-    *DEFAULT_LOCKFILE_TRUNCATE.borrow_mut() = dlft;
+
+/// A scoped override of the default file truncation, restoring the previous
+/// state on drop. With `std`, the override only applies to the current thread,
+/// so libraries may change the behavior locally without stomping on each
+/// other. Without `std`, it changes the state for every thread, like
+/// [`lockfile_truncate`].
+///
+/// # Example
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// use fslock::{LockFile, TruncateGuard};
+///
+/// let file = {
+///     let _guard = TruncateGuard::new(false);
+///     LockFile::open("testfiles/truncate_guard.lock")?
+/// };
+/// assert!(!file.truncate_on_close);
+/// assert!(LockFile::open("testfiles/truncate_guard.lock")?.truncate_on_close);
+///
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+#[must_use = "the override is reverted as soon as the guard is dropped"]
+pub struct TruncateGuard {
+    /// State restored on drop.
+    #[cfg(feature = "std")]
+    previous: Option<bool>,
+    /// State restored on drop.
+    #[cfg(not(feature = "std"))]
+    previous: bool,
+    /// The override belongs to the thread which created the guard.
+    _thread: core::marker::PhantomData<*const ()>,
+}
+
+impl TruncateGuard {
+    /// Overrides the default file truncation until the returned guard is
+    /// dropped.
+    pub fn new(truncate: bool) -> Self {
+        #[cfg(feature = "std")]
+        let previous =
+            TRUNCATE_OVERRIDE.with(|cell| cell.replace(Some(truncate)));
+        #[cfg(not(feature = "std"))]
+        let previous =
+            DEFAULT_LOCKFILE_TRUNCATE.swap(truncate, Ordering::Relaxed);
+        Self { previous, _thread: core::marker::PhantomData }
+    }
+}
+
+impl Drop for TruncateGuard {
+    fn drop(&mut self) {
+        #[cfg(feature = "std")]
+        TRUNCATE_OVERRIDE.with(|cell| cell.set(self.previous));
+        #[cfg(not(feature = "std"))]
+        DEFAULT_LOCKFILE_TRUNCATE.store(self.previous, Ordering::Relaxed);
+    }
 }
@@ -0,0 +1,170 @@
+//! This module implements access to the contents of lock files through their
+//! handles.
+
+use crate::{sys, Error, LockFile};
+
+/// Size of the chunks in which contents are read.
+#[cfg(feature = "std")]
+const CHUNK_SIZE: usize = 256;
+
+impl LockFile {
+    /// Reads the whole contents of this file through this handle, such as the
+    /// PID written by [`LockFile::lock_with_pid`], keeping the current
+    /// position of the handle. The file must have been opened for reading,
+    /// which is the default.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/read_contents.lock")?;
+    /// file.lock_with_pid()?;
+    /// let contents = file.read_contents()?;
+    /// let pid = String::from_utf8_lossy(&contents);
+    /// assert_eq!(pid.trim(), std::process::id().to_string());
+    /// file.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn read_contents(&mut self) -> Result<Vec<u8>, Error> {
+        debug_assert!(self.desc != sys::uninitialized_fd());
+        let mut contents = Vec::new();
+        loop {
+            let start = contents.len();
+            contents.resize(start + CHUNK_SIZE, 0);
+            let read =
+                sys::read_at(self.desc, &mut contents[start ..], start as u64)?;
+            contents.truncate(start + read);
+            if read == 0 {
+                break Ok(contents);
+            }
+        }
+    }
+
+    /// Replaces the contents of this file with the given bytes, such as a
+    /// small payload telling other processes how to reach the owner, and
+    /// flushes them to the disk. The file is truncated on unlock if
+    /// [`LockFile::truncate_on_close`] is set.
+    ///
+    /// # Panics
+    /// Panics if this handle does not own an exclusive lock.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/write_contents.lock")?;
+    /// file.lock()?;
+    /// file.write_contents(br#"{"port":8080}"#)?;
+    /// do_stuff();
+    /// file.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    ///
+    /// # Panicking Example
+    ///
+    /// ```should_panic
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/write_contents_panic.lock")?;
+    /// file.write_contents(b"unlocked")?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn write_contents(&mut self, contents: &[u8]) -> Result<(), Error> {
+        debug_assert!(self.desc != sys::uninitialized_fd());
+        if !self.locked || self.shared {
+            panic!("Cannot write contents without owning an exclusive lock");
+        }
+        sys::truncate(self.desc)?;
+        sys::write(self.desc, contents)?;
+        sys::fsync(self.desc)
+    }
+}
+
+/// Reads from the file at the current position of the handle.
+///
+/// # Example
+///
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// use fslock::LockFile;
+/// use std::io::{Read, Seek, SeekFrom, Write};
+///
+/// let mut file = LockFile::open("testfiles/io_read.lock")?;
+/// file.lock()?;
+/// write!(file, "state=ready")?;
+/// file.seek(SeekFrom::Start(0))?;
+/// let mut state = String::new();
+/// file.read_to_string(&mut state)?;
+/// assert_eq!(state, "state=ready");
+/// file.unlock()?;
+///
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "std")]
+impl std::io::Read for LockFile {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        debug_assert!(self.desc != sys::uninitialized_fd());
+        sys::read(self.desc, buf)
+    }
+}
+
+/// Moves the position of the handle, at which reads and writes happen.
+#[cfg(feature = "std")]
+impl std::io::Seek for LockFile {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> Result<u64, Error> {
+        debug_assert!(self.desc != sys::uninitialized_fd());
+        sys::seek(self.desc, pos)
+    }
+}
+
+/// Writes into the file at the current position of the handle, which follows
+/// any PID written by [`LockFile::lock_with_pid`]. Writes are not buffered, so
+/// flushing has no effect.
+///
+/// # Example
+///
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// use fslock::LockFile;
+/// use std::io::Write;
+///
+/// let mut file = LockFile::open("testfiles/io_write.lock")?;
+/// file.lock_with_pid()?;
+/// writeln!(file, "port={}", 8080)?;
+/// do_stuff();
+/// file.unlock()?;
+///
+/// # Ok(())
+/// # }
+/// # fn do_stuff() {
+/// #    // doing stuff here.
+/// # }
+/// ```
+#[cfg(feature = "std")]
+impl std::io::Write for LockFile {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        debug_assert!(self.desc != sys::uninitialized_fd());
+        sys::write(self.desc, buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
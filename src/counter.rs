@@ -0,0 +1,88 @@
+//! This module implements counters shared across processes, such as build
+//! numbers assigned to parallel jobs.
+
+use crate::{Error, LockFile, ToOsStr};
+use std::io::ErrorKind;
+
+/// A counter stored in decimal in a file, updated under an exclusive lock so
+/// that concurrent processes never observe nor assign the same value twice.
+/// A missing or empty file counts as zero.
+///
+/// # Example
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// use fslock::FileCounter;
+///
+/// let mut counter = FileCounter::open("testfiles/build_number")?;
+/// # counter.store(0)?;
+/// let build = counter.fetch_add(1)?;
+/// assert!(counter.load()? > build);
+///
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct FileCounter {
+    /// The file holding the counter.
+    file: LockFile,
+}
+
+impl FileCounter {
+    /// Opens the counter at the given path, creating the file if needed.
+    ///
+    /// # Panics
+    /// Panics if the path contains a nul-byte in a place other than the end.
+    pub fn open<P>(path: &P) -> Result<Self, Error>
+    where
+        P: ToOsStr + ?Sized,
+    {
+        let mut file = LockFile::open(path)?;
+        file.truncate_on_close = false;
+        Ok(Self { file })
+    }
+
+    /// Parses the value of the counter, which this handle must have locked.
+    fn read(&mut self) -> Result<u64, Error> {
+        let contents = self.file.read_contents()?;
+        let contents = String::from_utf8_lossy(&contents);
+        let contents = contents.trim();
+        if contents.is_empty() {
+            return Ok(0);
+        }
+        contents.parse().map_err(|_| {
+            Error::new(ErrorKind::InvalidData, "lock file is not a counter")
+        })
+    }
+
+    /// Reads the value of the counter. Blocks while someone else is updating
+    /// it.
+    pub fn load(&mut self) -> Result<u64, Error> {
+        self.file.lock_shared()?;
+        let result = self.read();
+        let unlocked = self.file.unlock();
+        result.and_then(|value| unlocked.map(|()| value))
+    }
+
+    /// Replaces the value of the counter. Blocks while someone else is using
+    /// it.
+    pub fn store(&mut self, value: u64) -> Result<(), Error> {
+        self.file.lock()?;
+        let result =
+            self.file.write_contents(format!("{}\n", value).as_bytes());
+        let unlocked = self.file.unlock();
+        result.and(unlocked)
+    }
+
+    /// Adds to the value of the counter, wrapping around on overflow, and
+    /// returns the previous value. Blocks while someone else is using it.
+    pub fn fetch_add(&mut self, value: u64) -> Result<u64, Error> {
+        self.file.lock()?;
+        let result = self.read().and_then(|previous| {
+            let next = format!("{}\n", previous.wrapping_add(value));
+            self.file.write_contents(next.as_bytes())?;
+            Ok(previous)
+        });
+        let unlocked = self.file.unlock();
+        result.and_then(|previous| unlocked.map(|()| previous))
+    }
+}
@@ -0,0 +1,280 @@
+//! This module implements locks by atomically creating directories, which
+//! works even on network file systems without support for file locks.
+
+use crate::{
+    info::iso_timestamp,
+    lease::{new_token, read_if_exists, with_suffix},
+    retry::Backoff,
+    sys,
+    Error,
+    LockInfo,
+    RetryPolicy,
+    ToOsStr,
+};
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+/// Name of the file inside the directory recording its owner.
+const OWNER_FILE: &str = "owner";
+
+/// Formats the record of this process owning a lock, as a [`LockInfo`] with a
+/// `token=` line identifying the handle.
+pub(crate) fn owner_record(token: &str) -> Result<String, Error> {
+    Ok(format!(
+        "{}\nhost={}\ntime={}\ntoken={}\n",
+        std::process::id(),
+        sys::hostname()?,
+        iso_timestamp(SystemTime::now()),
+        token,
+    ))
+}
+
+/// Whether a lock whose owner record has the given contents is stale: either
+/// because the owner died, or because the file at the given path was not
+/// modified for longer than `stale_after`. A lock without a readable record is
+/// either being created or was abandoned midway, and is only stale once old
+/// enough.
+pub(crate) fn is_stale(
+    path: &Path,
+    owner: Option<&[u8]>,
+    stale_after: Option<Duration>,
+) -> Result<bool, Error> {
+    if let Some(info) = owner.and_then(LockInfo::parse) {
+        if info.is_dead()? {
+            return Ok(true);
+        }
+    }
+    let Some(stale_after) = stale_after else {
+        return Ok(false);
+    };
+    let modified = match fs::metadata(path) {
+        Ok(metadata) => metadata.modified()?,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(true),
+        Err(error) => return Err(error),
+    };
+    let age = SystemTime::now().duration_since(modified);
+    Ok(age.is_ok_and(|age| age > stale_after))
+}
+
+/// A lock held by creating a directory, which `mkdir` does atomically, failing
+/// if it exists, even over NFS. The directory holds an `owner` file recording
+/// the PID, host name and time of locking (see [`LockInfo`]) of its owner.
+///
+/// A directory whose owner died, according to [`LockInfo`], is considered
+/// stale and taken over, as is one whose `owner` file was not modified for
+/// longer than [`DirLock::set_stale_after`], if set. The latter is needed to
+/// detect owners on other hosts, which should then call
+/// [`DirLock::refresh`] regularly. Unlike file locks, a directory is NOT
+/// removed when its owner exits without unlocking it.
+///
+/// # Example
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// use fslock::DirLock;
+/// use std::time::Duration;
+///
+/// let mut lock = DirLock::new("testfiles/dir.lock")?;
+/// lock.set_stale_after(Some(Duration::from_secs(60)));
+/// lock.lock()?;
+/// do_stuff();
+/// lock.unlock()?;
+///
+/// # Ok(())
+/// # }
+/// # fn do_stuff() {
+/// #    // doing stuff here.
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct DirLock {
+    /// Path of the directory.
+    path: PathBuf,
+    /// Age of the `owner` file after which the lock is stale.
+    stale_after: Option<Duration>,
+    /// Token identifying this handle as the owner.
+    token: String,
+    /// Contents of the `owner` file written by this handle, if locked.
+    owner: Option<Vec<u8>>,
+}
+
+impl DirLock {
+    /// Creates a handle to the lock at the given path. No directory is
+    /// created until the lock is acquired.
+    ///
+    /// # Panics
+    /// Panics if the path contains a nul-byte in a place other than the end.
+    pub fn new<P>(path: &P) -> Result<Self, Error>
+    where
+        P: ToOsStr + ?Sized,
+    {
+        Ok(Self {
+            path: path.to_os_str()?.to_path_buf(),
+            stale_after: None,
+            token: new_token(),
+            owner: None,
+        })
+    }
+
+    /// Sets how long after its `owner` file was last modified the lock is
+    /// considered stale, or `None` (the default) to only consider locks of dead
+    /// owners on this host stale.
+    pub fn set_stale_after(&mut self, stale_after: Option<Duration>) {
+        self.stale_after = stale_after;
+    }
+
+    /// Returns the path of the directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns whether this handle owns the lock, as far as it knows: the lock
+    /// may have been taken over as stale.
+    pub fn owns_lock(&self) -> bool {
+        self.owner.is_some()
+    }
+
+    /// Returns the path of the `owner` file in the given directory.
+    fn owner_path(dir: &Path) -> PathBuf {
+        dir.join(OWNER_FILE)
+    }
+
+    /// Creates the directory and records this handle as its owner. Does NOT
+    /// block if someone else holds the lock. Returns whether it was acquired.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns the lock.
+    pub fn try_lock(&mut self) -> Result<bool, Error> {
+        if self.owner.is_some() {
+            panic!("Cannot lock if already owning a lock");
+        }
+        loop {
+            match fs::create_dir(&self.path) {
+                Ok(()) => break,
+                Err(error) if error.kind() == ErrorKind::AlreadyExists => (),
+                Err(error) => return Err(error),
+            }
+            let observed = read_if_exists(&Self::owner_path(&self.path))?;
+            let modified = match observed {
+                Some(_) => Self::owner_path(&self.path),
+                None => self.path.clone(),
+            };
+            if !is_stale(&modified, observed.as_deref(), self.stale_after)? {
+                return Ok(false);
+            }
+            self.take_over(observed.as_deref())?;
+        }
+        let owner = owner_record(&self.token)?;
+        if let Err(error) = fs::write(Self::owner_path(&self.path), &owner) {
+            let _ = fs::remove_dir_all(&self.path);
+            return Err(error);
+        }
+        self.owner = Some(owner.into_bytes());
+        Ok(true)
+    }
+
+    /// Removes the stale directory whose `owner` file had the given contents,
+    /// unless it was replaced meanwhile. A directory replaced meanwhile is
+    /// moved back into place.
+    fn take_over(&self, observed: Option<&[u8]>) -> Result<(), Error> {
+        let grave = with_suffix(&self.path, &format!(".{}.old", self.token));
+        match fs::rename(&self.path, &grave) {
+            Ok(()) => (),
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(error) => return Err(error),
+        }
+        let moved = read_if_exists(&Self::owner_path(&grave))?;
+        if moved.as_deref() != observed
+            && fs::rename(&grave, &self.path).is_ok()
+        {
+            return Ok(());
+        }
+        fs::remove_dir_all(&grave)
+    }
+
+    /// Acquires the lock, retrying while someone else holds it, until it is
+    /// released or becomes stale.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns the lock.
+    pub fn lock(&mut self) -> Result<(), Error> {
+        let policy = RetryPolicy {
+            max_delay: Duration::from_secs(1),
+            ..RetryPolicy::default()
+        };
+        self.lock_with_policy(&policy)?;
+        Ok(())
+    }
+
+    /// Acquires the lock, retrying while someone else holds it as configured
+    /// by the given policy. Returns whether the lock was acquired before
+    /// attempts were exhausted.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns the lock.
+    pub fn lock_with_policy(
+        &mut self,
+        policy: &RetryPolicy,
+    ) -> Result<bool, Error> {
+        let mut backoff = Backoff::new(policy);
+        loop {
+            if self.try_lock()? {
+                return Ok(true);
+            }
+            match backoff.next_delay() {
+                Some(delay) => thread::sleep(delay),
+                None => return Ok(false),
+            }
+        }
+    }
+
+    /// Reads the record of the owner of the lock, if locked.
+    pub fn owner_info(&self) -> Result<Option<LockInfo>, Error> {
+        let owner = read_if_exists(&Self::owner_path(&self.path))?;
+        Ok(owner.and_then(|owner| LockInfo::parse(&owner)))
+    }
+
+    /// Refreshes the modification time of the `owner` file, so the lock is
+    /// not considered stale by handles with [`DirLock::set_stale_after`].
+    ///
+    /// # Panics
+    /// Panics if this handle does not own the lock.
+    pub fn refresh(&self) -> Result<(), Error> {
+        if self.owner.is_none() {
+            panic!("Attempted to refresh an unowned lock");
+        }
+        sys::touch(&Self::owner_path(&self.path).to_os_str()?)
+    }
+
+    /// Releases the lock by removing the directory, unless it was taken over
+    /// as stale.
+    ///
+    /// # Panics
+    /// Panics if this handle does not own the lock.
+    pub fn unlock(&mut self) -> Result<(), Error> {
+        let Some(owner) = self.owner.take() else {
+            panic!("Attempted to unlock already unlocked lockfile");
+        };
+        let owner_path = Self::owner_path(&self.path);
+        if read_if_exists(&owner_path)?.as_deref() != Some(&owner[..]) {
+            return Ok(());
+        }
+        fs::remove_file(owner_path)?;
+        match fs::remove_dir(&self.path) {
+            Err(error) if error.kind() != ErrorKind::NotFound => Err(error),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for DirLock {
+    fn drop(&mut self) {
+        if self.owner.is_some() {
+            let _ = self.unlock();
+        }
+    }
+}
@@ -0,0 +1,234 @@
+//! This module implements lock files compatible with `liblockfile` and
+//! `dotlockfile(1)`, as used on mail spools and by cron tooling.
+
+use crate::{lease::read_if_exists, sys, Error, ToOsStr};
+use std::{
+    fs,
+    io::ErrorKind,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+/// Age after which a lock file is stale, unless refreshed.
+const STALE_AFTER: Duration = Duration::from_secs(300);
+
+/// Increment of the delay between attempts.
+const SLEEP_STEP: Duration = Duration::from_secs(5);
+
+/// Maximum delay between attempts.
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+/// A lock file following the conventions of `liblockfile` and
+/// `dotlockfile(1)`, so Rust tools can interoperate with mail and cron tooling
+/// expecting them: the lock is held by creating the lock file, usually named
+/// after the locked file with a `.lock` suffix, and holding the PID of its
+/// owner followed by a newline. It is created by hard-linking a temporary file
+/// in the same directory and checking its link count, which works over NFS.
+///
+/// A lock file is stale, and removed by contenders, if its PID no longer runs
+/// on this host, or if it was not modified for five minutes: owners holding
+/// the lock for longer must call [`DotLock::refresh`], such as every minute.
+/// Retrying sleeps five more seconds after each attempt, up to a minute.
+///
+/// # Example
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// use fslock::DotLock;
+///
+/// let mut lock = DotLock::for_file("testfiles/mailbox")?;
+/// assert!(lock.path().ends_with("mailbox.lock"));
+/// if lock.lock_with_retries(5)? {
+///     do_stuff();
+///     lock.unlock()?;
+/// }
+///
+/// # Ok(())
+/// # }
+/// # fn do_stuff() {
+/// #    // doing stuff here.
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct DotLock {
+    /// Path of the lock file.
+    path: PathBuf,
+    /// Whether this handle owns the lock.
+    locked: bool,
+}
+
+impl DotLock {
+    /// Creates a handle to the lock file at the given path. No file is
+    /// created until the lock is acquired.
+    ///
+    /// # Panics
+    /// Panics if the path contains a nul-byte in a place other than the end.
+    pub fn new<P>(path: &P) -> Result<Self, Error>
+    where
+        P: ToOsStr + ?Sized,
+    {
+        Ok(Self { path: path.to_os_str()?.to_path_buf(), locked: false })
+    }
+
+    /// Creates a handle to the conventional lock file of the file at the given
+    /// path, i.e. with `.lock` appended to its name, like `dotlockfile -m`
+    /// does for mailboxes.
+    ///
+    /// # Panics
+    /// Panics if the path contains a nul-byte in a place other than the end.
+    pub fn for_file<P>(path: &P) -> Result<Self, Error>
+    where
+        P: ToOsStr + ?Sized,
+    {
+        let mut path = path.to_os_str()?.to_path_buf().into_os_string();
+        path.push(".lock");
+        Ok(Self { path: PathBuf::from(path), locked: false })
+    }
+
+    /// Returns the path of the lock file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns whether this handle owns the lock.
+    pub fn owns_lock(&self) -> bool {
+        self.locked
+    }
+
+    /// Returns the path of the temporary file linked to the lock file, named
+    /// like those of `liblockfile`.
+    fn temp_path(&self) -> Result<PathBuf, Error> {
+        let secs = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        let name = format!(
+            ".lk{:05}{:x}{}",
+            std::process::id(),
+            secs & 15,
+            sys::hostname()?
+        );
+        let dir = self.path.parent().unwrap_or_else(|| Path::new(""));
+        Ok(dir.join(name))
+    }
+
+    /// Creates the lock file, after removing it if stale. Does NOT block if
+    /// someone else holds the lock. Returns whether it was acquired.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns the lock.
+    pub fn try_lock(&mut self) -> Result<bool, Error> {
+        if self.locked {
+            panic!("Cannot lock if already owning a lock");
+        }
+        let temp = self.temp_path()?;
+        fs::write(&temp, format!("{}\n", std::process::id()))?;
+        let result = self.link(&temp);
+        let _ = fs::remove_file(&temp);
+        self.locked = result?;
+        Ok(self.locked)
+    }
+
+    /// Links the given temporary file to the lock file, removing it first if
+    /// stale. Returns whether the lock was acquired.
+    fn link(&self, temp: &Path) -> Result<bool, Error> {
+        for _ in 0 .. 2 {
+            // The outcome of link is unreliable over NFS; the link count is.
+            let _ = fs::hard_link(temp, &self.path);
+            if fs::metadata(temp)?.nlink() == 2 {
+                return Ok(true);
+            }
+            if !self.is_stale()? {
+                return Ok(false);
+            }
+            match fs::remove_file(&self.path) {
+                Err(error) if error.kind() != ErrorKind::NotFound => {
+                    return Err(error)
+                },
+                _ => (),
+            }
+        }
+        Ok(false)
+    }
+
+    /// Whether the lock file holds the PID of a process no longer running, or
+    /// was not modified for five minutes.
+    fn is_stale(&self) -> Result<bool, Error> {
+        let Some(contents) = read_if_exists(&self.path)? else {
+            return Ok(true);
+        };
+        let pid = String::from_utf8_lossy(&contents).trim().parse::<u32>();
+        if let Ok(pid) = pid {
+            if pid > 0 && !sys::process_alive(pid)? {
+                return Ok(true);
+            }
+        }
+        let modified = fs::metadata(&self.path)?.modified()?;
+        let age = SystemTime::now().duration_since(modified);
+        Ok(age.is_ok_and(|age| age > STALE_AFTER))
+    }
+
+    /// Acquires the lock, retrying up to the given number of times while
+    /// someone else holds it, like `dotlockfile -r`. Returns whether it was
+    /// acquired.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns the lock.
+    pub fn lock_with_retries(&mut self, retries: u32) -> Result<bool, Error> {
+        let mut sleep = Duration::ZERO;
+        for attempt in 0 ..= retries {
+            if attempt > 0 {
+                sleep = (sleep + SLEEP_STEP).min(MAX_SLEEP);
+                thread::sleep(sleep);
+            }
+            if self.try_lock()? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Acquires the lock, retrying until it is released or becomes stale.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns the lock.
+    pub fn lock(&mut self) -> Result<(), Error> {
+        while !self.lock_with_retries(u32::MAX)? {}
+        Ok(())
+    }
+
+    /// Refreshes the modification time of the lock file, so it is not
+    /// considered stale, like `lockfile_touch` does.
+    ///
+    /// # Panics
+    /// Panics if this handle does not own the lock.
+    pub fn refresh(&self) -> Result<(), Error> {
+        if !self.locked {
+            panic!("Attempted to refresh an unowned lock");
+        }
+        sys::touch(&self.path.to_os_str()?)
+    }
+
+    /// Releases the lock by removing the lock file.
+    ///
+    /// # Panics
+    /// Panics if this handle does not own the lock.
+    pub fn unlock(&mut self) -> Result<(), Error> {
+        if !self.locked {
+            panic!("Attempted to unlock already unlocked lockfile");
+        }
+        self.locked = false;
+        match fs::remove_file(&self.path) {
+            Err(error) if error.kind() != ErrorKind::NotFound => Err(error),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for DotLock {
+    fn drop(&mut self) {
+        if self.locked {
+            let _ = self.unlock();
+        }
+    }
+}
@@ -0,0 +1,77 @@
+//! This module implements locking files already opened through the standard
+//! library, without opening a second handle.
+
+use crate::{raw, Error};
+use std::fs::File;
+
+/// Locking of whole files opened through [`std::fs::File`], like by the
+/// functions of [`crate::raw`]: with `flock(2)` on Unix, whatever the access
+/// mode of the file, and `LockFileEx` on Windows. Locks are held by the handle:
+/// other handles to the same file are excluded, even in the same process, and
+/// closing the file releases its lock. On Unix, [`crate::LockFile`] handles are
+/// only excluded if they use `LockBackend::Flock`. Unlike them, nothing is ever
+/// written or truncated, so the file may be the protected data itself.
+///
+/// Since Rust 1.89, [`File`] has inherent locking methods named like some of
+/// these, which method calls resolve to instead; call these through the trait
+/// (e.g. `FileExt::lock_shared(&file)`) to get their signatures.
+///
+/// # Example
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// use fslock::FileExt;
+/// use std::{fs::OpenOptions, io::Write};
+///
+/// let mut file = OpenOptions::new()
+///     .create(true)
+///     .append(true)
+///     .open("testfiles/file_ext.log")?;
+/// file.lock_exclusive()?;
+/// writeln!(file, "one entry")?;
+/// FileExt::unlock(&file)?;
+///
+/// # Ok(())
+/// # }
+/// ```
+pub trait FileExt {
+    /// Locks this file exclusively. Blocks while someone else owns a lock on
+    /// it.
+    fn lock_exclusive(&self) -> Result<(), Error>;
+
+    /// Locks this file exclusively, unless someone else owns a lock on it.
+    /// Does NOT block. Returns whether the file was locked.
+    fn try_lock_exclusive(&self) -> Result<bool, Error>;
+
+    /// Locks this file shared. Blocks while someone else owns an exclusive
+    /// lock on it.
+    fn lock_shared(&self) -> Result<(), Error>;
+
+    /// Locks this file shared, unless someone else owns an exclusive lock on
+    /// it. Does NOT block. Returns whether the file was locked.
+    fn try_lock_shared(&self) -> Result<bool, Error>;
+
+    /// Unlocks this file. Unlocking a file which is not locked succeeds.
+    fn unlock(&self) -> Result<(), Error>;
+}
+
+impl FileExt for File {
+    fn lock_exclusive(&self) -> Result<(), Error> {
+        raw::lock_exclusive(self)
+    }
+
+    fn try_lock_exclusive(&self) -> Result<bool, Error> {
+        raw::try_lock_exclusive(self)
+    }
+
+    fn lock_shared(&self) -> Result<(), Error> {
+        raw::lock_shared(self)
+    }
+
+    fn try_lock_shared(&self) -> Result<bool, Error> {
+        raw::try_lock_shared(self)
+    }
+
+    fn unlock(&self) -> Result<(), Error> {
+        raw::unlock(self)
+    }
+}
@@ -31,7 +31,7 @@ impl Writer {
 
 /// Fmt <-> IO adapter.
 ///
-/// Buffer is flushed on drop.
+/// Buffer is flushed on [`Adapter::finish`], so flushing errors are reported.
 #[derive(Debug)]
 struct Adapter {
     /// File being written to.
@@ -58,9 +58,12 @@ impl Adapter {
         Ok(())
     }
 
-    /// Finishes the adapter, returning the I/O Result
+    /// Finishes the adapter, flushing the buffer and returning the first I/O
+    /// error that happened, if any. The file is not flushed to the disk, which
+    /// writers do once done, if configured to.
     fn finish(mut self) -> Result<(), sys::Error> {
-        mem::replace(&mut self.result, Ok(()))
+        mem::replace(&mut self.result, Ok(()))?;
+        self.flush()
     }
 }
 
@@ -68,7 +71,7 @@ impl Write for Adapter {
     fn write_str(&mut self, data: &str) -> fmt::Result {
         let mut bytes = data.as_bytes();
 
-        while bytes.len() > 0 && self.result.is_ok() {
+        while !bytes.is_empty() && self.result.is_ok() {
             let start = self.cursor;
             let size = (BUF_SIZE - self.cursor).min(bytes.len());
             let end = start + size;
@@ -77,7 +80,7 @@ impl Write for Adapter {
             self.cursor = end;
             bytes = &bytes[size ..];
 
-            if bytes.len() > 0 {
+            if !bytes.is_empty() {
                 self.result = self.flush();
             }
         }
@@ -88,10 +91,3 @@ impl Write for Adapter {
         }
     }
 }
-
-impl Drop for Adapter {
-    fn drop(&mut self) {
-        let _ = self.flush();
-        let _ = sys::fsync(self.desc);
-    }
-}
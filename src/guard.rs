@@ -0,0 +1,223 @@
+//! This module implements guards releasing locks when they go out of scope.
+
+use crate::{Error, LockFile, ToOsStr};
+use core::{mem::ManuallyDrop, ops::Deref};
+
+/// A lock on a [`LockFile`], released (and the file truncated, if configured
+/// to) when the guard is dropped. Created by [`LockFile::lock_guard`] and
+/// [`LockFile::try_lock_guard`].
+#[derive(Debug)]
+pub struct LockFileGuard<'file> {
+    file: &'file mut LockFile,
+}
+
+/// A lock owning its [`LockFile`], released (and the file truncated, if
+/// configured to) and closed when the guard is dropped. Unlike
+/// [`LockFileGuard`], it has no lifetime, so it can be stored in structs or
+/// moved across threads. Created by [`LockFile::lock_owned`].
+#[derive(Debug)]
+pub struct OwnedLockGuard {
+    file: LockFile,
+}
+
+impl LockFile {
+    /// Locks this file like [`LockFile::lock`], returning a guard which
+    /// unlocks it when dropped, including on early returns.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns the file.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/guard.lock")?;
+    /// {
+    ///     let _guard = file.lock_guard()?;
+    ///     do_stuff()?;
+    /// }
+    /// assert!(!file.owns_lock());
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() -> Result<(), fslock::Error> {
+    /// #    // doing stuff here.
+    /// #    Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Panicking Example
+    ///
+    /// ```should_panic
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/guard_panic.lock")?;
+    /// file.lock()?;
+    /// file.lock_guard()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn lock_guard(&mut self) -> Result<LockFileGuard<'_>, Error> {
+        self.lock()?;
+        Ok(LockFileGuard { file: self })
+    }
+
+    /// Locks this file like [`LockFile::try_lock`], returning a guard which
+    /// unlocks it when dropped, or `None` if someone else owns the lock.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns the file.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/try_guard.lock")?;
+    /// if let Some(_guard) = file.try_lock_guard()? {
+    ///     do_stuff();
+    /// }
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    pub fn try_lock_guard(
+        &mut self,
+    ) -> Result<Option<LockFileGuard<'_>>, Error> {
+        if self.try_lock()? {
+            Ok(Some(LockFileGuard { file: self }))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl LockFile {
+    /// Locks this file like [`LockFile::lock`], moving it into a guard which
+    /// unlocks and closes it when dropped.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns the file.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::{LockFile, OwnedLockGuard};
+    ///
+    /// struct Daemon {
+    ///     _lock: OwnedLockGuard,
+    /// }
+    ///
+    /// let file = LockFile::open("testfiles/owned_guard.lock")?;
+    /// let daemon = Daemon { _lock: file.lock_owned()? };
+    /// do_stuff(&daemon);
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff<T>(_: &T) {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    ///
+    /// # Panicking Example
+    ///
+    /// ```should_panic
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/owned_guard_panic.lock")?;
+    /// file.lock()?;
+    /// file.lock_owned()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn lock_owned(mut self) -> Result<OwnedLockGuard, Error> {
+        self.lock()?;
+        Ok(OwnedLockGuard { file: self })
+    }
+
+    /// Opens the file at the given path, locks it like [`LockFile::lock`],
+    /// runs the given function, then unlocks (unless the function already did)
+    /// and closes it, returning the function's result.
+    ///
+    /// # Panics
+    /// Panics if the path contains a nul-byte in a place other than the end.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let answer = LockFile::with_lock("testfiles/with_lock.lock", |file| {
+    ///     assert!(file.owns_lock());
+    ///     42
+    /// })?;
+    /// assert_eq!(answer, 42);
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_lock<P, F, T>(path: &P, function: F) -> Result<T, Error>
+    where
+        P: ToOsStr + ?Sized,
+        F: FnOnce(&mut LockFile) -> T,
+    {
+        let mut file = Self::open(path)?;
+        file.lock()?;
+        let output = function(&mut file);
+        if file.owns_lock() {
+            file.unlock()?;
+        }
+        Ok(output)
+    }
+}
+
+impl OwnedLockGuard {
+    /// Releases the lock, giving the file back, and reporting errors that
+    /// dropping would ignore.
+    pub fn unlock(mut self) -> Result<LockFile, Error> {
+        self.file.unlock()?;
+        Ok(self.file)
+    }
+}
+
+impl Deref for OwnedLockGuard {
+    type Target = LockFile;
+
+    fn deref(&self) -> &LockFile {
+        &self.file
+    }
+}
+
+impl LockFileGuard<'_> {
+    /// Releases the lock, reporting errors that dropping would ignore.
+    pub fn unlock(self) -> Result<(), Error> {
+        let mut this = ManuallyDrop::new(self);
+        this.file.unlock()
+    }
+}
+
+impl Deref for LockFileGuard<'_> {
+    type Target = LockFile;
+
+    fn deref(&self) -> &LockFile {
+        self.file
+    }
+}
+
+impl Drop for LockFileGuard<'_> {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
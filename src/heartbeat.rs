@@ -0,0 +1,172 @@
+//! This module implements refreshing the modification time of lock files while
+//! they are locked, so observers which cannot check PIDs (e.g. on other hosts
+//! sharing a network file system) can tell dead owners by the age of the file.
+
+use crate::{sys, Error, LockFile, OsString, ToOsStr};
+use std::{
+    fs,
+    io::ErrorKind,
+    sync::{Arc, Condvar, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime},
+};
+
+/// State shared with the heartbeat thread.
+#[derive(Debug)]
+struct State {
+    /// Whether the handle currently owns an exclusive lock.
+    armed: bool,
+    /// Whether the thread should exit.
+    stopped: bool,
+}
+
+/// A background thread touching a lock file while armed.
+#[derive(Debug)]
+pub(crate) struct Heartbeat {
+    /// State and a condition variable notified on its changes.
+    shared: Arc<(Mutex<State>, Condvar)>,
+    /// The heartbeat thread.
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Heartbeat {
+    /// Spawns a thread touching the file at the given path every interval
+    /// while armed, and as soon as it is armed.
+    fn spawn(
+        path: OsString,
+        interval: Duration,
+        armed: bool,
+    ) -> Result<Self, Error> {
+        let shared = Arc::new((
+            Mutex::new(State { armed, stopped: false }),
+            Condvar::new(),
+        ));
+        let thread_shared = shared.clone();
+        let thread = thread::Builder::new()
+            .name("fslock-heartbeat".to_owned())
+            .spawn(move || beat(&path, interval, &thread_shared))?;
+        Ok(Self { shared, thread: Some(thread) })
+    }
+
+    /// Records whether the handle owns an exclusive lock.
+    pub(crate) fn set_armed(&self, armed: bool) {
+        let (state, changed) = &*self.shared;
+        let mut state = state.lock().unwrap_or_else(|error| error.into_inner());
+        if state.armed != armed {
+            state.armed = armed;
+            changed.notify_one();
+        }
+    }
+}
+
+impl Drop for Heartbeat {
+    fn drop(&mut self) {
+        let (state, changed) = &*self.shared;
+        state.lock().unwrap_or_else(|error| error.into_inner()).stopped = true;
+        changed.notify_one();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Body of the heartbeat thread. Failures to touch the file are ignored, as a
+/// missed beat only makes the lock look older.
+fn beat(path: &OsString, interval: Duration, shared: &(Mutex<State>, Condvar)) {
+    let (state, changed) = shared;
+    let mut state = state.lock().unwrap_or_else(|error| error.into_inner());
+    while !state.stopped {
+        if state.armed {
+            let _ = sys::touch(path);
+        }
+        state = changed
+            .wait_timeout(state, interval)
+            .unwrap_or_else(|error| error.into_inner())
+            .0;
+    }
+}
+
+impl LockFile {
+    /// Refreshes the modification time of this file every `interval` (and as
+    /// soon as the lock is acquired) on a background thread, while this handle
+    /// owns an exclusive lock. Observers may then consider the lock abandoned
+    /// once [`LockFile::heartbeat_age_at`] exceeds a few intervals, even where
+    /// the PID of the owner is meaningless, such as on network file systems.
+    /// Passing `None` stops the heartbeat, which otherwise lasts until the
+    /// handle is dropped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    /// use std::time::Duration;
+    ///
+    /// let mut file = LockFile::open("testfiles/set_heartbeat.lock")?;
+    /// file.set_heartbeat(Some(Duration::from_secs(5)))?;
+    /// file.lock_with_info()?;
+    /// do_stuff();
+    /// file.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    pub fn set_heartbeat(
+        &mut self,
+        interval: Option<Duration>,
+    ) -> Result<(), Error> {
+        self.heartbeat = None;
+        if let Some(interval) = interval {
+            let armed = self.locked && !self.shared;
+            self.heartbeat =
+                Some(Heartbeat::spawn(self.path.clone(), interval, armed)?);
+        }
+        Ok(())
+    }
+
+    /// Returns how long ago the file at the given path was last modified, such
+    /// as by the heartbeat of its owner (see [`LockFile::set_heartbeat`]). The
+    /// file is neither locked nor created; `None` is returned if it does not
+    /// exist. Clocks of other hosts sharing the file system are assumed to be
+    /// roughly synchronized.
+    ///
+    /// # Panics
+    /// Panics if the path contains a nul-byte in a place other than the end.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    /// use std::time::Duration;
+    ///
+    /// let path = "testfiles/heartbeat_age_at.lock";
+    /// match LockFile::heartbeat_age_at(path)? {
+    ///     Some(age) if age > Duration::from_secs(15) => {
+    ///         eprintln!("owner stopped beating");
+    ///     },
+    ///     _ => (),
+    /// }
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn heartbeat_age_at<P>(path: &P) -> Result<Option<Duration>, Error>
+    where
+        P: ToOsStr + ?Sized,
+    {
+        let path = path.to_os_str()?.to_path_buf();
+        let modified = match fs::metadata(path) {
+            Ok(metadata) => metadata.modified()?,
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                return Ok(None)
+            },
+            Err(error) => return Err(error),
+        };
+        let age = SystemTime::now().duration_since(modified);
+        Ok(Some(age.unwrap_or(Duration::ZERO)))
+    }
+}
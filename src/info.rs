@@ -0,0 +1,342 @@
+//! This module implements locking with a structured record of the owner, and
+//! parsing of such records.
+
+use crate::{fmt, owner::peek_contents, sys, Error, LockFile, ToOsStr};
+use std::{
+    env,
+    io::{self, Write},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// A record of the owner of a lock, written into the file by
+/// [`LockFile::lock_with_pid`] or [`LockFile::lock_with_info`]. Fields other
+/// than the PID are only present if the writer recorded them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct LockInfo {
+    /// PID of the owner.
+    pub pid: u32,
+    /// Host name of the machine in which the owner ran.
+    pub hostname: Option<String>,
+    /// When the lock was acquired, with precision of seconds.
+    pub started_at: Option<SystemTime>,
+    /// File name of the owner's executable.
+    pub executable: Option<String>,
+    /// Start time of the owner, in platform-specific units (see
+    /// [`crate::LockFileOptions::record_start_time`]).
+    pub process_start: Option<u64>,
+    /// Identifier of the boot of the machine in which the owner ran.
+    pub boot_id: Option<String>,
+}
+
+impl LockInfo {
+    /// Parses the PID at the first line of the given contents, and the other
+    /// fields at later `key=value` lines. Unknown keys are ignored.
+    pub(crate) fn parse(contents: &[u8]) -> Option<Self> {
+        let contents = String::from_utf8_lossy(contents);
+        let mut lines = contents.lines();
+        let pid = lines.next()?.trim().parse().ok()?;
+        let mut info = Self {
+            pid,
+            hostname: None,
+            started_at: None,
+            executable: None,
+            process_start: None,
+            boot_id: None,
+        };
+        for line in lines {
+            let Some((key, value)) = line.trim().split_once('=') else {
+                continue;
+            };
+            match key {
+                "host" => info.hostname = Some(value.to_owned()),
+                "time" => info.started_at = parse_iso_timestamp(value),
+                "exe" => info.executable = Some(value.to_owned()),
+                "start" => info.process_start = value.parse().ok(),
+                "boot" => info.boot_id = Some(value.to_owned()),
+                _ => (),
+            }
+        }
+        Some(info)
+    }
+
+    /// Reads the record written into the file at the given path, without
+    /// locking it nor creating it. Returns `None` if the file does not exist
+    /// or does not start with a PID, such as when it is not locked.
+    ///
+    /// # Panics
+    /// Panics if the path contains a nul-byte in a place other than the end.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::{LockFile, LockInfo};
+    ///
+    /// let path = "testfiles/read_from.lock";
+    /// let mut file = LockFile::open(path)?;
+    /// file.lock_with_info()?;
+    /// let info = LockInfo::read_from(path)?.unwrap();
+    /// assert_eq!(info.pid, std::process::id());
+    /// assert!(info.hostname.is_some());
+    /// file.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_from<P>(path: &P) -> Result<Option<Self>, Error>
+    where
+        P: ToOsStr + ?Sized,
+    {
+        Ok(peek_contents(path)?.and_then(|file| Self::parse(&file)))
+    }
+
+    /// Whether the owner no longer runs, either because the machine rebooted,
+    /// because no process has its PID or because the PID was recycled by a
+    /// process started later. Owners on other hosts are never considered
+    /// dead, since their processes cannot be looked up.
+    pub(crate) fn is_dead(&self) -> Result<bool, Error> {
+        if let Some(hostname) = &self.hostname {
+            if *hostname != sys::hostname()? {
+                return Ok(false);
+            }
+        }
+        if let (Some(recorded), Some(current)) = (&self.boot_id, sys::boot_id())
+        {
+            if !sys::same_boot(recorded, &current) {
+                return Ok(true);
+            }
+        }
+        if !sys::process_alive(self.pid)? {
+            return Ok(true);
+        }
+        match (self.process_start, sys::process_start_time(self.pid)) {
+            (Some(recorded), Some(current)) => Ok(recorded != current),
+            _ => Ok(false),
+        }
+    }
+}
+
+/// Counts the days from the Unix epoch to the given date of the proleptic
+/// Gregorian calendar, returning `None` for dates before the epoch.
+fn days_from_civil(year: u64, month: u64, day: u64) -> Option<u64> {
+    let year = year.checked_sub(u64::from(month <= 2))?;
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let shifted_month = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * shifted_month + 2) / 5 + day - 1;
+    let day_of_era =
+        year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    (era * 146097 + day_of_era).checked_sub(719468)
+}
+
+/// Parses a timestamp formatted by [`iso_timestamp`].
+fn parse_iso_timestamp(timestamp: &str) -> Option<SystemTime> {
+    let (date, time) = timestamp.strip_suffix('Z')?.split_once('T')?;
+    let mut date = date.splitn(3, '-').map(str::parse::<u64>);
+    let mut time = time.splitn(3, ':').map(str::parse::<u64>);
+    let (year, month, day) =
+        (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    let (hour, minute, second) =
+        (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+    if !(1 ..= 12).contains(&month)
+        || !(1 ..= 31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+    let days = days_from_civil(year, month, day)?;
+    let secs = days * 86400 + hour * 3600 + minute * 60 + second;
+    UNIX_EPOCH.checked_add(Duration::from_secs(secs))
+}
+
+/// Formats the given time as an ISO 8601 timestamp in UTC, with precision of
+/// seconds, e.g. `2021-03-14T15:09:26Z`.
+pub(crate) fn iso_timestamp(time: SystemTime) -> String {
+    let secs =
+        time.duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+    let (days, secs) = (secs / 86400, secs % 86400);
+    // Converts days since the epoch to a civil date, counting years from March
+    // so that leap days fall at the end of each year.
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days % 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524
+        - day_of_era / 146096)
+        / 365;
+    let day_of_year =
+        day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month =
+        if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Returns the file name of the current executable, if known.
+fn executable_name() -> Option<String> {
+    let path = env::current_exe().ok()?;
+    Some(path.file_name()?.to_string_lossy().into_owned())
+}
+
+impl LockFile {
+    /// Writes this process's PID followed by `host=`, `time=` and `exe=` lines
+    /// with the host name, the current time and the executable name, and by
+    /// the start time if configured to.
+    fn write_info(&self) -> Result<(), Error> {
+        writeln!(fmt::Writer(self.desc), "{}", sys::pid())?;
+        writeln!(fmt::Writer(self.desc), "host={}", sys::hostname()?)?;
+        let time = iso_timestamp(SystemTime::now());
+        writeln!(fmt::Writer(self.desc), "time={}", time)?;
+        if let Some(exe) = executable_name() {
+            writeln!(fmt::Writer(self.desc), "exe={}", exe)?;
+        }
+        self.write_start()?;
+        if self.sync {
+            sys::sync_data(self.desc)?;
+        }
+        Ok(())
+    }
+
+    /// Locks this file and writes a record of this process into the file,
+    /// which will be erased on unlock. Like [`LockFile::lock_with_pid`], the
+    /// first line holds the PID, so PID readers keep working. The following
+    /// lines hold the host name (`host=`), the time of locking in ISO 8601
+    /// format (`time=`) and the executable name (`exe=`), so owners on shared
+    /// file systems can be told apart from local processes with the same PID.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns the file.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    /// use std::fs::read_to_string;
+    ///
+    /// let mut file = LockFile::open("testfiles/withinfo.lock")?;
+    /// file.lock_with_info()?;
+    /// let content = read_to_string("testfiles/withinfo.lock")?;
+    /// assert!(content.lines().any(|line| line.starts_with("host=")));
+    /// file.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Panicking Example
+    ///
+    /// ```should_panic
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/withinfo.lock")?;
+    /// file.lock_with_info()?;
+    /// file.lock_with_info()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn lock_with_info(&mut self) -> Result<(), Error> {
+        debug_assert!(self.desc != sys::uninitialized_fd());
+        self.lock()?;
+
+        let result = self.write_info();
+        if result.is_err() {
+            let _ = self.unlock();
+        }
+        result
+    }
+
+    /// Locks this file and lets the given function write an application
+    /// defined payload into it, such as the path of a socket through which the
+    /// owner may be reached. The payload is written before other handles can
+    /// observe the lock, and is erased on unlock. If the function fails, or
+    /// flushing to the disk fails (see [`crate::LockFileOptions::sync`]), this
+    /// file is unlocked and the error is returned, like
+    /// [`LockFile::lock_with_pid`] does.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns the file.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    /// use std::fs::read_to_string;
+    ///
+    /// let mut file = LockFile::open("testfiles/lock_with.lock")?;
+    /// file.lock_with(|out| writeln!(out, "socket=/run/daemon.sock"))?;
+    /// let content = read_to_string("testfiles/lock_with.lock")?;
+    /// assert_eq!(content, "socket=/run/daemon.sock\n");
+    /// file.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Panicking Example
+    ///
+    /// ```should_panic
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/lock_with.lock")?;
+    /// file.lock_with(|_| Ok(()))?;
+    /// file.lock_with(|_| Ok(()))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn lock_with<F>(&mut self, write: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut dyn Write) -> io::Result<()>,
+    {
+        debug_assert!(self.desc != sys::uninitialized_fd());
+        self.lock()?;
+
+        let mut result = write(self);
+        if result.is_ok() && self.sync {
+            result = sys::sync_data(self.desc);
+        }
+        if result.is_err() {
+            let _ = self.unlock();
+        }
+        result
+    }
+
+    /// Reads the record of the owner written into this file, such as by
+    /// [`LockFile::lock_with_info`], without moving the cursor. Returns `None`
+    /// if the file does not start with a PID, such as when it is not locked.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/owner_info.lock")?;
+    /// file.lock_with_info()?;
+    /// let info = file.owner_info()?.unwrap();
+    /// assert_eq!(info.pid, std::process::id());
+    /// file.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn owner_info(&mut self) -> Result<Option<LockInfo>, Error> {
+        Ok(LockInfo::parse(&self.read_contents()?))
+    }
+}
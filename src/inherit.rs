@@ -0,0 +1,213 @@
+//! This module implements passing lock files, locked or not, to child
+//! processes through inherited descriptors, whose numbers and state are
+//! passed through environment variables, and detaching handles of forked
+//! children from the descriptors they share with their parent.
+
+use crate::{sys, Error, IntoOsString, LockBackend, LockFile};
+use std::{
+    env,
+    io::ErrorKind,
+    os::unix::process::CommandExt,
+    process::{Child, Command},
+};
+
+/// Environment variable holding the descriptor, backend and lock state.
+const FD_VAR: &str = "FSLOCK_FD";
+
+/// Environment variable holding the path of the file.
+const PATH_VAR: &str = "FSLOCK_PATH";
+
+/// Names the given backend in [`FD_VAR`].
+fn backend_name(backend: LockBackend) -> Option<&'static str> {
+    match backend {
+        LockBackend::Flock => Some("flock"),
+        #[cfg(target_os = "linux")]
+        LockBackend::Ofd => Some("ofd"),
+        // Locks held by the process are not inherited.
+        LockBackend::Fcntl | LockBackend::Lockf => None,
+    }
+}
+
+/// Parses a backend named in [`FD_VAR`].
+fn parse_backend(name: &str) -> Option<LockBackend> {
+    match name {
+        "flock" => Some(LockBackend::Flock),
+        #[cfg(target_os = "linux")]
+        "ofd" => Some(LockBackend::Ofd),
+        _ => None,
+    }
+}
+
+/// Error of malformed variables.
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+impl LockFile {
+    /// Spawns the given command with a duplicate of this handle's descriptor,
+    /// so the child shares the lock owned by this handle, if any, and may get
+    /// a handle to it through [`LockFile::inherit_from_env`]. The descriptor
+    /// is only inherited by this child, and its number, the backend and the
+    /// lock state are passed through the `FSLOCK_FD` environment variable,
+    /// and the path through `FSLOCK_PATH`.
+    ///
+    /// The lock is held by the open file, shared by both processes: it stays
+    /// held until both close their descriptors, or until either unlocks it.
+    /// Parents exiting before their children should thus not unlock, but
+    /// rather [`LockFile::disown`] their handle. Fails with
+    /// [`std::io::ErrorKind::Unsupported`] with `fcntl` and `lockf` locks,
+    /// which are held by the process and not inherited.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    /// use std::process::Command;
+    ///
+    /// let mut file = LockFile::open("testfiles/build.lock")?;
+    /// file.lock()?;
+    /// file.spawn_holding(&mut Command::new("make"))?.wait()?;
+    /// file.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn spawn_holding(&self, command: &mut Command) -> Result<Child, Error> {
+        let Some(backend) = backend_name(self.backend) else {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "process-wide locks are not inherited",
+            ));
+        };
+        let state = match (self.locked, self.shared) {
+            (false, _) => "unlocked",
+            (true, false) => "exclusive",
+            (true, true) => "shared",
+        };
+        let fd = sys::duplicate(self.desc)?;
+        command
+            .env(FD_VAR, format!("{}:{}:{}", fd, backend, state))
+            .env(PATH_VAR, self.path.to_path_buf());
+        // Safe because: only the async-signal-safe `fcntl` is called.
+        unsafe {
+            command.pre_exec(move || sys::set_inheritable(fd, true));
+        }
+        let child = command.spawn();
+        sys::close(fd);
+        child
+    }
+
+    /// Gets the handle passed to this process by
+    /// [`LockFile::spawn_holding`], owning the lock it owned, if any. Returns
+    /// `None` if no handle was passed. The descriptor is made close-on-exec
+    /// again, so it is not inherited any further.
+    ///
+    /// Unlocking the handle releases the lock for the parent too, since they
+    /// share it, and so does dropping it while locked, unless
+    /// [`LockFile::keep_locked_on_drop`] is set, or the handle is disowned.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// if let Some(file) = LockFile::inherit_from_env()? {
+    ///     assert!(file.owns_lock());
+    ///     do_stuff();
+    ///     file.disown();
+    /// }
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    pub fn inherit_from_env() -> Result<Option<Self>, Error> {
+        let Some(value) = env::var_os(FD_VAR) else {
+            return Ok(None);
+        };
+        let value = value
+            .into_string()
+            .map_err(|_| invalid("FSLOCK_FD is not valid UTF-8"))?;
+        let mut fields = value.split(':');
+        let (Some(fd), Some(backend), Some(state), None) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            return Err(invalid("FSLOCK_FD is malformed"));
+        };
+        let fd = fd.parse().map_err(|_| invalid("FSLOCK_FD is malformed"))?;
+        let backend = parse_backend(backend)
+            .ok_or_else(|| invalid("FSLOCK_FD names an unknown backend"))?;
+        let (locked, shared) = match state {
+            "unlocked" => (false, false),
+            "exclusive" => (true, false),
+            "shared" => (true, true),
+            _ => return Err(invalid("FSLOCK_FD names an unknown state")),
+        };
+        let path = env::var_os(PATH_VAR)
+            .ok_or_else(|| invalid("FSLOCK_PATH is missing"))?;
+        let path = IntoOsString::into_os_string(path)?;
+        sys::set_inheritable(fd, false)?;
+        let mut file = LockFile::new(path, fd);
+        file.backend = backend;
+        file.locked = locked;
+        file.shared = shared;
+        Ok(Some(file))
+    }
+
+    /// Detaches this handle from its parent's in a child process forked while
+    /// the handle existed, by replacing the descriptor shared with the parent
+    /// with one of its own, reopened like the original. Since the child never
+    /// owns the parent's lock, this handle then owns no lock, and dropping or
+    /// unlocking it no longer releases the parent's lock. The heartbeat (see
+    /// [`LockFile::set_heartbeat`]), whose thread did not survive the fork, is
+    /// stopped.
+    ///
+    /// If `relock` is set and the handle owned a lock, a lock of the same kind
+    /// is then acquired independently of the parent, blocking while it is not
+    /// possible, such as until the parent releases an exclusive lock.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/after_fork_child.lock")?;
+    /// file.lock_shared()?;
+    /// match unsafe { libc::fork() } {
+    ///     0 => {
+    ///         file.after_fork_child(true)?;
+    ///         do_worker_stuff();
+    ///         file.unlock()?;
+    ///         std::process::exit(0);
+    ///     },
+    ///     child => {
+    ///         unsafe { libc::waitpid(child, std::ptr::null_mut(), 0) };
+    ///         file.unlock()?;
+    ///     },
+    /// }
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_worker_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    pub fn after_fork_child(&mut self, relock: bool) -> Result<(), Error> {
+        let owned = self.locked.then_some(self.shared);
+        core::mem::forget(self.heartbeat.take());
+        self.unclaim();
+        self.set_locked(false);
+        let file = self.same_options().open(self.path())?;
+        self.replace_with(file);
+        match owned {
+            Some(false) if relock => self.lock(),
+            Some(true) if relock => self.lock_shared(),
+            _ => Ok(()),
+        }
+    }
+}
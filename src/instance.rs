@@ -0,0 +1,203 @@
+//! This module implements single-instance applications: the first instance to
+//! start becomes the primary one, and later instances may forward their
+//! arguments to it before exiting, such as editors opening files in an
+//! already running window.
+
+use crate::{Error, LockFile};
+use std::{
+    io::{ErrorKind, Read, Write},
+    thread,
+    time::Duration,
+};
+
+#[cfg(windows)]
+use std::net::{TcpListener as Listener, TcpStream as Stream};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener as Listener, UnixStream as Stream};
+
+/// Attempts of secondary instances to read the endpoint, which the primary
+/// instance writes right after locking.
+const ENDPOINT_ATTEMPTS: u32 = 100;
+
+/// Delay between attempts to read the endpoint.
+const ENDPOINT_DELAY: Duration = Duration::from_millis(10);
+
+/// The outcome of [`SingleInstance::acquire`].
+#[derive(Debug)]
+pub enum Instance {
+    /// No other instance is running; this one is the primary instance.
+    Primary(SingleInstance),
+    /// Another instance is running.
+    Secondary(RunningInstance),
+}
+
+/// The primary instance of an application, holding the lock of its name for
+/// as long as it lives, and listening for arguments forwarded by secondary
+/// instances: on a Unix domain socket next to the lock file on Unix, and on a
+/// loopback TCP port on Windows.
+///
+/// # Example
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// use fslock::{Instance, SingleInstance};
+///
+/// match SingleInstance::acquire("fslock-instance-doctest")? {
+///     Instance::Primary(instance) => {
+///         std::thread::spawn(move || {
+///             while let Ok(args) = instance.accept_args() {
+///                 open_files(&args);
+///             }
+///         });
+///         run_editor();
+///     },
+///     Instance::Secondary(running) => {
+///         let args: Vec<String> = std::env::args().skip(1).collect();
+///         running.forward_args(&args)?;
+///     },
+/// }
+///
+/// # Ok(())
+/// # }
+/// # fn open_files(args: &[String]) {}
+/// # fn run_editor() {}
+/// ```
+#[derive(Debug)]
+pub struct SingleInstance {
+    /// Listener of forwarded arguments.
+    listener: Listener,
+    /// Endpoint of the listener written into the lock file.
+    endpoint: String,
+    /// The locked file, held until this instance is dropped.
+    _file: LockFile,
+}
+
+impl SingleInstance {
+    /// Becomes the primary instance of the application with the given name,
+    /// unless another instance already is. The lock file is the one opened by
+    /// [`LockFile::user_scoped`], so instances of different users do not
+    /// interfere, and it holds the PID and endpoint of the primary instance.
+    ///
+    /// # Panics
+    /// Panics if the name contains a nul-byte in a place other than the end.
+    pub fn acquire(name: &str) -> Result<Instance, Error> {
+        let mut file = LockFile::user_scoped(name)?;
+        if !file.try_lock()? {
+            return RunningInstance::find(&mut file).map(Instance::Secondary);
+        }
+        let (listener, endpoint) = listen(&file)?;
+        let contents =
+            format!("{}\nendpoint={}\n", std::process::id(), endpoint);
+        file.write_contents(contents.as_bytes())?;
+        Ok(Instance::Primary(Self { listener, endpoint, _file: file }))
+    }
+
+    /// Returns the endpoint on which forwarded arguments are received.
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// Blocks until a secondary instance forwards its arguments, and returns
+    /// them.
+    pub fn accept_args(&self) -> Result<Vec<String>, Error> {
+        let (mut stream, _) = self.listener.accept()?;
+        let mut message = Vec::new();
+        stream.read_to_end(&mut message)?;
+        let message = String::from_utf8(message)
+            .map_err(|error| Error::new(ErrorKind::InvalidData, error))?;
+        Ok(message.split_terminator('\0').map(str::to_owned).collect())
+    }
+}
+
+impl Drop for SingleInstance {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        let _ = std::fs::remove_file(&self.endpoint);
+    }
+}
+
+/// Binds the listener of a primary instance locking the given file.
+#[cfg(unix)]
+fn listen(file: &LockFile) -> Result<(Listener, String), Error> {
+    let mut path = file.path().to_path_buf().into_os_string();
+    path.push(".sock");
+    let endpoint = path.into_string().map_err(|_| {
+        Error::new(ErrorKind::InvalidInput, "lock path is not valid UTF-8")
+    })?;
+    // A socket left behind by a dead primary instance; no live one may own
+    // it, since this process holds the lock.
+    let _ = std::fs::remove_file(&endpoint);
+    Ok((Listener::bind(&endpoint)?, endpoint))
+}
+
+/// Binds the listener of a primary instance locking the given file.
+#[cfg(windows)]
+fn listen(_file: &LockFile) -> Result<(Listener, String), Error> {
+    let listener = Listener::bind("127.0.0.1:0")?;
+    let endpoint = listener.local_addr()?.to_string();
+    Ok((listener, endpoint))
+}
+
+/// The primary instance of an application, as seen by a secondary instance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunningInstance {
+    /// PID of the primary instance, if it could be read.
+    pid: Option<u32>,
+    /// Endpoint of the primary instance, if it could be read.
+    endpoint: Option<String>,
+}
+
+impl RunningInstance {
+    /// Reads the PID and endpoint written by the primary instance into the
+    /// given file, waiting briefly for them to be written.
+    fn find(file: &mut LockFile) -> Result<Self, Error> {
+        let mut running = Self { pid: None, endpoint: None };
+        for _ in 0 .. ENDPOINT_ATTEMPTS {
+            let contents = file.read_contents()?;
+            let contents = String::from_utf8_lossy(&contents);
+            let mut lines = contents.lines();
+            running.pid = lines.next().and_then(|pid| pid.trim().parse().ok());
+            running.endpoint = lines
+                .find_map(|line| line.strip_prefix("endpoint="))
+                .map(str::to_owned);
+            if running.endpoint.is_some() {
+                break;
+            }
+            thread::sleep(ENDPOINT_DELAY);
+        }
+        Ok(running)
+    }
+
+    /// Returns the PID of the primary instance, if it could be read.
+    pub fn pid(&self) -> Option<u32> {
+        self.pid
+    }
+
+    /// Returns the endpoint of the primary instance, if it could be read.
+    pub fn endpoint(&self) -> Option<&str> {
+        self.endpoint.as_deref()
+    }
+
+    /// Sends the given arguments to the primary instance, which receives them
+    /// from [`SingleInstance::accept_args`]. Arguments must not contain
+    /// nul-bytes. Fails with [`std::io::ErrorKind::NotConnected`] if the
+    /// endpoint of the primary instance is unknown.
+    pub fn forward_args<S>(&self, args: &[S]) -> Result<(), Error>
+    where
+        S: AsRef<str>,
+    {
+        let Some(endpoint) = &self.endpoint else {
+            return Err(Error::new(
+                ErrorKind::NotConnected,
+                "the primary instance has no endpoint",
+            ));
+        };
+        let mut message = String::new();
+        for arg in args {
+            message.push_str(arg.as_ref());
+            message.push('\0');
+        }
+        let mut stream = Stream::connect(endpoint)?;
+        stream.write_all(message.as_bytes())?;
+        stream.flush()
+    }
+}
@@ -0,0 +1,74 @@
+//! This module implements blocking lock acquisition which gives up when
+//! interrupted by a signal.
+
+use crate::{sys, Error, LockFile};
+
+/// The result of [`LockFile::lock_interruptible`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InterruptibleResult {
+    /// The lock was acquired by the handle.
+    Acquired,
+    /// A signal arrived while waiting, and the lock was not acquired.
+    Interrupted,
+}
+
+impl InterruptibleResult {
+    /// Whether the lock was acquired.
+    pub fn is_acquired(&self) -> bool {
+        matches!(self, Self::Acquired)
+    }
+}
+
+impl LockFile {
+    /// Locks this file, blocking while it is not possible to lock (i.e.
+    /// someone else already owns a lock), unless a signal arrives while
+    /// waiting, in which case [`InterruptibleResult::Interrupted`] is returned
+    /// instead of retrying, whatever `LockFileOptions::retry_interrupted` is
+    /// set to. Command-line tools may then honor Ctrl-C promptly.
+    ///
+    /// Only signals whose handlers were installed without `SA_RESTART` (Unix)
+    /// interrupt the wait, since the kernel restarts it otherwise; signals
+    /// which are not handled terminate the process as usual. On Windows, the
+    /// wait is never interrupted.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns the file.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::{InterruptibleResult, LockFile};
+    ///
+    /// let mut file = LockFile::open("testfiles/interruptible.lock")?;
+    /// match file.lock_interruptible()? {
+    ///     InterruptibleResult::Acquired => {
+    ///         do_stuff();
+    ///         file.unlock()?;
+    ///     },
+    ///     InterruptibleResult::Interrupted => eprintln!("canceled"),
+    /// }
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    pub fn lock_interruptible(&mut self) -> Result<InterruptibleResult, Error> {
+        #[cfg(unix)]
+        let retry = core::mem::replace(&mut self.retry_interrupted, false);
+        let result = self.lock();
+        #[cfg(unix)]
+        {
+            self.retry_interrupted = retry;
+        }
+        match result {
+            Ok(()) => Ok(InterruptibleResult::Acquired),
+            Err(error) if sys::is_interrupted(&error) => {
+                Ok(InterruptibleResult::Interrupted)
+            },
+            Err(error) => Err(error),
+        }
+    }
+}
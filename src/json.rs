@@ -0,0 +1,69 @@
+//! This module implements storing typed metadata in lock files as JSON.
+
+use crate::{sys, Error, LockFile};
+use serde::{de::DeserializeOwned, Serialize};
+
+impl LockFile {
+    /// Locks this file and writes this process's PID into the file, followed
+    /// by a line with the given value serialized as JSON, all of which will be
+    /// erased on unlock. Since the first line holds the PID, PID readers such
+    /// as [`LockFile::peek_owner_pid`] keep working. If writing fails, this
+    /// file is unlocked, like [`LockFile::lock_with`] does.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns the file.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    /// use serde::{Deserialize, Serialize};
+    ///
+    /// #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    /// struct Daemon {
+    ///     socket: String,
+    /// }
+    ///
+    /// let daemon = Daemon { socket: "/run/daemon.sock".to_owned() };
+    /// let mut file = LockFile::open("testfiles/lock_with_json.lock")?;
+    /// file.lock_with_json(&daemon)?;
+    /// assert_eq!(file.owner_info_json::<Daemon>()?, Some(daemon));
+    /// file.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn lock_with_json<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.lock_with(|out| {
+            writeln!(out, "{}", sys::pid())?;
+            serde_json::to_writer(&mut *out, value)?;
+            writeln!(out)
+        })
+    }
+
+    /// Reads the value written into this file by [`LockFile::lock_with_json`],
+    /// without moving the cursor. Returns `None` if the file does not start
+    /// with a PID followed by a line of JSON, such as when it is not locked.
+    /// Fails with [`std::io::ErrorKind::InvalidData`] if the JSON does not
+    /// describe a `T`.
+    pub fn owner_info_json<T>(&mut self) -> Result<Option<T>, Error>
+    where
+        T: DeserializeOwned,
+    {
+        let contents = self.read_contents()?;
+        let mut lines = contents.split(|&byte| byte == b'\n');
+        let starts_with_pid = lines.next().is_some_and(|line| {
+            !line.is_empty() && line.iter().all(u8::is_ascii_digit)
+        });
+        match lines.next() {
+            Some(line) if starts_with_pid && !line.is_empty() => {
+                Ok(Some(serde_json::from_slice(line)?))
+            },
+            _ => Ok(None),
+        }
+    }
+}
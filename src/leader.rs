@@ -0,0 +1,170 @@
+//! This module implements leader election among processes, possibly on several
+//! hosts, campaigning for the same [`LeaseLock`].
+
+use crate::{Error, LeaseLock, ToOsStr};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+        Condvar,
+        Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// A change of leadership reported by [`Leader::events`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LeaderEvent {
+    /// This campaign acquired the lease and is now the leader.
+    Elected,
+    /// This campaign failed to renew the lease and is no longer the leader.
+    /// Whatever it was doing as a leader should stop, since another campaign
+    /// may be elected as soon as the lease expires.
+    Lost,
+}
+
+/// State shared with the campaign thread.
+#[derive(Debug)]
+struct Shared {
+    /// Whether the campaign should stop.
+    stopped: Mutex<bool>,
+    /// Notified when the campaign should stop.
+    changed: Condvar,
+    /// Whether the campaign currently holds the lease.
+    leader: AtomicBool,
+}
+
+/// A campaign for leadership, run on a background thread which keeps trying
+/// to acquire a [`LeaseLock`] and, once acquired, renews it every third of its
+/// time to live. Changes of leadership are sent through [`Leader::events`].
+/// Dropping the campaign stops it and releases the lease, if held.
+///
+/// # Example
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// use fslock::{Leader, LeaderEvent};
+/// use std::time::Duration;
+///
+/// let ttl = Duration::from_secs(10);
+/// let leader = Leader::campaign("testfiles/leader.lock", ttl)?;
+/// for event in leader.events() {
+///     match event {
+///         LeaderEvent::Elected => {
+///             do_leader_stuff();
+///             break;
+///         },
+///         LeaderEvent::Lost => stop_leader_stuff(),
+///     }
+/// }
+///
+/// # Ok(())
+/// # }
+/// # fn do_leader_stuff() {
+/// #    // doing stuff here.
+/// # }
+/// # fn stop_leader_stuff() {
+/// #    // stopping stuff here.
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Leader {
+    /// State shared with the campaign thread.
+    shared: Arc<Shared>,
+    /// Changes of leadership.
+    events: Receiver<LeaderEvent>,
+    /// The campaign thread.
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Leader {
+    /// Starts campaigning for the lease at the given path, acquired with the
+    /// given time to live. Campaigns on other hosts sharing the file system
+    /// take part in the same election, provided their clocks are roughly
+    /// synchronized.
+    ///
+    /// # Panics
+    /// Panics if the path contains a nul-byte in a place other than the end.
+    pub fn campaign<P>(path: &P, ttl: Duration) -> Result<Self, Error>
+    where
+        P: ToOsStr + ?Sized,
+    {
+        let lease = LeaseLock::new(path, ttl)?;
+        let shared = Arc::new(Shared {
+            stopped: Mutex::new(false),
+            changed: Condvar::new(),
+            leader: AtomicBool::new(false),
+        });
+        let (sender, events) = mpsc::channel();
+        let thread_shared = shared.clone();
+        let thread = thread::Builder::new()
+            .name("fslock-leader".to_owned())
+            .spawn(move || run(lease, &thread_shared, &sender))?;
+        Ok(Self { shared, events, thread: Some(thread) })
+    }
+
+    /// Returns whether this campaign currently holds the lease.
+    pub fn is_leader(&self) -> bool {
+        self.shared.leader.load(Ordering::Acquire)
+    }
+
+    /// Returns the receiver of changes of leadership, in the order they
+    /// happened.
+    pub fn events(&self) -> &Receiver<LeaderEvent> {
+        &self.events
+    }
+
+    /// Stops campaigning, releasing the lease if held. Same as dropping.
+    pub fn resign(self) {}
+}
+
+impl Drop for Leader {
+    fn drop(&mut self) {
+        *self
+            .shared
+            .stopped
+            .lock()
+            .unwrap_or_else(|error| error.into_inner()) = true;
+        self.shared.changed.notify_one();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Body of the campaign thread. The lease is released when it is dropped on
+/// return.
+fn run(mut lease: LeaseLock, shared: &Shared, sender: &Sender<LeaderEvent>) {
+    let interval = lease.ttl() / 3;
+    let mut stopped =
+        shared.stopped.lock().unwrap_or_else(|error| error.into_inner());
+    while !*stopped {
+        let event = if lease.owns_lock() {
+            match lease.renew() {
+                Ok(true) => None,
+                Ok(false) => Some(LeaderEvent::Lost),
+                // Exclusive access can no longer be guaranteed.
+                Err(_) => {
+                    let _ = lease.unlock();
+                    Some(LeaderEvent::Lost)
+                },
+            }
+        } else {
+            match lease.try_lock() {
+                Ok(true) => Some(LeaderEvent::Elected),
+                _ => None,
+            }
+        };
+        if let Some(event) = event {
+            shared.leader.store(lease.owns_lock(), Ordering::Release);
+            let _ = sender.send(event);
+        }
+        stopped = shared
+            .changed
+            .wait_timeout(stopped, interval)
+            .unwrap_or_else(|error| error.into_inner())
+            .0;
+    }
+    shared.leader.store(false, Ordering::Release);
+}
@@ -0,0 +1,366 @@
+//! This module implements leases: locks which expire unless renewed, and which
+//! do not rely on kernel locks nor on PIDs, so they also work on network file
+//! systems where neither can be trusted.
+//!
+//! A lease is a file holding its expiry and a random token identifying the
+//! holder. It is created by hard-linking a fully written temporary file into
+//! place, which atomically fails if the lease exists, even over NFS. An
+//! expired lease is taken over by renaming it away, which only one contender
+//! can do, and checking that the renamed file is the expired one.
+
+use crate::{retry::Backoff, sys, Error, RetryPolicy, ToOsStr};
+use std::{
+    collections::hash_map::RandomState,
+    fs,
+    hash::{BuildHasher, Hasher},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Contents of a lease file.
+#[derive(Debug, PartialEq, Eq)]
+struct Record {
+    /// When the lease expires, in milliseconds since the Unix epoch.
+    expires: u128,
+    /// Token of the holder.
+    token: String,
+}
+
+impl Record {
+    /// Parses the `expires=` and `token=` lines of a lease file.
+    fn parse(contents: &[u8]) -> Option<Self> {
+        let contents = String::from_utf8_lossy(contents);
+        let mut expires = None;
+        let mut token = None;
+        for line in contents.lines() {
+            if let Some(value) = line.strip_prefix("expires=") {
+                expires = value.parse().ok();
+            } else if let Some(value) = line.strip_prefix("token=") {
+                token = Some(value.to_owned());
+            }
+        }
+        Some(Self { expires: expires?, token: token? })
+    }
+
+    /// Whether the lease expired at the given time.
+    fn expired(&self, now: u128) -> bool {
+        self.expires <= now
+    }
+}
+
+/// Milliseconds since the Unix epoch.
+fn now_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis())
+}
+
+/// Generates a token unlikely to be chosen by any other holder.
+pub(crate) fn new_token() -> String {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(std::process::id());
+    hasher.write_u128(now_millis());
+    format!("{:x}{:016x}", std::process::id(), hasher.finish())
+}
+
+/// Appends a suffix to the file name of the given path.
+pub(crate) fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// Reads the file at the given path, returning `None` if it does not exist.
+pub(crate) fn read_if_exists(path: &Path) -> Result<Option<Vec<u8>>, Error> {
+    match fs::read(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// An exclusive lease on a file, held until it expires, unless renewed within
+/// its time to live. Kernel locks and PIDs are not used, so leases also work
+/// across hosts sharing a network file system, provided their clocks are
+/// roughly synchronized; the time to live should dwarf the clock skew.
+///
+/// Holders must [`LeaseLock::renew`] their lease before it expires, such as
+/// every third of the time to live: once expired, another process may take it
+/// over, and the previous holder no longer has exclusive access. A lease held
+/// by this handle is released when it is dropped.
+///
+/// # Example
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// use fslock::LeaseLock;
+/// use std::time::Duration;
+///
+/// let ttl = Duration::from_secs(30);
+/// let mut lease = LeaseLock::new("testfiles/lease.lock", ttl)?;
+/// lease.lock()?;
+/// for _ in 0 .. 3 {
+///     do_stuff();
+///     if !lease.renew()? {
+///         panic!("lease lost");
+///     }
+/// }
+/// lease.unlock()?;
+///
+/// # Ok(())
+/// # }
+/// # fn do_stuff() {
+/// #    // doing stuff here.
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct LeaseLock {
+    /// Path of the lease file.
+    path: PathBuf,
+    /// Time to live of acquired and renewed leases.
+    ttl: Duration,
+    /// Token identifying this handle as a holder.
+    token: String,
+    /// Expiry of the lease held by this handle, if any.
+    expires: Option<u128>,
+}
+
+impl LeaseLock {
+    /// Creates a handle to the lease at the given path, with the given time to
+    /// live. No file is created until the lease is acquired.
+    ///
+    /// # Panics
+    /// Panics if the path contains a nul-byte in a place other than the end.
+    pub fn new<P>(path: &P, ttl: Duration) -> Result<Self, Error>
+    where
+        P: ToOsStr + ?Sized,
+    {
+        Ok(Self {
+            path: path.to_os_str()?.to_path_buf(),
+            ttl,
+            token: new_token(),
+            expires: None,
+        })
+    }
+
+    /// Returns the time to live of leases acquired by this handle.
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Returns whether this handle holds the lease, as far as it knows: the
+    /// lease may have expired and been taken over since it was last renewed.
+    pub fn owns_lock(&self) -> bool {
+        self.expires.is_some()
+    }
+
+    /// Returns when the lease held by this handle expires.
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        let expires = self.expires?;
+        let millis = u64::try_from(expires).ok()?;
+        UNIX_EPOCH.checked_add(Duration::from_millis(millis))
+    }
+
+    /// Writes a fresh record of this handle into a temporary file, returning
+    /// its path and the new expiry.
+    fn write_record(&self) -> Result<(PathBuf, u128), Error> {
+        let expires = now_millis() + self.ttl.as_millis();
+        let contents = format!(
+            "{}\nhost={}\nexpires={}\ntoken={}\n",
+            std::process::id(),
+            sys::hostname()?,
+            expires,
+            self.token,
+        );
+        let temp = with_suffix(&self.path, &format!(".{}.tmp", self.token));
+        fs::write(&temp, contents)?;
+        Ok((temp, expires))
+    }
+
+    /// Acquires the lease if it does not exist, or if it expired. Does NOT
+    /// block if someone else holds it. Returns whether it was acquired.
+    ///
+    /// # Panics
+    /// Panics if this handle already holds the lease.
+    pub fn try_lock(&mut self) -> Result<bool, Error> {
+        if self.expires.is_some() {
+            panic!("Cannot lock if already owning a lock");
+        }
+        loop {
+            let (temp, expires) = self.write_record()?;
+            let linked = fs::hard_link(&temp, &self.path);
+            let _ = fs::remove_file(&temp);
+            match linked {
+                Ok(()) => {
+                    self.expires = Some(expires);
+                    return Ok(true);
+                },
+                Err(error) if error.kind() == ErrorKind::AlreadyExists => (),
+                Err(error) => return Err(error),
+            }
+            let Some(contents) = read_if_exists(&self.path)? else {
+                continue;
+            };
+            match Record::parse(&contents) {
+                Some(record) if record.expired(now_millis()) => {
+                    self.take_over(&contents)?;
+                },
+                Some(_) => return Ok(false),
+                // A lease which cannot be parsed is either being replaced or
+                // was corrupted; it expires one time to live after written.
+                None => {
+                    if !self.modified_long_ago()? {
+                        return Ok(false);
+                    }
+                    self.take_over(&contents)?;
+                },
+            }
+        }
+    }
+
+    /// Whether the lease file was last modified over a time to live ago.
+    fn modified_long_ago(&self) -> Result<bool, Error> {
+        let modified = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata.modified()?,
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                return Ok(true)
+            },
+            Err(error) => return Err(error),
+        };
+        let age = SystemTime::now().duration_since(modified);
+        Ok(age.is_ok_and(|age| age > self.ttl))
+    }
+
+    /// Removes the expired or corrupted lease with the given contents, unless
+    /// it was replaced meanwhile. A lease replaced meanwhile is moved back into
+    /// place, unless yet another was acquired meanwhile; if it cannot be moved
+    /// back, it is left aside and the error is returned.
+    fn take_over(&self, expected: &[u8]) -> Result<(), Error> {
+        let grave = with_suffix(&self.path, &format!(".{}.old", self.token));
+        match fs::rename(&self.path, &grave) {
+            Ok(()) => (),
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(error) => return Err(error),
+        }
+        let moved = read_if_exists(&grave)?;
+        if moved.as_deref() != Some(expected) {
+            match fs::hard_link(&grave, &self.path) {
+                Err(error) if error.kind() != ErrorKind::AlreadyExists => {
+                    return Err(error)
+                },
+                _ => (),
+            }
+        }
+        let _ = fs::remove_file(&grave);
+        Ok(())
+    }
+
+    /// Acquires the lease, retrying while someone else holds it, until it is
+    /// released or expires.
+    ///
+    /// # Panics
+    /// Panics if this handle already holds the lease.
+    pub fn lock(&mut self) -> Result<(), Error> {
+        let policy = RetryPolicy {
+            max_delay: Duration::from_secs(1),
+            ..RetryPolicy::default()
+        };
+        self.lock_with_policy(&policy)?;
+        Ok(())
+    }
+
+    /// Acquires the lease, retrying while someone else holds it as configured
+    /// by the given policy. Returns whether the lease was acquired before
+    /// attempts were exhausted.
+    ///
+    /// # Panics
+    /// Panics if this handle already holds the lease.
+    pub fn lock_with_policy(
+        &mut self,
+        policy: &RetryPolicy,
+    ) -> Result<bool, Error> {
+        let mut backoff = Backoff::new(policy);
+        loop {
+            if self.try_lock()? {
+                return Ok(true);
+            }
+            match backoff.next_delay() {
+                Some(delay) => thread::sleep(delay),
+                None => return Ok(false),
+            }
+        }
+    }
+
+    /// Extends the lease held by this handle by its time to live from now.
+    /// Returns `false` if the lease was lost, because it expired or was
+    /// removed, in which case this handle no longer holds it.
+    ///
+    /// Since others only take over expired leases, the lease is only replaced
+    /// while it has not expired yet, so it must be renewed early enough to
+    /// outweigh the clock skew between holders and the time renewing takes.
+    /// An expired lease is lost even if nobody took it over yet.
+    ///
+    /// # Panics
+    /// Panics if this handle does not hold the lease.
+    pub fn renew(&mut self) -> Result<bool, Error> {
+        if self.expires.is_none() {
+            panic!("Attempted to renew an unowned lease");
+        }
+        if !self.unexpired() || !self.still_held()? {
+            self.expires = None;
+            return Ok(false);
+        }
+        let (temp, expires) = self.write_record()?;
+        if let Err(error) = fs::rename(&temp, &self.path) {
+            let _ = fs::remove_file(&temp);
+            return Err(error);
+        }
+        self.expires = Some(expires);
+        Ok(true)
+    }
+
+    /// Whether the lease held by this handle has not expired yet, so nobody
+    /// else may take it over.
+    fn unexpired(&self) -> bool {
+        self.expires.is_some_and(|expires| now_millis() < expires)
+    }
+
+    /// Whether the lease file still holds the token of this handle.
+    fn still_held(&self) -> Result<bool, Error> {
+        let record = read_if_exists(&self.path)?
+            .and_then(|contents| Record::parse(&contents));
+        Ok(record.is_some_and(|record| record.token == self.token))
+    }
+
+    /// Releases the lease held by this handle, unless it was lost. Like for
+    /// [`LeaseLock::renew`], an expired lease is left for others to take over,
+    /// since they may be doing so.
+    ///
+    /// # Panics
+    /// Panics if this handle does not hold the lease.
+    pub fn unlock(&mut self) -> Result<(), Error> {
+        if self.expires.is_none() {
+            panic!("Attempted to unlock already unlocked lockfile");
+        }
+        let unexpired = self.unexpired();
+        self.expires = None;
+        if unexpired && self.still_held()? {
+            match fs::remove_file(&self.path) {
+                Err(error) if error.kind() != ErrorKind::NotFound => {
+                    return Err(error)
+                },
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for LeaseLock {
+    fn drop(&mut self) {
+        if self.expires.is_some() {
+            let _ = self.unlock();
+        }
+    }
+}
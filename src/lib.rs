@@ -35,20 +35,140 @@ mod test;
 mod unix;
 #[cfg(unix)]
 use crate::unix as sys;
+#[cfg(all(unix, feature = "std"))]
+pub use crate::unix::NamedSemaphore;
+#[cfg(all(target_os = "linux", feature = "std"))]
+pub use crate::unix::{RobustLockResult, RobustMutex};
 
 mod constants;
-pub use constants::lockfile_truncate;
+mod registry;
+mod atomic;
+pub use constants::{lockfile_truncate, TruncateGuard};
 mod string;
 mod fmt;
+pub mod signal;
+mod append;
+pub use append::{append_locked, append_locked_synced};
+mod rwlock;
+pub use rwlock::{RwLockFile, RwLockReadGuard, RwLockWriteGuard};
+mod range;
+pub use range::RangeLock;
+mod guard;
+pub use guard::{LockFileGuard, OwnedLockGuard};
+mod checked;
+pub use checked::LockError;
+mod interrupt;
+pub use interrupt::InterruptibleResult;
+mod trace;
+mod options;
+pub use options::LockFileOptions;
+mod contents;
+#[cfg(feature = "std")]
+mod heartbeat;
+#[cfg(feature = "std")]
+mod lease;
+#[cfg(feature = "std")]
+pub use lease::LeaseLock;
+#[cfg(feature = "std")]
+mod dir;
+#[cfg(feature = "std")]
+pub use dir::DirLock;
+#[cfg(all(unix, feature = "std"))]
+mod dotlock;
+#[cfg(all(unix, feature = "std"))]
+pub use dotlock::DotLock;
+#[cfg(all(unix, feature = "std"))]
+mod inherit;
+#[cfg(all(unix, feature = "std"))]
+mod link;
+#[cfg(all(unix, feature = "std"))]
+pub use link::LinkLock;
+#[cfg(feature = "std")]
+mod leader;
+#[cfg(feature = "std")]
+pub use leader::{Leader, LeaderEvent};
+#[cfg(feature = "std")]
+mod named;
+#[cfg(feature = "std")]
+pub use named::user_lock_dir;
+#[cfg(feature = "std")]
+mod instance;
+#[cfg(feature = "std")]
+pub use instance::{Instance, RunningInstance, SingleInstance};
+#[cfg(feature = "std")]
+mod once;
+#[cfg(feature = "std")]
+pub use once::run_once;
+#[cfg(feature = "std")]
+mod counter;
+#[cfg(feature = "std")]
+pub use counter::FileCounter;
+#[cfg(feature = "std")]
+mod set;
+#[cfg(feature = "std")]
+pub use set::LockSet;
+#[cfg(feature = "std")]
+pub mod raw;
+#[cfg(feature = "std")]
+mod ext;
+#[cfg(feature = "std")]
+pub use ext::FileExt;
+#[cfg(feature = "std")]
+mod tree;
+#[cfg(feature = "std")]
+pub use tree::{TreeChild, TreeLock};
+#[cfg(feature = "std")]
+mod info;
+#[cfg(feature = "std")]
+mod audit;
+#[cfg(feature = "std")]
+pub use info::LockInfo;
+#[cfg(feature = "serde")]
+mod json;
+#[cfg(feature = "std")]
+mod owner;
+#[cfg(feature = "std")]
+pub use owner::{LockOwner, TryLockResult};
+#[cfg(feature = "std")]
+mod steal;
+#[cfg(feature = "std")]
+pub use steal::{BrokenLock, StalePolicy, StealOutcome};
+pub mod typestate;
+#[cfg(feature = "std")]
+mod timeout;
+#[cfg(feature = "std")]
+mod cancel;
+#[cfg(feature = "std")]
+pub use cancel::CancelToken;
+#[cfg(feature = "std")]
+pub mod poison;
+#[cfg(feature = "std")]
+pub mod metrics;
+#[cfg(feature = "std")]
+mod retry;
+#[cfg(feature = "std")]
+pub use retry::RetryPolicy;
 
 #[cfg(windows)]
 mod windows;
 #[cfg(windows)]
 use crate::windows as sys;
+#[cfg(all(windows, feature = "std"))]
+pub use crate::windows::NamedMutex;
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+pub mod uring;
+
+#[cfg(any(feature = "tokio", feature = "unblock"))]
+mod async_file;
+#[cfg(feature = "tokio")]
+pub mod tokio;
+#[cfg(feature = "unblock")]
+pub mod unblock;
 
 pub use crate::{
     string::{EitherOsStr, IntoOsString, ToOsStr},
-    sys::{Error, FileDesc, OsStr, OsString},
+    sys::{Error, FileDesc, LockBackend, OsStr, OsString, OwnedDesc},
 };
 
 #[derive(Debug)]
@@ -76,22 +196,208 @@ pub use crate::{
 pub struct LockFile {
     pub truncate_on_close: bool,
     locked: bool,
+    shared: bool,
+    backend: LockBackend,
     desc: sys::FileDesc,
+    path: OsString,
+    created: bool,
+    sync: bool,
+    observer: bool,
+    keep_locked: bool,
+    #[cfg(unix)]
+    retry_interrupted: bool,
+    #[cfg(feature = "std")]
+    record_start: bool,
+    signal_slot: Option<usize>,
+    #[cfg(feature = "std")]
+    heartbeat: Option<heartbeat::Heartbeat>,
+    #[cfg(feature = "std")]
+    poison: bool,
+    #[cfg(feature = "std")]
+    metrics: metrics::Metrics,
+    #[cfg(feature = "std")]
+    audit: bool,
+    #[cfg(feature = "std")]
+    options: LockFileOptions,
+    #[cfg(all(unix, feature = "std"))]
+    claimed: Option<registry::FileId>,
+    #[cfg(all(unix, feature = "std"))]
+    atomic_pid: bool,
+    #[cfg(all(unix, feature = "std"))]
+    remove_on_unlock: bool,
 }
 
 // Private functions
 impl LockFile {
-    fn new() -> Self {
-        #[allow(unused_unsafe)]
-        // not safe to get state of default truncation setting on no_std
+    fn new(path: OsString, desc: sys::FileDesc) -> Self {
         Self {
-            desc: sys::uninitialized_fd(),
+            desc,
+            path,
+            created: false,
+            sync: false,
+            observer: false,
+            keep_locked: false,
+            #[cfg(unix)]
+            retry_interrupted: true,
+            #[cfg(feature = "std")]
+            record_start: false,
             locked: false,
-            truncate_on_close: unsafe {
-                constants::default_lockfile_truncate_state()
-            },
+            shared: false,
+            backend: LockBackend::default(),
+            signal_slot: None,
+            #[cfg(feature = "std")]
+            heartbeat: None,
+            #[cfg(feature = "std")]
+            poison: false,
+            #[cfg(feature = "std")]
+            metrics: metrics::Metrics::default(),
+            #[cfg(feature = "std")]
+            audit: false,
+            #[cfg(feature = "std")]
+            options: LockFileOptions::new(),
+            #[cfg(all(unix, feature = "std"))]
+            claimed: None,
+            #[cfg(all(unix, feature = "std"))]
+            atomic_pid: false,
+            #[cfg(all(unix, feature = "std"))]
+            remove_on_unlock: false,
+            truncate_on_close: constants::default_lockfile_truncate_state(),
+        }
+    }
+
+    /// Writes this process's PID at the current position, followed by its
+    /// start time and the boot ID and flushed to the disk if configured to.
+    fn write_pid(&self) -> Result<(), Error> {
+        writeln!(fmt::Writer(self.desc), "{}", sys::pid())?;
+        #[cfg(feature = "std")]
+        self.write_start()?;
+        if self.sync {
+            sys::sync_data(self.desc)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the start time of this process and the boot ID, if configured
+    /// to record them.
+    #[cfg(feature = "std")]
+    fn write_start(&self) -> Result<(), Error> {
+        if self.record_start {
+            if let Some(start) = sys::process_start_time(std::process::id()) {
+                writeln!(fmt::Writer(self.desc), "start={}", start)?;
+            }
+            if let Some(boot) = sys::boot_id() {
+                writeln!(fmt::Writer(self.desc), "boot={}", boot)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Records whether this handle owns the lock. Shared locks are never
+    /// truncated nor removed on signals, nor refreshed by heartbeats, since
+    /// other readers may be using them.
+    fn set_locked(&mut self, locked: bool) {
+        if locked != self.locked {
+            self.trace_locked(locked);
+            #[cfg(feature = "std")]
+            self.audit(locked);
+        }
+        self.locked = locked;
+        #[cfg(feature = "signals")]
+        if locked && !self.shared && self.signal_slot.is_none() {
+            self.register_automatically();
+        }
+        if let Some(index) = self.signal_slot {
+            signal::set_armed(index, locked && !self.shared);
+        }
+        #[cfg(feature = "std")]
+        if let Some(heartbeat) = &self.heartbeat {
+            heartbeat.set_armed(locked && !self.shared);
+        }
+        #[cfg(feature = "std")]
+        self.set_poison_held(locked && !self.shared);
+        #[cfg(feature = "std")]
+        self.account_locked(locked);
+    }
+
+    /// Writes this process's PID, truncating the file first if requested.
+    #[cfg(not(feature = "std"))]
+    fn publish_pid_keeping_poison(
+        &mut self,
+        truncate: bool,
+    ) -> Result<(), Error> {
+        if truncate {
+            sys::truncate(self.desc)?;
+        }
+        self.publish_pid()
+    }
+
+    /// Blocks until this file is locked, exclusively or shared, retrying when
+    /// interrupted by signals if configured to.
+    fn lock_blocking(&self, shared: bool) -> Result<(), Error> {
+        loop {
+            let result = if shared {
+                sys::lock_shared(self.desc, self.backend)
+            } else {
+                sys::lock(self.desc, self.backend)
+            };
+            match result {
+                Err(error) if self.retries(&error) => {},
+                result => return result,
+            }
         }
     }
+
+    /// Runs the given acquisition; waits are not accounted here.
+    #[cfg(not(feature = "std"))]
+    fn waiting<T, F>(&mut self, acquire: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut Self) -> Result<T, Error>,
+    {
+        acquire(self)
+    }
+
+    /// Whether a blocking lock failing with the given error is retried.
+    #[cfg(unix)]
+    fn retries(&self, error: &Error) -> bool {
+        self.retry_interrupted && sys::is_interrupted(error)
+    }
+
+    /// Blocking locks are never interrupted here.
+    #[cfg(windows)]
+    fn retries(&self, _error: &Error) -> bool {
+        false
+    }
+
+    /// Takes the descriptor out of this handle, which will no longer close it.
+    fn take_desc(&mut self) -> FileDesc {
+        if let Some(index) = self.signal_slot.take() {
+            signal::unregister(index);
+        }
+        #[cfg(feature = "std")]
+        {
+            self.heartbeat = None;
+            self.set_poison_held(false);
+        }
+        core::mem::replace(&mut self.desc, sys::uninitialized_fd())
+    }
+
+    /// Releases the lock owned by this handle, removing the file first if
+    /// configured to and not poisoned. Returns whether the file should then be
+    /// truncated, which is left to the caller.
+    pub(crate) fn release_lock(&mut self) -> Result<bool, Error> {
+        #[cfg(feature = "std")]
+        let poisoned = self.poisoned();
+        #[cfg(not(feature = "std"))]
+        let poisoned = false;
+        let removed = if poisoned { Ok(()) } else { self.remove_if_last() };
+        self.set_locked(false);
+        if self.unclaim() {
+            sys::unlock(self.desc, self.backend)
+                .map_err(|error| self.trace_error("unlock", error))?;
+        }
+        removed?;
+        Ok(self.truncate_on_close && !self.shared && !poisoned)
+    }
 }
 
 // Public functions
@@ -138,9 +444,222 @@ impl LockFile {
     where
         P: ToOsStr + ?Sized,
     {
-        let path = path.to_os_str()?;
-        let desc = sys::open(path.as_ref())?;
-        Ok(Self { desc, ..Self::new() })
+        LockFileOptions::new().open(path)
+    }
+
+    /// Opens a file for locking like [`LockFile::open`], but locking it with
+    /// the given backend instead of the platform default. Handles using
+    /// different backends may not exclude each other, so every process
+    /// locking the file should use the same backend.
+    ///
+    /// # Panics
+    /// Panics if the path contains a nul-byte in a place other than the end.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::{LockBackend, LockFile};
+    ///
+    /// # #[cfg(unix)]
+    /// let backend = LockBackend::Fcntl;
+    /// # #[cfg(windows)]
+    /// # let backend = LockBackend::default();
+    /// let path = "testfiles/nfs.lock";
+    /// let mut file = LockFile::open_with_backend(path, backend)?;
+    /// file.lock()?;
+    /// do_stuff();
+    /// file.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    pub fn open_with_backend<P>(
+        path: &P,
+        backend: LockBackend,
+    ) -> Result<Self, Error>
+    where
+        P: ToOsStr + ?Sized,
+    {
+        LockFileOptions::new().backend(backend).open(path)
+    }
+
+    /// Opens a directory for locking, such as a package of files which must be
+    /// locked as a whole. On Unix, the directory itself is opened (read-only,
+    /// with `O_DIRECTORY`) and locked with `flock`, since `fcntl` locks need a
+    /// writable descriptor, so nothing is created inside it; since nothing can
+    /// be written either, [`LockFile::lock_with_pid`] and the like fail. On
+    /// Windows, where directory handles cannot be locked, a `.lock` file
+    /// inside the directory is opened instead, like by [`LockFile::open`].
+    /// Either way, locks only exclude other handles locking the same
+    /// directory, not handles locking files inside it.
+    ///
+    /// # Panics
+    /// Panics if the path contains a nul-byte in a place other than the end.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// # #[cfg(feature = "std")]
+    /// # {
+    /// # std::fs::create_dir_all("testfiles/font.ufo")?;
+    /// let mut package = LockFile::open_dir("testfiles/font.ufo")?;
+    /// package.lock()?;
+    /// do_stuff();
+    /// package.unlock()?;
+    /// # }
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    #[cfg(any(unix, all(windows, feature = "std")))]
+    pub fn open_dir<P>(path: &P) -> Result<Self, Error>
+    where
+        P: ToOsStr + ?Sized,
+    {
+        #[cfg(unix)]
+        {
+            let mut options = LockFileOptions::new();
+            options
+                .create(false)
+                .backend(LockBackend::Flock)
+                .truncate_on_close(false);
+            options.write = false;
+            options.directory = true;
+            options.open(path)
+        }
+        #[cfg(windows)]
+        {
+            let dir = path.to_os_str()?.to_path_buf();
+            if !dir.is_dir() {
+                return Err(Error::from_raw_os_error(
+                    winapi::shared::winerror::ERROR_DIRECTORY as i32,
+                ));
+            }
+            LockFile::open(dir.join(".lock").as_path())
+        }
+    }
+
+    /// Opens an existing file for locking like [`LockFile::open`], but failing
+    /// with [`std::io::ErrorKind::NotFound`] instead of creating the file if
+    /// it is missing. Useful when lock files are provisioned beforehand with
+    /// specific ownership or permissions.
+    ///
+    /// # Panics
+    /// Panics if the path contains a nul-byte in a place other than the end.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// LockFile::open("testfiles/provisioned.lock")?;
+    /// let mut file = LockFile::open_existing("testfiles/provisioned.lock")?;
+    /// file.lock()?;
+    /// do_stuff();
+    /// file.unlock()?;
+    ///
+    /// assert!(LockFile::open_existing("testfiles/missing.lock").is_err());
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    pub fn open_existing<P>(path: &P) -> Result<Self, Error>
+    where
+        P: ToOsStr + ?Sized,
+    {
+        LockFileOptions::new().create(false).open(path)
+    }
+
+    /// Creates a new file for locking, failing with
+    /// [`std::io::ErrorKind::AlreadyExists`] if the file is present. Only one
+    /// caller may succeed in creating the file, which enables first-writer-wins
+    /// protocols such as atomic PID file creation.
+    ///
+    /// # Panics
+    /// Panics if the path contains a nul-byte in a place other than the end.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// # let _ = std::fs::remove_file("testfiles/create_new.lock");
+    /// let mut file = LockFile::create_new("testfiles/create_new.lock")?;
+    /// file.lock_with_pid()?;
+    /// assert!(LockFile::create_new("testfiles/create_new.lock").is_err());
+    /// file.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn create_new<P>(path: &P) -> Result<Self, Error>
+    where
+        P: ToOsStr + ?Sized,
+    {
+        LockFileOptions::new().create_new(true).open(path)
+    }
+
+    /// Returns the backend used to lock this file.
+    pub fn backend(&self) -> LockBackend {
+        self.backend
+    }
+
+    /// Returns the path of this file: with feature `std`, the canonical path
+    /// (see [`LockFileOptions::canonicalize`]), otherwise the path this file
+    /// was opened with.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let file = LockFile::open("testfiles/path.lock")?;
+    /// assert!(file.path().to_string().ends_with("path.lock"));
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn path(&self) -> &OsStr {
+        &self.path
+    }
+
+    /// Returns whether the file was created when this handle was opened, or
+    /// whether it already existed. If another process created the file at the
+    /// same time, only one of them reports it as created.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// # let _ = std::fs::remove_file("testfiles/was_created.lock");
+    /// let file = LockFile::open("testfiles/was_created.lock")?;
+    /// assert!(file.was_created());
+    /// let other = LockFile::open("testfiles/was_created.lock")?;
+    /// assert!(!other.was_created());
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn was_created(&self) -> bool {
+        self.created
     }
 
     /// Locks this file. Blocks while it is not possible to lock (i.e. someone
@@ -148,7 +667,8 @@ impl LockFile {
     /// made, it will be automatically unlocked on the file handle drop.
     ///
     /// # Panics
-    /// Panics if this handle already owns the file.
+    /// Panics if this handle already owns the file, or if it is an observer
+    /// (see [`LockFile::try_clone`]).
     ///
     /// # Example
     ///
@@ -186,8 +706,20 @@ impl LockFile {
         if self.locked {
             panic!("Cannot lock if already owning a lock");
         }
-        sys::lock(self.desc)?;
-        self.locked = true;
+        if self.observer {
+            panic!("Cannot lock through an observer handle");
+        }
+        self.trace_attempt(false, true);
+        self.claim(false, true)?;
+        if let Err(error) = self.waiting(|file| file.lock_blocking(false)) {
+            self.unclaim();
+            return Err(self.trace_error("lock", error));
+        }
+        if self.reopen_if_replaced()? {
+            return self.lock();
+        }
+        self.shared = false;
+        self.set_locked(true);
         Ok(())
     }
 
@@ -218,7 +750,7 @@ impl LockFile {
     /// # #[cfg(feature = "std")]
     /// fn do_stuff() -> Result<(), fslock::Error> {
     ///     let mut content = read_to_string("testfiles/withpid.lock")?;
-    ///     assert!(content.trim().len() > 0);
+    ///     assert!(!content.trim().is_empty());
     ///     assert!(content.trim().chars().all(|ch| ch.is_ascii_digit()));
     ///     Ok(())
     /// }
@@ -228,11 +760,9 @@ impl LockFile {
     /// ```
     pub fn lock_with_pid(&mut self) -> Result<(), Error> {
         debug_assert!(self.desc != sys::uninitialized_fd());
-        if let Err(error) = self.lock() {
-            return Err(error);
-        }
+        self.lock()?;
 
-        let result = writeln!(fmt::Writer(self.desc), "{}", sys::pid());
+        let result = self.publish_pid_keeping_poison(false);
         if result.is_err() {
             let _ = self.unlock();
         }
@@ -245,7 +775,8 @@ impl LockFile {
     /// drop.
     ///
     /// # Panics
-    /// Panics if this handle already owns the file.
+    /// Panics if this handle already owns the file, or if it is an observer
+    /// (see [`LockFile::try_clone`]).
     ///
     /// # Example
     ///
@@ -284,10 +815,25 @@ impl LockFile {
         if self.locked {
             panic!("Cannot lock if already owning a lock");
         }
-        let lock_result = sys::try_lock(self.desc);
+        if self.observer {
+            panic!("Cannot lock through an observer handle");
+        }
+        self.trace_attempt(false, false);
+        if !self.claim(false, false)? {
+            self.trace_contended();
+            return Ok(false);
+        }
+        let lock_result = sys::try_lock(self.desc, self.backend);
         if let Ok(true) = lock_result {
-            self.locked = true;
+            if self.reopen_if_replaced()? {
+                return self.try_lock();
+            }
+            self.shared = false;
+            self.set_locked(true);
+        } else {
+            self.unclaim();
         }
+        self.trace_outcome(&lock_result);
         lock_result
     }
 
@@ -298,7 +844,8 @@ impl LockFile {
     /// drop.
     ///
     /// Warning: even if `constants::lockfile_truncate` is false, this still
-    /// truncates.
+    /// truncates. Use [`LockFile::try_lock_with_pid_preserving`] to keep the
+    /// existing content.
     ///
     /// # Panics
     /// Panics if this handle already owns the file.
@@ -325,7 +872,7 @@ impl LockFile {
     /// # #[cfg(feature = "std")]
     /// fn do_stuff() -> Result<(), fslock::Error> {
     ///     let mut content = read_to_string("testfiles/pid_attempt.lock")?;
-    ///     assert!(content.trim().len() > 0);
+    ///     assert!(!content.trim().is_empty());
     ///     assert!(content.trim().chars().all(|ch| ch.is_ascii_digit()));
     ///     Ok(())
     /// }
@@ -381,14 +928,187 @@ impl LockFile {
             Err(error) => return Err(error),
         }
 
-        let result = sys::truncate(self.desc)
-            .and_then(|_| writeln!(fmt::Writer(self.desc), "{}", sys::pid()));
+        let result = self.publish_pid_keeping_poison(true);
+        if result.is_err() {
+            let _ = self.unlock();
+        }
+        result.map(|_| true)
+    }
+
+    /// Locks this file and appends this process's PID to it as a new line,
+    /// keeping the existing content. Does NOT block if it is not possible to
+    /// lock (i.e. someone else already owns a lock). The file is still
+    /// truncated on unlock if [`LockFile::truncate_on_close`] is set.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns the file.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/pid_preserving.lock")?;
+    /// file.truncate_on_close = false;
+    /// if file.try_lock_with_pid_preserving()? {
+    ///     do_stuff();
+    ///     file.unlock()?;
+    /// }
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    pub fn try_lock_with_pid_preserving(&mut self) -> Result<bool, Error> {
+        debug_assert!(self.desc != sys::uninitialized_fd());
+        if !self.try_lock()? {
+            return Ok(false);
+        }
+
+        let result = sys::seek_end(self.desc).and_then(|_| self.write_pid());
         if result.is_err() {
             let _ = self.unlock();
         }
         result.map(|_| true)
     }
 
+    /// Locks this file in shared mode. Any number of handles may own a shared
+    /// lock at the same time, while [`LockFile::lock`] waits for all of them
+    /// to be released. Blocks while it is not possible to lock (i.e. someone
+    /// else already owns an exclusive lock). After locked, if no attempt to
+    /// unlock is made, it will be automatically unlocked on the file handle
+    /// drop. Shared locks are never truncated on unlock.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns the file, or if it is an observer
+    /// (see [`LockFile::try_clone`]).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut reader = LockFile::open("testfiles/shared.lock")?;
+    /// let mut other_reader = LockFile::open("testfiles/shared.lock")?;
+    /// reader.lock_shared()?;
+    /// other_reader.lock_shared()?;
+    /// do_stuff();
+    /// reader.unlock()?;
+    /// other_reader.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    ///
+    /// # Panicking Example
+    ///
+    /// ```should_panic
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/shared_panic.lock")?;
+    /// file.lock_shared()?;
+    /// file.lock_shared()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn lock_shared(&mut self) -> Result<(), Error> {
+        debug_assert!(self.desc != sys::uninitialized_fd());
+        if self.locked {
+            panic!("Cannot lock if already owning a lock");
+        }
+        if self.observer {
+            panic!("Cannot lock through an observer handle");
+        }
+        self.trace_attempt(true, true);
+        self.claim(true, true)?;
+        if let Err(error) = self.waiting(|file| file.lock_blocking(true)) {
+            self.unclaim();
+            return Err(self.trace_error("lock", error));
+        }
+        if self.reopen_if_replaced()? {
+            return self.lock_shared();
+        }
+        self.shared = true;
+        self.set_locked(true);
+        Ok(())
+    }
+
+    /// Locks this file in shared mode like [`LockFile::lock_shared`]. Does NOT
+    /// block if it is not possible to lock (i.e. someone else already owns an
+    /// exclusive lock). After locked, if no attempt to unlock is made, it will
+    /// be automatically unlocked on the file handle drop.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns the file, or if it is an observer
+    /// (see [`LockFile::try_clone`]).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut writer = LockFile::open("testfiles/try_shared.lock")?;
+    /// let mut reader = LockFile::open("testfiles/try_shared.lock")?;
+    /// writer.lock()?;
+    /// assert!(!reader.try_lock_shared()?);
+    /// writer.unlock()?;
+    /// assert!(reader.try_lock_shared()?);
+    /// reader.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Panicking Example
+    ///
+    /// ```should_panic
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/try_shared_panic.lock")?;
+    /// file.lock()?;
+    /// file.try_lock_shared()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn try_lock_shared(&mut self) -> Result<bool, Error> {
+        debug_assert!(self.desc != sys::uninitialized_fd());
+        if self.locked {
+            panic!("Cannot lock if already owning a lock");
+        }
+        if self.observer {
+            panic!("Cannot lock through an observer handle");
+        }
+        self.trace_attempt(true, false);
+        if !self.claim(true, false)? {
+            self.trace_contended();
+            return Ok(false);
+        }
+        let lock_result = sys::try_lock_shared(self.desc, self.backend);
+        if let Ok(true) = lock_result {
+            if self.reopen_if_replaced()? {
+                return self.try_lock_shared();
+            }
+            self.shared = true;
+            self.set_locked(true);
+        } else {
+            self.unclaim();
+        }
+        self.trace_outcome(&lock_result);
+        lock_result
+    }
+
     /// Returns whether this file handle owns the lock.
     ///
     /// # Example
@@ -459,28 +1179,233 @@ impl LockFile {
         if !self.locked {
             panic!("Attempted to unlock already locked lockfile");
         }
-        self.locked = false;
-        sys::unlock(self.desc)?;
-        if self.truncate_on_close {
+        if self.release_lock()? {
             sys::truncate(self.desc)?;
         }
         Ok(())
     }
 
-    /// It is recommended that you use the implementation [`<&mut LockFile as
-    /// Into<File>>::into`](struct.LockFile.html#impl-Into<File>) instead of
-    /// this low-level function.
+    /// It is recommended that you use [`LockFile::into_raw`] to take the
+    /// descriptor instead of this low-level function, or, to borrow it, the
+    /// implementation of `AsFd` on Unix or `AsHandle` on Windows.
+    ///
+    /// # Safety
+    /// The returned descriptor is still owned by this handle: it must not be
+    /// closed, and it must not be used after this handle is dropped.
     pub unsafe fn raw(&self) -> FileDesc {
         debug_assert!(self.desc != sys::uninitialized_fd());
         self.desc
     }
+
+    /// Sets whether dropping this handle while it owns a lock keeps the lock
+    /// held, leaking the descriptor instead of unlocking and closing it, such
+    /// as to hand the lock over to a program executed in place of this one,
+    /// which inherits the descriptor unless it is close-on-exec (as it is by
+    /// default on Unix; see also [`LockFile::spawn_holding`]). The file is
+    /// then neither truncated nor removed, and handlers registered by
+    /// [`LockFile::release_on_signal`] no longer release it. Explicitly
+    /// unlocking the handle still releases the lock. Defaults to `false`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/keep_locked_on_drop.lock")?;
+    /// file.lock()?;
+    /// file.keep_locked_on_drop(true);
+    /// drop(file);
+    ///
+    /// let mut other = LockFile::open("testfiles/keep_locked_on_drop.lock")?;
+    /// assert!(!other.try_lock()?);
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn keep_locked_on_drop(&mut self, keep: bool) {
+        self.keep_locked = keep;
+    }
+
+    /// Leaks this handle, so the lock it owns, if any, is held for the rest of
+    /// the lifetime of the process, such as by daemons locking their lock file
+    /// once at startup. The file is never closed, truncated nor removed,
+    /// though the heartbeat (see [`LockFile::set_heartbeat`]) keeps beating,
+    /// and handlers registered by [`LockFile::release_on_signal`] still
+    /// release the lock when the process is asked to terminate.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/daemon.lock")?;
+    /// if !file.try_lock_with_pid()? {
+    ///     panic!("already running");
+    /// }
+    /// file.disown();
+    /// run_daemon();
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn run_daemon() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    pub fn disown(self) {
+        core::mem::forget(self);
+    }
+
+    /// Duplicates the descriptor of this handle into an observer handle,
+    /// which may read the file (e.g. [`LockFile::read_contents`]) but never
+    /// owns a lock, nor locks anything through the duplicate: attempts to lock
+    /// through it panic. Whether the lock owned by this handle, if any, is
+    /// shared with the observer depends on the backend:
+    ///
+    /// - With `flock` and open file description locks (Unix), and on Windows,
+    ///   the duplicate refers to the same open file, which holds the lock, so
+    ///   closing the observer never releases it; it is released when this
+    ///   handle unlocks it or is dropped.
+    /// - With `fcntl` and `lockf` locks (Unix), locks are held by the process,
+    ///   so, like closing any other handle to the file, closing the observer
+    ///   releases every lock of the process on it, including this handle's.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/try_clone.lock")?;
+    /// file.lock_with_pid()?;
+    /// let mut observer = file.try_clone()?;
+    /// assert!(!observer.owns_lock());
+    /// # #[cfg(feature = "std")]
+    /// # {
+    /// let contents = observer.read_contents()?;
+    /// let pid = String::from_utf8_lossy(&contents);
+    /// assert_eq!(pid.trim(), std::process::id().to_string());
+    /// # }
+    /// drop(observer);
+    /// file.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Panicking Example
+    ///
+    /// ```should_panic
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let file = LockFile::open("testfiles/observer.lock")?;
+    /// let mut observer = file.try_clone()?;
+    /// observer.lock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn try_clone(&self) -> Result<Self, Error> {
+        debug_assert!(self.desc != sys::uninitialized_fd());
+        let desc = sys::duplicate(self.desc)?;
+        let mut file = LockFile::new(self.path.clone(), desc);
+        file.backend = self.backend;
+        file.truncate_on_close = false;
+        file.observer = true;
+        Ok(file)
+    }
+
+    /// Turns this handle into its descriptor (an `OwnedFd` on Unix and an
+    /// `OwnedHandle` on Windows with feature `std`, raw otherwise), such as to
+    /// carry it across FFI boundaries. The lock owned by the handle, if any,
+    /// stays held until the descriptor is closed, and the file is neither
+    /// truncated nor removed. Pass it to [`LockFile::from_raw`] to get a
+    /// handle back.
+    ///
+    /// # Panics
+    /// Panics if this handle owns a lock held by the process, i.e. acquired
+    /// with the `Fcntl` or `Lockf` backend, which other handles of this
+    /// process could then acquire too.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let path = "testfiles/into_raw.lock";
+    /// let mut file = LockFile::open(path)?;
+    /// file.lock()?;
+    /// let desc = file.into_raw();
+    /// do_stuff();
+    /// let mut file = unsafe { LockFile::from_raw(desc, path, true)? };
+    /// assert!(file.owns_lock());
+    /// file.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    pub fn into_raw(mut self) -> OwnedDesc {
+        #[cfg(all(unix, feature = "std"))]
+        if self.claimed.is_some() {
+            panic!("Cannot take the descriptor of a lock held by the process");
+        }
+        let desc = self.take_desc();
+        unsafe { sys::own(desc) }
+    }
+
+    /// Turns a descriptor of the file at the given path back into a handle,
+    /// owning an exclusive lock if `locked` is set. The handle is configured
+    /// like by [`LockFile::open`], and its path is kept as is.
+    ///
+    /// # Safety
+    /// The descriptor must be a descriptor of the file at the given path,
+    /// readable and writable, such as returned by [`LockFile::into_raw`] on a
+    /// handle opened by [`LockFile::open`]. If `locked` is set, it must hold an
+    /// exclusive lock acquired with the default [`LockBackend`], and
+    /// otherwise, it must hold no lock. Descriptors holding shared locks must
+    /// be unlocked first.
+    ///
+    /// # Panics
+    /// Panics if the path contains a nul-byte in a place other than the end.
+    pub unsafe fn from_raw<P>(
+        desc: OwnedDesc,
+        path: &P,
+        locked: bool,
+    ) -> Result<Self, Error>
+    where
+        P: ToOsStr + ?Sized,
+    {
+        let path = path.to_os_str()?.into_os_string()?;
+        let mut file = LockFile::new(path, sys::disown(desc));
+        if locked {
+            file.claim(false, false)?;
+            file.set_locked(true);
+        }
+        Ok(file)
+    }
 }
 
 impl Drop for LockFile {
     fn drop(&mut self) {
+        if self.desc == sys::uninitialized_fd() {
+            return;
+        }
+        if self.locked && self.keep_locked {
+            self.take_desc();
+            return;
+        }
         if self.locked {
             let _ = self.unlock();
         }
+        if let Some(index) = self.signal_slot.take() {
+            signal::unregister(index);
+        }
         sys::close(self.desc);
     }
 }
@@ -0,0 +1,236 @@
+//! This module implements the lock protocol based on counting hard links,
+//! recommended for NFSv2 and NFSv3, where neither `O_EXCL` nor `fcntl` locks
+//! can be trusted.
+
+use crate::{
+    dir::{is_stale, owner_record},
+    lease::{new_token, read_if_exists, with_suffix},
+    retry::Backoff,
+    sys,
+    Error,
+    LockInfo,
+    RetryPolicy,
+    ToOsStr,
+};
+use std::{
+    fs,
+    io::ErrorKind,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+/// A lock held by hard-linking a uniquely named temporary file to the lock
+/// path (see `link(2)`). Since NFS may report the outcome of `link` wrongly,
+/// it is ignored, and the lock is held if the temporary file has two links
+/// afterwards. The lock file records the PID, host name and time of locking
+/// (see [`LockInfo`]) of its owner.
+///
+/// Staleness is detected as by [`crate::DirLock`]: a lock whose owner died,
+/// according to [`LockInfo`], is taken over, as is one whose file was not
+/// modified for longer than [`LinkLock::set_stale_after`], if set. Owners on
+/// other hosts should then call [`LinkLock::refresh`] regularly. Unlike file
+/// locks, the lock file is NOT removed when its owner exits without
+/// unlocking it.
+///
+/// # Example
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// use fslock::LinkLock;
+/// use std::time::Duration;
+///
+/// let mut lock = LinkLock::new("testfiles/link.lock")?;
+/// lock.set_stale_after(Some(Duration::from_secs(60)));
+/// lock.lock()?;
+/// do_stuff();
+/// lock.unlock()?;
+///
+/// # Ok(())
+/// # }
+/// # fn do_stuff() {
+/// #    // doing stuff here.
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct LinkLock {
+    /// Path of the lock file.
+    path: PathBuf,
+    /// Age of the lock file after which the lock is stale.
+    stale_after: Option<Duration>,
+    /// Token identifying this handle as the owner.
+    token: String,
+    /// Contents of the lock file written by this handle, if locked.
+    owner: Option<Vec<u8>>,
+}
+
+impl LinkLock {
+    /// Creates a handle to the lock at the given path. No file is created
+    /// until the lock is acquired.
+    ///
+    /// # Panics
+    /// Panics if the path contains a nul-byte in a place other than the end.
+    pub fn new<P>(path: &P) -> Result<Self, Error>
+    where
+        P: ToOsStr + ?Sized,
+    {
+        Ok(Self {
+            path: path.to_os_str()?.to_path_buf(),
+            stale_after: None,
+            token: new_token(),
+            owner: None,
+        })
+    }
+
+    /// Sets how long after the lock file was last modified the lock is
+    /// considered stale, or `None` (the default) to only consider locks of dead
+    /// owners on this host stale.
+    pub fn set_stale_after(&mut self, stale_after: Option<Duration>) {
+        self.stale_after = stale_after;
+    }
+
+    /// Returns the path of the lock file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns whether this handle owns the lock, as far as it knows: the lock
+    /// may have been taken over as stale.
+    pub fn owns_lock(&self) -> bool {
+        self.owner.is_some()
+    }
+
+    /// Links a temporary file recording this handle to the lock path. Does NOT
+    /// block if someone else holds the lock. Returns whether it was acquired.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns the lock.
+    pub fn try_lock(&mut self) -> Result<bool, Error> {
+        if self.owner.is_some() {
+            panic!("Cannot lock if already owning a lock");
+        }
+        let owner = owner_record(&self.token)?;
+        let temp = with_suffix(&self.path, &format!(".{}.tmp", self.token));
+        fs::write(&temp, &owner)?;
+        let result = self.link(&temp);
+        let _ = fs::remove_file(&temp);
+        if result? {
+            self.owner = Some(owner.into_bytes());
+        }
+        Ok(self.owner.is_some())
+    }
+
+    /// Links the given temporary file to the lock path, taking over stale
+    /// locks. Returns whether the lock was acquired.
+    fn link(&self, temp: &Path) -> Result<bool, Error> {
+        loop {
+            // The outcome of link is unreliable over NFS; the link count is.
+            let _ = fs::hard_link(temp, &self.path);
+            if fs::metadata(temp)?.nlink() == 2 {
+                return Ok(true);
+            }
+            let Some(observed) = read_if_exists(&self.path)? else {
+                continue;
+            };
+            if !is_stale(&self.path, Some(&observed), self.stale_after)? {
+                return Ok(false);
+            }
+            self.take_over(&observed)?;
+        }
+    }
+
+    /// Removes the stale lock file with the given contents, unless it was
+    /// replaced meanwhile. A file replaced meanwhile is moved back into place.
+    fn take_over(&self, observed: &[u8]) -> Result<(), Error> {
+        let grave = with_suffix(&self.path, &format!(".{}.old", self.token));
+        match fs::rename(&self.path, &grave) {
+            Ok(()) => (),
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(error) => return Err(error),
+        }
+        if read_if_exists(&grave)?.as_deref() != Some(observed) {
+            let _ = fs::hard_link(&grave, &self.path);
+        }
+        fs::remove_file(&grave)
+    }
+
+    /// Acquires the lock, retrying while someone else holds it, until it is
+    /// released or becomes stale.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns the lock.
+    pub fn lock(&mut self) -> Result<(), Error> {
+        let policy = RetryPolicy {
+            max_delay: Duration::from_secs(1),
+            ..RetryPolicy::default()
+        };
+        self.lock_with_policy(&policy)?;
+        Ok(())
+    }
+
+    /// Acquires the lock, retrying while someone else holds it as configured
+    /// by the given policy. Returns whether the lock was acquired before
+    /// attempts were exhausted.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns the lock.
+    pub fn lock_with_policy(
+        &mut self,
+        policy: &RetryPolicy,
+    ) -> Result<bool, Error> {
+        let mut backoff = Backoff::new(policy);
+        loop {
+            if self.try_lock()? {
+                return Ok(true);
+            }
+            match backoff.next_delay() {
+                Some(delay) => thread::sleep(delay),
+                None => return Ok(false),
+            }
+        }
+    }
+
+    /// Reads the record of the owner of the lock, if locked.
+    pub fn owner_info(&self) -> Result<Option<LockInfo>, Error> {
+        let owner = read_if_exists(&self.path)?;
+        Ok(owner.and_then(|owner| LockInfo::parse(&owner)))
+    }
+
+    /// Refreshes the modification time of the lock file, so the lock is not
+    /// considered stale by handles with [`LinkLock::set_stale_after`].
+    ///
+    /// # Panics
+    /// Panics if this handle does not own the lock.
+    pub fn refresh(&self) -> Result<(), Error> {
+        if self.owner.is_none() {
+            panic!("Attempted to refresh an unowned lock");
+        }
+        sys::touch(&self.path.to_os_str()?)
+    }
+
+    /// Releases the lock by removing the lock file, unless it was taken over
+    /// as stale.
+    ///
+    /// # Panics
+    /// Panics if this handle does not own the lock.
+    pub fn unlock(&mut self) -> Result<(), Error> {
+        let Some(owner) = self.owner.take() else {
+            panic!("Attempted to unlock already unlocked lockfile");
+        };
+        if read_if_exists(&self.path)?.as_deref() != Some(&owner[..]) {
+            return Ok(());
+        }
+        match fs::remove_file(&self.path) {
+            Err(error) if error.kind() != ErrorKind::NotFound => Err(error),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for LinkLock {
+    fn drop(&mut self) {
+        if self.owner.is_some() {
+            let _ = self.unlock();
+        }
+    }
+}
@@ -0,0 +1,185 @@
+//! This module implements accounting of the time handles spend waiting for
+//! locks and holding them, so services can export lock contention to their
+//! monitoring systems.
+//!
+//! Every handle keeps its own totals (see [`LockFile::metrics`]). Time is
+//! waited in blocking acquisitions, such as [`LockFile::lock`], and in those
+//! retrying attempts, such as [`LockFile::try_lock_until`]; single attempts,
+//! such as [`LockFile::try_lock`], never wait. Once [`enable_registry`] is
+//! called, the totals of all handles are also added up per path, as waits and
+//! holds complete, for [`snapshot`] to report.
+//!
+//! # Example
+//! ```
+//! # fn main() -> Result<(), fslock::Error> {
+//! use fslock::{metrics, LockFile};
+//!
+//! metrics::enable_registry();
+//! let mut file = LockFile::open("testfiles/metrics.lock")?;
+//! file.lock()?;
+//! do_stuff();
+//! file.unlock()?;
+//!
+//! for (path, totals) in metrics::snapshot() {
+//!     println!(
+//!         "{}: {} acquisitions, waited {:?}, held {:?}",
+//!         path.display(),
+//!         totals.acquisitions,
+//!         totals.time_waited,
+//!         totals.time_held,
+//!     );
+//! }
+//!
+//! # Ok(())
+//! # }
+//! # fn do_stuff() {
+//! #    // doing stuff here.
+//! # }
+//! ```
+
+use crate::{Error, LockFile};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Totals of lock acquisitions.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct LockMetrics {
+    /// Number of times the lock was acquired, exclusively or shared.
+    pub acquisitions: u64,
+    /// Time spent waiting for the lock.
+    pub time_waited: Duration,
+    /// Time spent holding the lock.
+    pub time_held: Duration,
+}
+
+/// Accounting of a handle.
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    /// Totals of completed waits and holds.
+    totals: LockMetrics,
+    /// When the lock currently held was acquired.
+    held_since: Option<Instant>,
+}
+
+/// Totals per path, once enabled.
+static REGISTRY: Mutex<Option<HashMap<PathBuf, LockMetrics>>> =
+    Mutex::new(None);
+
+/// Starts adding up the totals of all handles per path. Calling it again has
+/// no effect.
+pub fn enable_registry() {
+    let mut registry =
+        REGISTRY.lock().unwrap_or_else(|error| error.into_inner());
+    registry.get_or_insert_with(HashMap::new);
+}
+
+/// Returns the totals per path added up since [`enable_registry`] was called,
+/// sorted by path. Locks still held only count for their acquisition.
+pub fn snapshot() -> Vec<(PathBuf, LockMetrics)> {
+    let registry = REGISTRY.lock().unwrap_or_else(|error| error.into_inner());
+    let mut totals: Vec<_> = registry
+        .iter()
+        .flatten()
+        .map(|(path, totals)| (path.clone(), *totals))
+        .collect();
+    totals.sort_by(|(left, _), (right, _)| left.cmp(right));
+    totals
+}
+
+impl LockFile {
+    /// Returns the totals of this handle, including the lock it currently
+    /// holds, if any.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/lock_metrics.lock")?;
+    /// file.lock()?;
+    /// file.unlock()?;
+    /// file.lock_shared()?;
+    /// assert_eq!(file.metrics().acquisitions, 2);
+    /// file.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn metrics(&self) -> LockMetrics {
+        let mut totals = self.metrics.totals;
+        if let Some(since) = self.metrics.held_since {
+            totals.time_held += since.elapsed();
+        }
+        totals
+    }
+
+    /// Returns the time this handle spent waiting for locks.
+    pub fn time_waited(&self) -> Duration {
+        self.metrics().time_waited
+    }
+
+    /// Returns the time this handle spent holding locks, including the lock it
+    /// currently holds, if any.
+    pub fn time_held(&self) -> Duration {
+        self.metrics().time_held
+    }
+
+    /// Returns the number of times this handle acquired a lock.
+    pub fn acquisitions(&self) -> u64 {
+        self.metrics().acquisitions
+    }
+
+    /// Runs the given acquisition, accounting the time it takes as waited.
+    pub(crate) fn waiting<T, F>(&mut self, acquire: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut Self) -> Result<T, Error>,
+    {
+        #[cfg(feature = "tracing")]
+        let _span =
+            tracing::debug_span!("waiting", path = %self.path()).entered();
+        let start = Instant::now();
+        let result = acquire(self);
+        let waited = start.elapsed();
+        self.metrics.totals.time_waited += waited;
+        self.record(|totals| totals.time_waited += waited);
+        result
+    }
+
+    /// Accounts for whether this handle owns a lock, counting acquisitions
+    /// and completed holds.
+    pub(crate) fn account_locked(&mut self, locked: bool) {
+        match (self.metrics.held_since, locked) {
+            (None, true) => {
+                self.metrics.held_since = Some(Instant::now());
+                self.metrics.totals.acquisitions += 1;
+                self.record(|totals| totals.acquisitions += 1);
+            },
+            (Some(since), false) => {
+                let held = since.elapsed();
+                self.metrics.held_since = None;
+                self.metrics.totals.time_held += held;
+                self.record(|totals| totals.time_held += held);
+            },
+            _ => {},
+        }
+    }
+
+    /// Updates the totals of the path of this handle in the registry, if
+    /// enabled.
+    fn record<F>(&self, update: F)
+    where
+        F: FnOnce(&mut LockMetrics),
+    {
+        let mut registry =
+            REGISTRY.lock().unwrap_or_else(|error| error.into_inner());
+        if let Some(registry) = registry.as_mut() {
+            update(registry.entry(self.path.to_path_buf()).or_default());
+        }
+    }
+}
@@ -0,0 +1,331 @@
+//! This module implements locks identified by a name rather than a path.
+
+use crate::{Error, LockFile, LockFileOptions, ToOsStr};
+use std::{
+    env,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// Returns the directory holding named locks.
+#[cfg(unix)]
+fn directory() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => dir,
+        _ => PathBuf::from("/tmp"),
+    }
+}
+
+/// Returns the directory holding named locks.
+#[cfg(windows)]
+fn directory() -> PathBuf {
+    match env::var_os("LOCALAPPDATA").map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => dir.join("Temp"),
+        _ => env::temp_dir(),
+    }
+}
+
+/// Returns the directory holding system-wide locks: `/run/lock`, or
+/// `/var/lock` on systems without it.
+#[cfg(unix)]
+fn system_directory() -> PathBuf {
+    let run = PathBuf::from("/run/lock");
+    if run.is_dir() {
+        run
+    } else {
+        PathBuf::from("/var/lock")
+    }
+}
+
+/// Returns the directory holding system-wide locks: `fslock` in
+/// `%ProgramData%`.
+#[cfg(windows)]
+fn system_directory() -> PathBuf {
+    match env::var_os("ProgramData").map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => dir.join("fslock"),
+        _ => PathBuf::from(r"C:\ProgramData\fslock"),
+    }
+}
+
+/// Replaces characters which are not ASCII alphanumerics, `-`, `_` nor `.`
+/// with `_`, so that the name is valid on every platform.
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|ch| match ch {
+            'a' ..= 'z' | 'A' ..= 'Z' | '0' ..= '9' | '-' | '_' | '.' => ch,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// Returns the file name of the lock with the given name, sanitized.
+fn file_name(name: &str) -> String {
+    format!("{}.lock", sanitize(name))
+}
+
+/// Hashes the given bytes with 64-bit FNV-1a, which is simple enough for any
+/// tool to reproduce.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Returns the name of the sidecar lock file of the file at the given
+/// canonical path in the directory of named locks.
+fn hashed_sidecar_name(path: &Path) -> String {
+    let stem = path
+        .file_name()
+        .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+    let hash = fnv1a(path.to_string_lossy().as_bytes());
+    let name = file_name(&stem);
+    format!("{}-{:016x}.lock", name.trim_end_matches(".lock"), hash)
+}
+
+/// Returns a directory for lock files private to the current user, creating
+/// it if needed: `$XDG_RUNTIME_DIR` if set, or else `/tmp/fslock-<uid>` with
+/// `0700` permissions on Unix, and `%LOCALAPPDATA%\fslock` on Windows. Locks
+/// in it cannot be contended nor held hostage by other users.
+///
+/// On Unix, fails with [`std::io::ErrorKind::PermissionDenied`] if the
+/// directory in `/tmp` exists but is not a directory owned by the current
+/// user, as another user may have created it to interfere.
+///
+/// # Example
+///
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// let dir = fslock::user_lock_dir()?;
+/// assert!(dir.is_dir());
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(unix)]
+pub fn user_lock_dir() -> Result<PathBuf, Error> {
+    use std::{
+        io::ErrorKind,
+        os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt},
+    };
+
+    if let Some(dir) = env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from) {
+        if dir.is_absolute() {
+            return Ok(dir);
+        }
+    }
+    let uid = crate::sys::user_id();
+    let dir = PathBuf::from(format!("/tmp/fslock-{}", uid));
+    match fs::DirBuilder::new().mode(0o700).create(&dir) {
+        Err(error) if error.kind() != ErrorKind::AlreadyExists => {
+            return Err(error)
+        },
+        _ => (),
+    }
+    let metadata = fs::symlink_metadata(&dir)?;
+    if !metadata.is_dir() || metadata.uid() != uid {
+        return Err(Error::new(
+            ErrorKind::PermissionDenied,
+            "lock directory is not owned by the current user",
+        ));
+    }
+    if metadata.mode() & 0o777 != 0o700 {
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(dir)
+}
+
+/// Returns a directory for lock files private to the current user, creating
+/// it if needed: `$XDG_RUNTIME_DIR` if set, or else `/tmp/fslock-<uid>` with
+/// `0700` permissions on Unix, and `%LOCALAPPDATA%\fslock` on Windows. Locks
+/// in it cannot be contended nor held hostage by other users.
+///
+/// # Example
+///
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// let dir = fslock::user_lock_dir()?;
+/// assert!(dir.is_dir());
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(windows)]
+pub fn user_lock_dir() -> Result<PathBuf, Error> {
+    let dir = match env::var_os("LOCALAPPDATA").map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => dir.join("fslock"),
+        _ => env::temp_dir().join("fslock"),
+    };
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+impl LockFile {
+    /// Returns the path of the lock file named by [`LockFile::named`]:
+    /// `<name>.lock` in `$XDG_RUNTIME_DIR`, or `/tmp` if unset, on Unix, and
+    /// in `%LOCALAPPDATA%\Temp` on Windows. Characters of the name other than
+    /// ASCII alphanumerics, `-`, `_` and `.` are replaced with `_`, so names
+    /// differing only by such characters name the same lock.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fslock::LockFile;
+    ///
+    /// let path = LockFile::named_path("my app/daemon");
+    /// assert!(path.ends_with("my_app_daemon.lock"));
+    /// ```
+    pub fn named_path(name: &str) -> PathBuf {
+        directory().join(file_name(name))
+    }
+
+    /// Opens the lock file with the given name, shared by every process of
+    /// the machine using the same name (of the same user where
+    /// `$XDG_RUNTIME_DIR` is set, since it is private to each user), creating
+    /// it if needed. See [`LockFile::named_path`] for where it is found.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::named("fslock-doctest")?;
+    /// file.lock()?;
+    /// do_stuff();
+    /// file.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    pub fn named(name: &str) -> Result<Self, Error> {
+        LockFile::open(Self::named_path(name).as_path())
+    }
+
+    /// Opens the lock file with the given name in the directory private to
+    /// the current user returned by [`user_lock_dir`], creating both if
+    /// needed. Names are sanitized like by [`LockFile::named_path`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::user_scoped("fslock-doctest")?;
+    /// file.lock()?;
+    /// do_stuff();
+    /// file.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    pub fn user_scoped(name: &str) -> Result<Self, Error> {
+        LockFile::open(user_lock_dir()?.join(file_name(name)).as_path())
+    }
+
+    /// Opens the conventional companion lock file of the file at the given
+    /// path, creating it if needed: the path followed by `.lock` (e.g.
+    /// `data.db.lock` for `data.db`), or, if it cannot be created there
+    /// because the directory is read-only, a file in the directory of
+    /// [`LockFile::named_path`] named after the file name and the 64-bit
+    /// FNV-1a hash of the UTF-8 canonical path in hexadecimal (e.g.
+    /// `data.db-0123456789abcdef.lock`), sanitized like by
+    /// [`LockFile::named_path`]. The file itself need not exist.
+    ///
+    /// # Panics
+    /// Panics if the path contains a nul-byte in a place other than the end.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::sidecar("testfiles/data.db")?;
+    /// assert!(file.path().to_string().ends_with("data.db.lock"));
+    /// file.lock()?;
+    /// do_stuff();
+    /// file.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    pub fn sidecar<P>(path: &P) -> Result<Self, Error>
+    where
+        P: ToOsStr + ?Sized,
+    {
+        let path = path.to_os_str()?.to_path_buf();
+        let path = fs::canonicalize(&path).unwrap_or(path);
+        let mut sidecar = path.clone().into_os_string();
+        sidecar.push(".lock");
+        match LockFile::open(sidecar.as_os_str()) {
+            Err(error)
+                if matches!(
+                    error.kind(),
+                    ErrorKind::PermissionDenied | ErrorKind::ReadOnlyFilesystem
+                ) =>
+            {
+                let hashed = directory().join(hashed_sidecar_name(&path));
+                LockFile::open(hashed.as_path())
+            },
+            result => result,
+        }
+    }
+
+    /// Returns the path of the lock file named by [`LockFile::system`]:
+    /// `<name>.lock` in `/run/lock`, or `/var/lock` where it does not exist,
+    /// on Unix, following the Filesystem Hierarchy Standard, and in
+    /// `%ProgramData%\fslock` on Windows. Names are sanitized like by
+    /// [`LockFile::named_path`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use fslock::LockFile;
+    ///
+    /// let path = LockFile::system_path("my-daemon");
+    /// assert!(path.ends_with("my-daemon.lock"));
+    /// ```
+    pub fn system_path(name: &str) -> PathBuf {
+        system_directory().join(file_name(name))
+    }
+
+    /// Opens the system-wide lock file with the given name, such as the one
+    /// of a daemon, creating it if needed. See [`LockFile::system_path`] for
+    /// where it is found. Created files are readable by everyone but only
+    /// writable by their owner (`0o644`, regardless of the umask, on Unix),
+    /// so any user may read the PID of the owner. The directory is usually
+    /// writable only by privileged users, or by the `lock` group on some
+    /// systems; on Windows, it is created if needed.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::system("my-daemon")?;
+    /// if !file.try_lock_with_pid()? {
+    ///     panic!("my-daemon is already running");
+    /// }
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn system(name: &str) -> Result<Self, Error> {
+        let mut options = LockFileOptions::new();
+        #[cfg(unix)]
+        options.mode(0o644);
+        #[cfg(windows)]
+        options.create_dirs(true);
+        options.open(Self::system_path(name).as_path())
+    }
+}
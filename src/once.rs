@@ -0,0 +1,54 @@
+//! This module implements running code once across processes, such as one-time
+//! migrations.
+
+use crate::{Error, LockFile, ToOsStr};
+
+/// Contents of the file at the path of a completed [`run_once`].
+const DONE: &[u8] = b"done\n";
+
+/// Runs the given function unless it already ran to completion for the given
+/// path, in this process or any other sharing the path. Returns what the
+/// function returned, or `None` if it was skipped.
+///
+/// The file at the path is created if needed and exclusively locked while the
+/// function runs, so concurrent callers block until it returns and then skip
+/// it. Completion is recorded into the file, flushed to the disk, before it is
+/// unlocked. If the function panics, completion is not recorded and the next
+/// caller runs it again.
+///
+/// # Panics
+/// Panics if the path contains a nul-byte in a place other than the end.
+///
+/// # Example
+///
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// # let _ = std::fs::remove_file("testfiles/migration.once");
+/// let ran = fslock::run_once("testfiles/migration.once", || migrate())?;
+/// assert_eq!(ran, Some(()));
+/// let ran = fslock::run_once("testfiles/migration.once", || migrate())?;
+/// assert_eq!(ran, None);
+/// # Ok(())
+/// # }
+/// # fn migrate() {
+/// #    // migrating stuff here.
+/// # }
+/// ```
+pub fn run_once<P, F, T>(path: &P, function: F) -> Result<Option<T>, Error>
+where
+    P: ToOsStr + ?Sized,
+    F: FnOnce() -> T,
+{
+    let mut file = LockFile::open(path)?;
+    file.truncate_on_close = false;
+    file.lock()?;
+    if file.read_contents()? == DONE {
+        file.unlock()?;
+        return Ok(None);
+    }
+
+    let output = function();
+    let result = file.write_contents(DONE);
+    let unlocked = file.unlock();
+    result.and(unlocked).map(|()| Some(output))
+}
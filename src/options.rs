@@ -0,0 +1,462 @@
+//! This module implements the builder configuring how lock files are opened.
+
+use crate::{sys, trace, Error, IntoOsString, LockBackend, LockFile, ToOsStr};
+
+/// Options and flags for opening a [`LockFile`], in the style of
+/// `std::fs::OpenOptions`. By default, the file is created if missing, opened
+/// for reading and writing, truncated on close according to
+/// [`crate::lockfile_truncate`], and locked with the default [`LockBackend`].
+///
+/// # Example
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// use fslock::LockFileOptions;
+///
+/// let mut options = LockFileOptions::new();
+/// options.create(true).truncate_on_close(false);
+/// # #[cfg(unix)]
+/// options.mode(0o600);
+/// let mut file = options.open("testfiles/options.lock")?;
+/// file.lock()?;
+/// do_stuff();
+/// file.unlock()?;
+///
+/// # Ok(())
+/// # }
+/// # fn do_stuff() {
+/// #    // doing stuff here.
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct LockFileOptions {
+    /// Whether a missing file is created.
+    pub(crate) create: bool,
+    /// Whether opening fails if the file already exists.
+    pub(crate) create_new: bool,
+    /// Whether the file is opened for reading besides writing.
+    pub(crate) read: bool,
+    /// Whether the file is opened for writing, only unset internally to
+    /// inspect files without being able to lock them.
+    pub(crate) write: bool,
+    /// Whether missing parent directories are created.
+    #[cfg(feature = "std")]
+    create_dirs: bool,
+    /// Permissions of created parent directories, or `None` for the default.
+    #[cfg(all(unix, feature = "std"))]
+    dir_mode: Option<u32>,
+    /// Whether a symbolic link at the path is followed.
+    pub(crate) follow_symlinks: bool,
+    /// Whether the path must be a directory, only set internally by
+    /// [`LockFile::open_dir`].
+    #[cfg(unix)]
+    pub(crate) directory: bool,
+    /// Permissions of a created file, or `None` for the default.
+    #[cfg(unix)]
+    pub(crate) mode: Option<u32>,
+    /// Sharing mode passed to `CreateFileW`.
+    #[cfg(windows)]
+    pub(crate) share_mode: u32,
+    /// Address of the `SECURITY_ATTRIBUTES` passed to `CreateFileW`, or zero
+    /// for the default.
+    #[cfg(windows)]
+    pub(crate) security_attributes: usize,
+    /// Whether written PIDs are flushed to the disk.
+    sync: bool,
+    /// Whether start times are written after PIDs.
+    #[cfg(feature = "std")]
+    record_start_time: bool,
+    /// Whether acquisitions and releases are appended to the history.
+    #[cfg(feature = "std")]
+    audit_log: bool,
+    /// Whether the parent directory is flushed after creating the file.
+    sync_dir: bool,
+    /// Whether the path of the handle is canonicalized.
+    #[cfg(feature = "std")]
+    canonicalize: bool,
+    /// Whether PIDs are published by replacing the file.
+    #[cfg(all(unix, feature = "std"))]
+    atomic_pid: bool,
+    /// Whether the file is removed by its last holder.
+    #[cfg(all(unix, feature = "std"))]
+    remove_on_unlock: bool,
+    /// Whether blocking locks interrupted by signals are retried.
+    #[cfg(unix)]
+    retry_interrupted: bool,
+    /// Truncation on close, or `None` for the default.
+    truncate_on_close: Option<bool>,
+    /// Backend locking the file.
+    backend: LockBackend,
+}
+
+impl LockFileOptions {
+    /// Creates the default options.
+    pub fn new() -> Self {
+        Self {
+            create: true,
+            create_new: false,
+            read: true,
+            write: true,
+            #[cfg(feature = "std")]
+            create_dirs: false,
+            #[cfg(all(unix, feature = "std"))]
+            dir_mode: None,
+            follow_symlinks: true,
+            #[cfg(unix)]
+            directory: false,
+            #[cfg(unix)]
+            mode: None,
+            #[cfg(windows)]
+            share_mode: sys::DEFAULT_SHARE_MODE,
+            #[cfg(windows)]
+            security_attributes: 0,
+            sync: false,
+            #[cfg(feature = "std")]
+            record_start_time: false,
+            #[cfg(feature = "std")]
+            audit_log: false,
+            sync_dir: false,
+            #[cfg(feature = "std")]
+            canonicalize: true,
+            #[cfg(all(unix, feature = "std"))]
+            atomic_pid: false,
+            #[cfg(all(unix, feature = "std"))]
+            remove_on_unlock: false,
+            #[cfg(unix)]
+            retry_interrupted: true,
+            truncate_on_close: None,
+            backend: LockBackend::default(),
+        }
+    }
+
+    /// Sets whether the file is created if missing, or opening fails instead.
+    /// Defaults to `true`.
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.create = create;
+        self
+    }
+
+    /// Sets whether the file must be created by this call, failing with
+    /// `AlreadyExists` if it is present, atomically. When set, [`Self::create`]
+    /// is ignored. Defaults to `false`.
+    pub fn create_new(&mut self, create_new: bool) -> &mut Self {
+        self.create_new = create_new;
+        self
+    }
+
+    /// Sets whether the file is opened for reading besides writing, so its
+    /// contents (such as the PID of the owner) can be read through the handle.
+    /// Defaults to `true`.
+    pub fn read(&mut self, read: bool) -> &mut Self {
+        self.read = read;
+        self
+    }
+
+    /// Sets whether missing parent directories are created (recursively)
+    /// before the file, if the file may be created. Defaults to `false`.
+    #[cfg(feature = "std")]
+    pub fn create_dirs(&mut self, create_dirs: bool) -> &mut Self {
+        self.create_dirs = create_dirs;
+        self
+    }
+
+    /// Sets the permissions of parent directories created through
+    /// [`Self::create_dirs`], before the umask is applied. Defaults to
+    /// `0o777`.
+    #[cfg(all(unix, feature = "std"))]
+    pub fn dir_mode(&mut self, mode: u32) -> &mut Self {
+        self.dir_mode = Some(mode);
+        self
+    }
+
+    /// Sets whether a symbolic link at the path is followed. When unset,
+    /// opening fails if the path itself is a symbolic link (`O_NOFOLLOW` on
+    /// Unix, any reparse point on Windows), defending against links
+    /// planted in shared directories such as `/tmp`. Links in parent
+    /// directories are still followed. Defaults to `true`.
+    pub fn follow_symlinks(&mut self, follow: bool) -> &mut Self {
+        self.follow_symlinks = follow;
+        self
+    }
+
+    /// Sets the permissions of the file if created by this call. They are
+    /// applied with `fchmod` after creation, so they are not restricted by
+    /// the umask, allowing group-writable or private lock files. By default,
+    /// files are created with `0o644` restricted by the umask.
+    #[cfg(unix)]
+    pub fn mode(&mut self, mode: u32) -> &mut Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Sets the sharing mode of the file (`dwShareMode` of `CreateFileW`),
+    /// controlling how others may open it while this handle is open. Other
+    /// handles locking the file need at least `FILE_SHARE_WRITE`. Defaults to
+    /// `FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE`.
+    #[cfg(windows)]
+    pub fn share_mode(&mut self, share_mode: u32) -> &mut Self {
+        self.share_mode = share_mode;
+        self
+    }
+
+    /// Sets the `SECURITY_ATTRIBUTES` passed to `CreateFileW`, such as a
+    /// security descriptor granting access to service accounts. A null
+    /// pointer restores the default, which creates handles not inherited by
+    /// child processes and files with the default security descriptor.
+    ///
+    /// # Safety
+    /// The pointer must be null or point to a valid `SECURITY_ATTRIBUTES`
+    /// structure, which must stay valid while these options are used to open
+    /// files, and while handles opened with them are in use, since they may
+    /// reopen their file, such as when it was replaced.
+    #[cfg(windows)]
+    pub unsafe fn security_attributes(
+        &mut self,
+        attributes: *mut core::ffi::c_void,
+    ) -> &mut Self {
+        self.security_attributes = attributes as usize;
+        self
+    }
+
+    /// Sets whether PIDs written by [`LockFile::lock_with_pid`] and its
+    /// variants are flushed to the disk (`fdatasync` on Linux, `fsync` on
+    /// other Unix systems, `FlushFileBuffers` on Windows), so they survive
+    /// crashes and power losses. Defaults to `false`.
+    pub fn sync(&mut self, sync: bool) -> &mut Self {
+        self.sync = sync;
+        self
+    }
+
+    /// Sets whether PIDs written by [`LockFile::lock_with_pid`] and its
+    /// variants are followed by a `start=` line with the start time of the
+    /// process and a `boot=` line identifying the current boot of the machine,
+    /// where available (currently on Linux and Windows). Staleness checks such
+    /// as [`LockFile::is_stale`] treat PIDs written before a reboot as stale,
+    /// and compare the start time with the one of the process currently using
+    /// the PID, so a recycled PID is not mistaken for the owner. Defaults to
+    /// `false`.
+    #[cfg(feature = "std")]
+    pub fn record_start_time(&mut self, record: bool) -> &mut Self {
+        self.record_start_time = record;
+        self
+    }
+
+    /// Sets whether handles append a line to `<lock>.history` (the path of
+    /// the lock file followed by `.history`) whenever they acquire or release
+    /// a lock, while still owning it, such as to keep an audit trail of who
+    /// held shared resources and when. Lines hold an ISO 8601 timestamp in
+    /// UTC, the PID, the host name and the action (`acquire-exclusive`,
+    /// `acquire-shared` or `release`), e.g.
+    /// `2021-03-14T15:09:26Z pid=1234 host=build-01 action=acquire-exclusive`.
+    /// They are flushed to the disk if [`LockFileOptions::sync`] is set.
+    /// Failures to append do not fail locking nor unlocking. Defaults to
+    /// `false`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFileOptions;
+    ///
+    /// let mut file = LockFileOptions::new()
+    ///     .audit_log(true)
+    ///     .open("testfiles/audit_log.lock")?;
+    /// file.lock()?;
+    /// do_stuff();
+    /// file.unlock()?;
+    /// let path = "testfiles/audit_log.lock.history";
+    /// let history = std::fs::read_to_string(path)?;
+    /// assert!(history.ends_with("action=release\n"));
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn audit_log(&mut self, audit: bool) -> &mut Self {
+        self.audit_log = audit;
+        self
+    }
+
+    /// Sets whether the parent directory is flushed to the disk when the file
+    /// is created, so the file is guaranteed to exist after a crash (`fsync`
+    /// of the directory on Unix, while NTFS already journals it on Windows).
+    /// Defaults to `false`.
+    pub fn sync_dir(&mut self, sync_dir: bool) -> &mut Self {
+        self.sync_dir = sync_dir;
+        self
+    }
+
+    /// Sets whether the path of the opened handle, reported by
+    /// [`LockFile::path`], is canonicalized: made absolute, with `.`, `..`
+    /// and symbolic links resolved (`realpath` on Unix,
+    /// `GetFinalPathNameByHandleW` on Windows). The path is then unaffected by
+    /// later changes of the working directory, and every path to the same file
+    /// yields the same one, which matters to features working through the
+    /// path, such as [`LockFile::set_heartbeat`] and
+    /// [`LockFile::lock_or_steal`]. Locks themselves apply to the file, however
+    /// it was named. If the path cannot be canonicalized, it is kept as given.
+    /// Defaults to `true`.
+    #[cfg(feature = "std")]
+    pub fn canonicalize(&mut self, canonicalize: bool) -> &mut Self {
+        self.canonicalize = canonicalize;
+        self
+    }
+
+    /// Sets whether PIDs written by [`LockFile::lock_with_pid`] and
+    /// [`LockFile::try_lock_with_pid`] are published atomically, so observers
+    /// never see an empty or partially written file: the PID is written into
+    /// a temporary file next to the lock file, which is locked and renamed
+    /// over it, and the handle then refers to the new file. Handles which
+    /// acquire a lock on a file replaced meanwhile reopen the path and try
+    /// again, so every process locking the file must set this option.
+    /// Defaults to `false`.
+    #[cfg(all(unix, feature = "std"))]
+    pub fn atomic_pid(&mut self, atomic: bool) -> &mut Self {
+        self.atomic_pid = atomic;
+        self
+    }
+
+    /// Sets whether the file is removed when its last holder unlocks it, so no
+    /// lock file is left behind. The file is only removed while still locked
+    /// and after checking that it is still the one at its path, and handles
+    /// which acquire a lock on a file removed meanwhile reopen the path and try
+    /// again, so no two handles ever own locks on different files of the same
+    /// path. Every process locking the file must thus set this option. Shared
+    /// holders only remove the file if no one else holds it. Defaults to
+    /// `false`.
+    #[cfg(all(unix, feature = "std"))]
+    pub fn remove_on_unlock(&mut self, remove: bool) -> &mut Self {
+        self.remove_on_unlock = remove;
+        self
+    }
+
+    /// Sets whether blocking locks ([`LockFile::lock`] and
+    /// [`LockFile::lock_shared`], and methods built on them) are retried when
+    /// interrupted by a signal (`EINTR`), such as `SIGCHLD` or `SIGWINCH`
+    /// delivered to a handler, instead of failing with
+    /// [`std::io::ErrorKind::Interrupted`]. Defaults to `true`.
+    #[cfg(unix)]
+    pub fn retry_interrupted(&mut self, retry: bool) -> &mut Self {
+        self.retry_interrupted = retry;
+        self
+    }
+
+    /// Sets [`LockFile::truncate_on_close`] of the opened handle. Defaults to
+    /// the value set by [`crate::lockfile_truncate`].
+    pub fn truncate_on_close(&mut self, truncate: bool) -> &mut Self {
+        self.truncate_on_close = Some(truncate);
+        self
+    }
+
+    /// Sets the backend locking the file, as in
+    /// [`LockFile::open_with_backend`].
+    pub fn backend(&mut self, backend: LockBackend) -> &mut Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Opens a file for locking with these options. On Unix, if the path is
+    /// nul-terminated (ends with 0), no extra allocation will be made.
+    ///
+    /// # Panics
+    /// Panics if the path contains a nul-byte in a place other than the end.
+    pub fn open<P>(&self, path: &P) -> Result<LockFile, Error>
+    where
+        P: ToOsStr + ?Sized,
+    {
+        let path = path.to_os_str()?.into_os_string()?;
+        #[cfg(feature = "std")]
+        if self.create_dirs && (self.create || self.create_new) {
+            self.create_parent(path.as_ref())?;
+        }
+        let (desc, created) = sys::open(path.as_ref(), self)
+            .map_err(|error| trace::open_failed(path.as_ref(), error))?;
+        #[cfg(feature = "std")]
+        let path = if self.canonicalize { canonical(path) } else { path };
+        let mut file = LockFile::new(path, desc);
+        file.created = created;
+        file.sync = self.sync;
+        #[cfg(unix)]
+        {
+            file.retry_interrupted = self.retry_interrupted;
+        }
+        #[cfg(feature = "std")]
+        {
+            file.record_start = self.record_start_time;
+            file.audit = self.audit_log;
+        }
+        #[cfg(all(unix, feature = "std"))]
+        {
+            file.atomic_pid = self.atomic_pid;
+            file.remove_on_unlock = self.remove_on_unlock;
+        }
+        if created && self.sync_dir {
+            sys::sync_parent(file.path())?;
+        }
+        file.backend = self.backend;
+        if let Some(truncate) = self.truncate_on_close {
+            file.truncate_on_close = truncate;
+        }
+        #[cfg(feature = "std")]
+        {
+            file.options = self.clone();
+        }
+        file.trace_opened();
+        Ok(file)
+    }
+
+    /// Creates the missing parent directories of the given path.
+    #[cfg(feature = "std")]
+    fn create_parent(&self, path: &crate::OsStr) -> Result<(), Error> {
+        let path = path.to_path_buf();
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => return Ok(()),
+        };
+        let mut builder = std::fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        if let Some(mode) = self.dir_mode {
+            use std::os::unix::fs::DirBuilderExt;
+            builder.mode(mode);
+        }
+        builder.create(parent)
+    }
+}
+
+impl Default for LockFileOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LockFile {
+    /// Returns the options this handle was opened with, reopening the path as
+    /// is without failing if the file exists, and with the settings changed
+    /// since, such as [`LockFile::truncate_on_close`].
+    #[cfg(feature = "std")]
+    pub(crate) fn same_options(&self) -> LockFileOptions {
+        let mut options = self.options.clone();
+        if options.create_new {
+            options.create(true);
+        }
+        options
+            .create_new(false)
+            .canonicalize(false)
+            .truncate_on_close(self.truncate_on_close);
+        #[cfg(unix)]
+        options.retry_interrupted(self.retry_interrupted);
+        options
+    }
+}
+
+/// Canonicalizes the path of an existing file, keeping it as is on failure.
+#[cfg(feature = "std")]
+fn canonical(path: crate::OsString) -> crate::OsString {
+    std::fs::canonicalize(path.to_path_buf())
+        .ok()
+        .and_then(|canonical| IntoOsString::into_os_string(canonical).ok())
+        .unwrap_or(path)
+}
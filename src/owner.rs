@@ -0,0 +1,361 @@
+//! This module implements queries about the owner of a lock file.
+
+use crate::{
+    retry::Backoff,
+    sys,
+    Error,
+    LockFile,
+    LockFileOptions,
+    LockInfo,
+    RetryPolicy,
+    ToOsStr,
+};
+use std::{
+    io::ErrorKind,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// A process owning a lock, as far as it could be found out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct LockOwner {
+    /// PID of the owner, reported by the kernel or, failing that, written
+    /// into the file by [`LockFile::lock_with_pid`].
+    pub pid: Option<u32>,
+    /// Name of the owner's executable, where available (currently on Linux).
+    pub name: Option<String>,
+}
+
+impl LockOwner {
+    /// Finds out the owner of the lock conflicting with the given handle.
+    pub(crate) fn of(file: &mut LockFile) -> Result<Self, Error> {
+        let pid = match file.blocking_owner()? {
+            Some(pid) => Some(pid),
+            None => LockInfo::parse(&file.read_contents()?)
+                .map(|recorded| recorded.pid),
+        };
+        Ok(Self { pid, name: pid.and_then(process_name) })
+    }
+}
+
+/// The result of [`LockFile::try_lock_detailed`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TryLockResult {
+    /// The lock was acquired by the handle.
+    Acquired,
+    /// The lock is owned by someone else.
+    HeldBy(LockOwner),
+}
+
+impl TryLockResult {
+    /// Whether the lock was acquired.
+    pub fn is_acquired(&self) -> bool {
+        matches!(self, Self::Acquired)
+    }
+}
+
+/// Whether the process with the given PID, owning a lock on a file with the
+/// given contents, no longer runs.
+pub(crate) fn owner_dead(pid: u32, contents: &[u8]) -> Result<bool, Error> {
+    match LockInfo::parse(contents) {
+        Some(recorded) if recorded.pid == pid => recorded.is_dead(),
+        _ => Ok(!sys::process_alive(pid)?),
+    }
+}
+
+/// Reads the contents of the file at the given path without locking it nor
+/// creating it, returning `None` if it does not exist.
+pub(crate) fn peek_contents<P>(path: &P) -> Result<Option<Vec<u8>>, Error>
+where
+    P: ToOsStr + ?Sized,
+{
+    let mut options = LockFileOptions::new();
+    options.create(false).truncate_on_close(false);
+    options.write = false;
+    match options.open(path) {
+        Ok(mut file) => file.read_contents().map(Some),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// Looks up the name of the process with the given PID.
+#[cfg(target_os = "linux")]
+fn process_name(pid: u32) -> Option<String> {
+    let name = std::fs::read_to_string(format!("/proc/{}/comm", pid)).ok()?;
+    Some(name.trim_end().to_owned())
+}
+
+/// Looks up the name of the process with the given PID.
+#[cfg(not(target_os = "linux"))]
+fn process_name(_pid: u32) -> Option<String> {
+    None
+}
+
+impl LockFile {
+    /// Reads the PID written by [`LockFile::lock_with_pid`] into the file at
+    /// the given path, without locking it nor creating it. Returns `None` if
+    /// the file does not exist or does not start with a PID, such as when it
+    /// is not locked. If the file is not truncated on close, the PID may
+    /// belong to a previous owner.
+    ///
+    /// # Panics
+    /// Panics if the path contains a nul-byte in a place other than the end.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let path = "testfiles/peek_owner_pid.lock";
+    /// let mut file = LockFile::open(path)?;
+    /// file.lock_with_pid()?;
+    /// assert_eq!(LockFile::peek_owner_pid(path)?, Some(std::process::id()));
+    /// file.unlock()?;
+    /// assert_eq!(LockFile::peek_owner_pid(path)?, None);
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn peek_owner_pid<P>(path: &P) -> Result<Option<u32>, Error>
+    where
+        P: ToOsStr + ?Sized,
+    {
+        let recorded =
+            peek_contents(path)?.and_then(|file| LockInfo::parse(&file));
+        Ok(recorded.map(|recorded| recorded.pid))
+    }
+
+    /// Tells whether this file is stale: it holds the PID written by
+    /// [`LockFile::lock_with_pid`] of a process which no longer exists, left
+    /// behind because the file was not truncated. The lock itself was released
+    /// by the kernel when the process died. If the start time of the owner was
+    /// recorded (see [`LockFileOptions::record_start_time`]), a PID written
+    /// before a reboot or recycled by another process is also stale. Files
+    /// without a PID are not stale.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/is_stale.lock")?;
+    /// file.lock_with_pid()?;
+    /// assert!(!file.is_stale()?);
+    /// file.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn is_stale(&mut self) -> Result<bool, Error> {
+        match LockInfo::parse(&self.read_contents()?) {
+            Some(recorded) => recorded.is_dead(),
+            None => Ok(false),
+        }
+    }
+
+    /// Like [`LockFile::is_stale`], but for the file at the given path, which
+    /// is neither locked nor created. Missing files are not stale.
+    ///
+    /// # Panics
+    /// Panics if the path contains a nul-byte in a place other than the end.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// if LockFile::is_stale_at("testfiles/is_stale_at.lock")? {
+    ///     eprintln!("previous owner died");
+    /// }
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn is_stale_at<P>(path: &P) -> Result<bool, Error>
+    where
+        P: ToOsStr + ?Sized,
+    {
+        match peek_contents(path)?.and_then(|file| LockInfo::parse(&file)) {
+            Some(recorded) => recorded.is_dead(),
+            None => Ok(false),
+        }
+    }
+
+    /// Asks the kernel for the PID of a process owning a lock which prevents
+    /// this handle from locking the file, such as after
+    /// [`LockFile::try_lock`] fails. Unlike [`LockFile::peek_owner_pid`],
+    /// this does not rely on the contents of the file. Returns `None` if no
+    /// conflicting lock is found or the kernel does not track its owner:
+    /// owners are reported for the `fcntl` and `lockf` backends, and for
+    /// `flock` on Linux, but not for open file description locks nor on
+    /// Windows.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/blocking_owner.lock")?;
+    /// if !file.try_lock()? {
+    ///     match file.blocking_owner()? {
+    ///         Some(pid) => eprintln!("lock held by process {}", pid),
+    ///         None => eprintln!("lock held by an unknown process"),
+    ///     }
+    /// }
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn blocking_owner(&self) -> Result<Option<u32>, Error> {
+        debug_assert!(self.desc != sys::uninitialized_fd());
+        sys::lock_owner(self.desc, self.backend)
+    }
+
+    /// Like [`LockFile::try_lock`], but tells who owns the lock if it could
+    /// not be acquired, as found out by [`LockFile::blocking_owner`] or,
+    /// failing that, from the PID written into the file.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns the file.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::{LockFile, TryLockResult};
+    ///
+    /// let mut file = LockFile::open("testfiles/try_lock_detailed.lock")?;
+    /// match file.try_lock_detailed()? {
+    ///     TryLockResult::Acquired => {
+    ///         do_stuff();
+    ///         file.unlock()?;
+    ///     },
+    ///     TryLockResult::HeldBy(owner) => {
+    ///         eprintln!("lock held by {:?} ({:?})", owner.pid, owner.name);
+    ///     },
+    /// }
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    pub fn try_lock_detailed(&mut self) -> Result<TryLockResult, Error> {
+        if self.try_lock()? {
+            Ok(TryLockResult::Acquired)
+        } else {
+            Ok(TryLockResult::HeldBy(LockOwner::of(self)?))
+        }
+    }
+
+    /// Blocks until the process owning the lock which prevents this handle
+    /// from locking the file exits, as found out by [`LockOwner`]. Returns
+    /// whether an owner was found. On Linux 5.3 and later, the owner is
+    /// waited on through a pidfd rather than polled, so a subsequent
+    /// [`LockFile::try_lock`] may succeed as soon as the owner is gone.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[cfg(not(target_os = "linux"))]
+    /// # fn main() {}
+    /// # #[cfg(target_os = "linux")]
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/wait_for_owner_exit.lock")?;
+    /// while !file.try_lock()? {
+    ///     if !file.wait_for_owner_exit()? {
+    ///         file.lock()?;
+    ///         break;
+    ///     }
+    /// }
+    /// do_stuff();
+    /// file.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    #[cfg(target_os = "linux")]
+    pub fn wait_for_owner_exit(&mut self) -> Result<bool, Error> {
+        match LockOwner::of(self)?.pid {
+            Some(pid) => sys::wait_process_exit(pid).map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    /// Locks this file like [`LockFile::lock`], but if the lock cannot be
+    /// acquired immediately, first calls the given callback with its owner, as
+    /// found out by [`LockFile::try_lock_detailed`], such as to tell users who
+    /// they are waiting for rather than freezing silently. Without an
+    /// interval, the callback is called once before blocking. With one, it is
+    /// called again after every interval spent waiting, with the owner at that
+    /// time, and attempts are retried every few milliseconds.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns the file.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    /// use std::time::Duration;
+    ///
+    /// let mut file = LockFile::open("testfiles/lock_on_contention.lock")?;
+    /// let interval = Some(Duration::from_secs(5));
+    /// file.lock_on_contention(interval, |owner| match owner.pid {
+    ///     Some(pid) => eprintln!("Waiting for process {} to release it", pid),
+    ///     None => eprintln!("Waiting for the lock to be released"),
+    /// })?;
+    /// do_stuff();
+    /// file.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    pub fn lock_on_contention<F>(
+        &mut self,
+        interval: Option<Duration>,
+        mut callback: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(&LockOwner),
+    {
+        let TryLockResult::HeldBy(owner) = self.try_lock_detailed()? else {
+            return Ok(());
+        };
+        callback(&owner);
+        let Some(interval) = interval else {
+            return self.lock();
+        };
+        self.waiting(|file| {
+            let policy = RetryPolicy::default();
+            let mut backoff = Backoff::new(&policy);
+            let mut notified = Instant::now();
+            loop {
+                thread::sleep(backoff.next_delay().unwrap_or(policy.max_delay));
+                if file.try_lock()? {
+                    return Ok(());
+                }
+                if notified.elapsed() >= interval {
+                    callback(&LockOwner::of(file)?);
+                    notified = Instant::now();
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,274 @@
+//! This module implements an opt-in panic hook poisoning lock files: handles
+//! registered through [`LockFile::poison_on_panic`] which own an exclusive lock
+//! when the thread which acquired it panics get a `poisoned` line appended to
+//! their file, so the next owner can tell the protected state may be
+//! inconsistent. Panics of other threads leave them alone, even if caught.
+//!
+//! Poisoned handles neither truncate nor remove their file when unlocked, such
+//! as while unwinding, so the marker survives them. Like with
+//! [`std::sync::Mutex`], but across processes, acquirers check
+//! [`LockFile::is_poisoned`] and, once they repaired the state,
+//! [`LockFile::clear_poison`]. Until then, registered handles keep the marker
+//! when they write PIDs.
+
+use crate::{sys, Error, FileDesc, LockFile};
+use std::{
+    io::SeekFrom,
+    panic,
+    sync::{Mutex, Once},
+    thread::{self, ThreadId},
+};
+
+/// Line appended to poisoned lock files.
+pub(crate) const MARKER: &str = "poisoned";
+
+/// A handle owning an exclusive lock.
+struct Held {
+    /// Descriptor of the handle.
+    desc: usize,
+    /// Thread which acquired the lock.
+    thread: ThreadId,
+    /// Whether the marker was appended.
+    poisoned: bool,
+}
+
+/// Handles owning an exclusive lock.
+static HELD: Mutex<Vec<Held>> = Mutex::new(Vec::new());
+
+/// Installs the hook poisoning held lock files, which then calls the previous
+/// hook. Called implicitly on registration; calling it again has no effect.
+pub fn install() {
+    static INSTALLED: Once = Once::new();
+    INSTALLED.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            poison_all();
+            previous(info);
+        }));
+    });
+}
+
+/// Appends the marker to every lock file held by the current thread. Called
+/// from the panic hook.
+fn poison_all() {
+    let current = thread::current().id();
+    let mut held = HELD.lock().unwrap_or_else(|error| error.into_inner());
+    for entry in held
+        .iter_mut()
+        .filter(|entry| entry.thread == current && !entry.poisoned)
+    {
+        entry.poisoned = mark(entry.desc as FileDesc).is_ok();
+    }
+}
+
+/// Appends the marker to the file referenced by the given descriptor.
+fn mark(desc: FileDesc) -> Result<(), Error> {
+    sys::seek_end(desc)?;
+    sys::write(desc, format!("{}\n", MARKER).as_bytes())
+}
+
+/// Records whether the handle with the given descriptor owns an exclusive
+/// lock, acquired by the current thread.
+pub(crate) fn set_held(desc: FileDesc, held: bool) {
+    let desc = desc as usize;
+    let mut entries = HELD.lock().unwrap_or_else(|error| error.into_inner());
+    let index = entries.iter().position(|entry| entry.desc == desc);
+    match (index, held) {
+        (None, true) => entries.push(Held {
+            desc,
+            thread: thread::current().id(),
+            poisoned: false,
+        }),
+        (Some(index), false) => {
+            entries.swap_remove(index);
+        },
+        _ => {},
+    }
+}
+
+/// Records that the file of the handle with the given descriptor is no longer
+/// poisoned.
+fn clear_held(desc: FileDesc) {
+    let desc = desc as usize;
+    let mut entries = HELD.lock().unwrap_or_else(|error| error.into_inner());
+    for entry in entries.iter_mut().filter(|entry| entry.desc == desc) {
+        entry.poisoned = false;
+    }
+}
+
+/// Returns whether the handle with the given descriptor was poisoned.
+pub(crate) fn is_held_poisoned(desc: FileDesc) -> bool {
+    let desc = desc as usize;
+    HELD.lock()
+        .unwrap_or_else(|error| error.into_inner())
+        .iter()
+        .any(|entry| entry.desc == desc && entry.poisoned)
+}
+
+impl LockFile {
+    /// Sets whether this handle is poisoned if the thread which acquired its
+    /// exclusive lock panics while it owns it, installing the panic hook if
+    /// needed. The hook appends a `poisoned` line to the file, after the PID
+    /// if any, and this handle then neither truncates nor removes the file
+    /// when unlocked. While set, writing PIDs keeps the marker of poisoned
+    /// files. Defaults to `false`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/poison_on_panic.lock")?;
+    /// file.poison_on_panic(true);
+    /// file.lock_with_pid()?;
+    /// do_stuff();
+    /// file.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    pub fn poison_on_panic(&mut self, poison: bool) {
+        if poison {
+            install();
+        }
+        self.set_poison_held(false);
+        self.poison = poison;
+        self.set_poison_held(self.locked && !self.shared);
+    }
+
+    /// Returns whether this file holds the marker appended by the panic hook
+    /// (see [`LockFile::poison_on_panic`]) when a previous owner panicked, in
+    /// which case the protected state may be inconsistent. Like
+    /// [`LockFile::read_contents`], the file must have been opened for
+    /// reading.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/is_poisoned.lock")?;
+    /// file.poison_on_panic(true);
+    /// file.lock_with_pid()?;
+    /// if file.is_poisoned()? {
+    ///     repair_stuff();
+    ///     file.clear_poison()?;
+    /// }
+    /// do_stuff();
+    /// file.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn repair_stuff() {
+    /// #    // repairing stuff here.
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    pub fn is_poisoned(&mut self) -> Result<bool, Error> {
+        let contents = self.read_to_end()?;
+        Ok(contents
+            .split(|&byte| byte == b'\n')
+            .any(|line| line == MARKER.as_bytes()))
+    }
+
+    /// Removes the marker appended by the panic hook from this file, once the
+    /// protected state was repaired, keeping the rest of the contents. The
+    /// file is then truncated and removed on unlock as configured.
+    ///
+    /// # Panics
+    /// Panics if this handle does not own an exclusive lock.
+    ///
+    /// # Panicking Example
+    ///
+    /// ```should_panic
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/clear_poison_panic.lock")?;
+    /// file.clear_poison()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn clear_poison(&mut self) -> Result<(), Error> {
+        if !self.locked || self.shared {
+            panic!("Cannot clear poison without owning an exclusive lock");
+        }
+        let contents = self.read_to_end()?;
+        let mut kept = Vec::with_capacity(contents.len());
+        for line in contents.split_inclusive(|&byte| byte == b'\n') {
+            if line.strip_suffix(b"\n").unwrap_or(line) != MARKER.as_bytes() {
+                kept.extend_from_slice(line);
+            }
+        }
+        sys::truncate(self.desc)?;
+        sys::write(self.desc, &kept)?;
+        if self.sync {
+            sys::sync_data(self.desc)?;
+        }
+        clear_held(self.desc);
+        Ok(())
+    }
+
+    /// Reads the contents of this file up to its current length, keeping the
+    /// current position. Unlike [`LockFile::read_contents`], this terminates on
+    /// devices producing endless data, such as `/dev/full`.
+    fn read_to_end(&mut self) -> Result<Vec<u8>, Error> {
+        let position = sys::seek(self.desc, SeekFrom::Current(0))?;
+        let len = sys::seek(self.desc, SeekFrom::End(0));
+        sys::seek(self.desc, SeekFrom::Start(position))?;
+        let mut contents = vec![0; len? as usize];
+        let mut start = 0;
+        while start < contents.len() {
+            let read =
+                sys::read_at(self.desc, &mut contents[start ..], start as u64)?;
+            if read == 0 {
+                break;
+            }
+            start += read;
+        }
+        contents.truncate(start);
+        Ok(contents)
+    }
+
+    /// Writes this process's PID like `publish_pid`, truncating the file first
+    /// if requested. Registered handles keep the marker of poisoned files,
+    /// which are then always truncated, unless opened without reading, in
+    /// which case they cannot tell and overwrite it.
+    pub(crate) fn publish_pid_keeping_poison(
+        &mut self,
+        truncate: bool,
+    ) -> Result<(), Error> {
+        let poisoned =
+            self.poison && self.options.read && self.is_poisoned()?;
+        if truncate || poisoned {
+            sys::truncate(self.desc)?;
+        }
+        self.publish_pid()?;
+        if poisoned {
+            mark(self.desc)?;
+        }
+        Ok(())
+    }
+
+    /// Records whether this handle owns an exclusive lock for the panic hook,
+    /// if registered.
+    pub(crate) fn set_poison_held(&self, held: bool) {
+        if self.poison {
+            set_held(self.desc, held);
+        }
+    }
+
+    /// Returns whether the panic hook poisoned the file while this handle
+    /// owned it.
+    pub(crate) fn poisoned(&self) -> bool {
+        self.poison && is_held_poisoned(self.desc)
+    }
+}
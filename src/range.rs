@@ -0,0 +1,179 @@
+//! This module implements locking byte ranges of a file.
+
+#[cfg(doc)]
+use crate::LockBackend;
+use crate::{sys, Error, LockFile};
+#[cfg(feature = "std")]
+use std::sync::Mutex;
+
+/// Ranges locked through each handle, by descriptor, offset and length.
+#[cfg(feature = "std")]
+static HELD: Mutex<Vec<(usize, u64, u64)>> = Mutex::new(Vec::new());
+
+/// A lockable byte range of a [`LockFile`], created by [`LockFile::range`].
+/// The range is automatically unlocked on drop.
+///
+/// Under the `fcntl`-based [`LockBackend`]s, including the default one on
+/// Linux, whole-file locks of [`LockFile`] cover the whole file with a range,
+/// so they exclude range locks; otherwise, range locks do not interact with
+/// whole-file locks. Distinct ranges of the same file may be owned
+/// by different handles at the same time. On Linux and Windows, range locks are
+/// held by the file handle, but on other Unix systems they are held by the
+/// whole process, so ranges of the same process never exclude each other there.
+/// Ranges cannot be locked while their handle owns a whole-file lock. With the
+/// feature `std`, overlapping ranges of the same handle exclude each other;
+/// without it, they must not be locked at once, since unlocking either
+/// unlocks the overlap.
+///
+/// # Example
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// use fslock::LockFile;
+///
+/// let file = LockFile::open("testfiles/shards.lock")?;
+/// let mut first = file.range(0, 4096);
+/// let mut second = file.range(4096, 4096);
+/// first.lock()?;
+/// second.lock()?;
+/// do_stuff();
+/// first.unlock()?;
+/// second.unlock()?;
+///
+/// # Ok(())
+/// # }
+/// # fn do_stuff() {
+/// #    // doing stuff here.
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct RangeLock<'file> {
+    file: &'file LockFile,
+    offset: u64,
+    len: u64,
+    locked: bool,
+}
+
+impl LockFile {
+    /// Creates a handle to the `len` bytes starting at `offset` in this file,
+    /// which can be locked independently of other ranges. The range may extend
+    /// past the end of the file.
+    ///
+    /// # Panics
+    /// Panics if `len` is zero.
+    pub fn range(&self, offset: u64, len: u64) -> RangeLock<'_> {
+        debug_assert!(self.desc != sys::uninitialized_fd());
+        if len == 0 {
+            panic!("Cannot lock an empty range");
+        }
+        RangeLock { file: self, offset, len, locked: false }
+    }
+}
+
+impl RangeLock<'_> {
+    /// Locks this range. Blocks while it is not possible to lock (i.e. someone
+    /// else already owns an overlapping range). After locked, if no attempt to
+    /// unlock is made, it will be automatically unlocked on drop. Fails with
+    /// `Deadlock` if the handle already owns an overlapping range.
+    ///
+    /// # Panics
+    /// Panics if this range is already locked, or if the handle owns a
+    /// whole-file lock.
+    pub fn lock(&mut self) -> Result<(), Error> {
+        if !self.claim() {
+            #[cfg(feature = "std")]
+            return Err(Error::new(
+                std::io::ErrorKind::Deadlock,
+                "overlapping range already locked by this handle",
+            ));
+        }
+        let lock_result =
+            sys::lock_range(self.file.desc, self.offset, self.len);
+        self.settle(lock_result.is_ok());
+        lock_result
+    }
+
+    /// Locks this range. Does NOT block if it is not possible to lock (i.e.
+    /// someone else, including another range of the handle, already owns an
+    /// overlapping range). After locked, if no attempt to unlock is made, it
+    /// will be automatically unlocked on drop.
+    ///
+    /// # Panics
+    /// Panics if this range is already locked, or if the handle owns a
+    /// whole-file lock.
+    pub fn try_lock(&mut self) -> Result<bool, Error> {
+        if !self.claim() {
+            return Ok(false);
+        }
+        let lock_result =
+            sys::try_lock_range(self.file.desc, self.offset, self.len);
+        self.settle(matches!(lock_result, Ok(true)));
+        lock_result
+    }
+
+    /// Returns whether this range is locked by this handle.
+    pub fn owns_lock(&self) -> bool {
+        self.locked
+    }
+
+    /// Unlocks this range. The file is never truncated.
+    ///
+    /// # Panics
+    /// Panics if this range is not locked.
+    pub fn unlock(&mut self) -> Result<(), Error> {
+        if !self.locked {
+            panic!("Attempted to unlock already locked lockfile");
+        }
+        self.settle(false);
+        sys::unlock_range(self.file.desc, self.offset, self.len)
+    }
+
+    /// Records this range as held by the handle before locking it, unless the
+    /// handle already holds an overlapping range.
+    fn claim(&self) -> bool {
+        if self.locked {
+            panic!("Cannot lock if already owning a lock");
+        }
+        if self.file.locked {
+            panic!("Cannot lock a range while owning the whole file");
+        }
+        #[cfg(feature = "std")]
+        {
+            let desc = self.file.desc as usize;
+            let mut held =
+                HELD.lock().unwrap_or_else(|error| error.into_inner());
+            let end = self.offset.saturating_add(self.len);
+            if held.iter().any(|&(held_desc, offset, len)| {
+                held_desc == desc
+                    && offset < end
+                    && self.offset < offset.saturating_add(len)
+            }) {
+                return false;
+            }
+            held.push((desc, self.offset, self.len));
+        }
+        true
+    }
+
+    /// Records whether this range is locked, forgetting it as held by the
+    /// handle otherwise.
+    fn settle(&mut self, locked: bool) {
+        self.locked = locked;
+        #[cfg(feature = "std")]
+        if !locked {
+            let range = (self.file.desc as usize, self.offset, self.len);
+            let mut held =
+                HELD.lock().unwrap_or_else(|error| error.into_inner());
+            if let Some(index) = held.iter().position(|&entry| entry == range) {
+                held.swap_remove(index);
+            }
+        }
+    }
+}
+
+impl Drop for RangeLock<'_> {
+    fn drop(&mut self) {
+        if self.locked {
+            let _ = self.unlock();
+        }
+    }
+}
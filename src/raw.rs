@@ -0,0 +1,106 @@
+//! This module implements locking any open file through its raw descriptor
+//! (`AsRawFd` on Unix, `AsRawHandle` on Windows), such as files of memory maps
+//! or temporary files obtained from other crates.
+//!
+//! Locks are applied with `flock(2)` on Unix, whatever the access mode of the
+//! file, and `LockFileEx` on Windows, so they are held by the open file and
+//! released when it is closed, like with [`crate::FileExt`]. Descriptors must
+//! refer to files: sockets and other descriptors cannot be locked (on Windows,
+//! sockets do not implement `AsRawHandle` at all).
+//!
+//! # Example
+//! ```
+//! # fn main() -> Result<(), fslock::Error> {
+//! use fslock::raw;
+//! use std::fs::File;
+//!
+//! let file = File::create("testfiles/raw.dat")?;
+//! raw::lock_exclusive(&file)?;
+//! do_stuff();
+//! raw::unlock(&file)?;
+//!
+//! # Ok(())
+//! # }
+//! # fn do_stuff() {
+//! #    // doing stuff here.
+//! # }
+//! ```
+
+use crate::{sys, Error, LockBackend};
+
+/// Types owning a raw descriptor which may be locked.
+#[cfg(unix)]
+pub use std::os::unix::io::AsRawFd as AsRawDesc;
+/// Types owning a raw descriptor which may be locked.
+#[cfg(windows)]
+pub use std::os::windows::io::AsRawHandle as AsRawDesc;
+
+/// Backend locking files, which on Unix must not depend on the access mode.
+#[cfg(unix)]
+const BACKEND: LockBackend = LockBackend::Flock;
+
+/// Backend locking files.
+#[cfg(windows)]
+const BACKEND: LockBackend = LockBackend::LockFileEx;
+
+/// Returns the descriptor of the given file.
+#[cfg(unix)]
+fn desc<D>(file: &D) -> sys::FileDesc
+where
+    D: AsRawDesc + ?Sized,
+{
+    file.as_raw_fd()
+}
+
+/// Returns the descriptor of the given file.
+#[cfg(windows)]
+fn desc<D>(file: &D) -> sys::FileDesc
+where
+    D: AsRawDesc + ?Sized,
+{
+    file.as_raw_handle().cast()
+}
+
+/// Locks the given file exclusively. Blocks while someone else owns a lock on
+/// it.
+pub fn lock_exclusive<D>(file: &D) -> Result<(), Error>
+where
+    D: AsRawDesc + ?Sized,
+{
+    sys::lock(desc(file), BACKEND)
+}
+
+/// Locks the given file exclusively, unless someone else owns a lock on it.
+/// Does NOT block. Returns whether the file was locked.
+pub fn try_lock_exclusive<D>(file: &D) -> Result<bool, Error>
+where
+    D: AsRawDesc + ?Sized,
+{
+    sys::try_lock(desc(file), BACKEND)
+}
+
+/// Locks the given file shared. Blocks while someone else owns an exclusive
+/// lock on it.
+pub fn lock_shared<D>(file: &D) -> Result<(), Error>
+where
+    D: AsRawDesc + ?Sized,
+{
+    sys::lock_shared(desc(file), BACKEND)
+}
+
+/// Locks the given file shared, unless someone else owns an exclusive lock on
+/// it. Does NOT block. Returns whether the file was locked.
+pub fn try_lock_shared<D>(file: &D) -> Result<bool, Error>
+where
+    D: AsRawDesc + ?Sized,
+{
+    sys::try_lock_shared(desc(file), BACKEND)
+}
+
+/// Unlocks the given file. Unlocking a file which is not locked succeeds.
+pub fn unlock<D>(file: &D) -> Result<(), Error>
+where
+    D: AsRawDesc + ?Sized,
+{
+    sys::unlock(desc(file), BACKEND)
+}
@@ -0,0 +1,139 @@
+//! This module implements a process-wide registry of locks held through
+//! backends whose locks are held by the process (`fcntl` and `lockf`), so that
+//! handles of the same process exclude each other like handles of different
+//! processes do. Locks of other backends are held by the handle and need no
+//! registry.
+
+#[cfg(all(unix, feature = "std"))]
+use crate::sys;
+use crate::{Error, LockFile};
+#[cfg(all(unix, feature = "std"))]
+use std::{
+    collections::HashMap,
+    sync::{Condvar, Mutex, MutexGuard},
+};
+
+/// Device and inode numbers identifying a file.
+#[cfg(all(unix, feature = "std"))]
+pub(crate) type FileId = (u64, u64);
+
+/// Locks held by handles of this process on a file.
+#[cfg(all(unix, feature = "std"))]
+#[derive(Debug, Default)]
+struct Entry {
+    /// Whether a handle holds an exclusive lock.
+    exclusive: bool,
+    /// Number of handles holding shared locks.
+    shared: usize,
+}
+
+/// Registered locks, keyed by file.
+#[cfg(all(unix, feature = "std"))]
+static REGISTRY: Mutex<Option<HashMap<FileId, Entry>>> = Mutex::new(None);
+
+/// Notified whenever a registered lock is released.
+#[cfg(all(unix, feature = "std"))]
+static RELEASED: Condvar = Condvar::new();
+
+/// Locks the registry, ignoring poisoning since entries are always consistent.
+#[cfg(all(unix, feature = "std"))]
+fn registry() -> MutexGuard<'static, Option<HashMap<FileId, Entry>>> {
+    REGISTRY.lock().unwrap_or_else(|error| error.into_inner())
+}
+
+impl LockFile {
+    /// Claims the file in the registry before locking it with a process-wide
+    /// backend, blocking while another handle of this process holds a
+    /// conflicting lock if `block` is set. Returns whether the file was
+    /// claimed, which it always is with other backends.
+    #[cfg(all(unix, feature = "std"))]
+    pub(crate) fn claim(
+        &mut self,
+        shared: bool,
+        block: bool,
+    ) -> Result<bool, Error> {
+        if !self.backend.is_process_wide() {
+            return Ok(true);
+        }
+        let id = sys::file_id(self.desc)?;
+        let mut registry = registry();
+        loop {
+            let entry = registry
+                .get_or_insert_with(HashMap::new)
+                .entry(id)
+                .or_default();
+            let conflicts = entry.exclusive || (!shared && entry.shared > 0);
+            if !conflicts {
+                if shared {
+                    entry.shared += 1;
+                } else {
+                    entry.exclusive = true;
+                }
+                self.claimed = Some(id);
+                return Ok(true);
+            }
+            if !block {
+                return Ok(false);
+            }
+            registry = RELEASED
+                .wait(registry)
+                .unwrap_or_else(|error| error.into_inner());
+        }
+    }
+
+    /// Releases the claim of this handle, if any. Returns whether the lock
+    /// should be released in the kernel, i.e. no other handle of this process
+    /// still holds a shared lock on the file.
+    #[cfg(all(unix, feature = "std"))]
+    pub(crate) fn unclaim(&mut self) -> bool {
+        let Some(id) = self.claimed.take() else {
+            return true;
+        };
+        let mut registry = registry();
+        let Some(entries) = registry.as_mut() else {
+            return true;
+        };
+        let Some(entry) = entries.get_mut(&id) else {
+            return true;
+        };
+        if entry.exclusive {
+            entry.exclusive = false;
+        } else {
+            entry.shared = entry.shared.saturating_sub(1);
+        }
+        let last = entry.shared == 0;
+        if last {
+            entries.remove(&id);
+        }
+        RELEASED.notify_all();
+        last
+    }
+
+    /// Whether no other handle of this process holds a shared lock on the file
+    /// on which this handle holds one, if any.
+    #[cfg(all(unix, feature = "std"))]
+    pub(crate) fn sole_claim(&self) -> bool {
+        let Some(id) = self.claimed else {
+            return true;
+        };
+        let registry = registry();
+        let entry = registry.as_ref().and_then(|entries| entries.get(&id));
+        entry.is_none_or(|entry| entry.shared <= 1)
+    }
+
+    /// Locks of handles are never shared with other handles here.
+    #[cfg(not(all(unix, feature = "std")))]
+    pub(crate) fn claim(
+        &mut self,
+        _shared: bool,
+        _block: bool,
+    ) -> Result<bool, Error> {
+        Ok(true)
+    }
+
+    /// Locks of handles are never shared with other handles here.
+    #[cfg(not(all(unix, feature = "std")))]
+    pub(crate) fn unclaim(&mut self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,181 @@
+//! This module implements retry policies for acquiring contended locks.
+
+use crate::{Error, LockFile};
+use std::{
+    collections::hash_map::{DefaultHasher, RandomState},
+    hash::{BuildHasher, Hasher},
+    thread,
+    time::Duration,
+};
+
+/// How to retry locking a contended file: the delay between attempts starts
+/// at `initial_delay` and is multiplied by `multiplier` after every attempt,
+/// up to `max_delay`. Each delay is then shortened by a random fraction of
+/// up to `jitter` of it, so contending processes spread their attempts.
+///
+/// # Example
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// use fslock::{LockFile, RetryPolicy};
+/// use std::time::Duration;
+///
+/// let policy = RetryPolicy {
+///     initial_delay: Duration::from_millis(10),
+///     max_attempts: Some(20),
+///     ..RetryPolicy::default()
+/// };
+/// let mut file = LockFile::open("testfiles/policy.lock")?;
+/// if file.lock_with_policy(&policy)? {
+///     do_stuff();
+///     file.unlock()?;
+/// }
+///
+/// # Ok(())
+/// # }
+/// # fn do_stuff() {
+/// #    // doing stuff here.
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Delay after the first failed attempt. Defaults to 1 millisecond.
+    pub initial_delay: Duration,
+    /// Factor applied to the delay after each failed attempt. Factors below 1,
+    /// and those which are not finite, count as 1. Defaults to 2.
+    pub multiplier: f64,
+    /// Maximum delay between attempts. Defaults to 50 milliseconds.
+    pub max_delay: Duration,
+    /// Maximum fraction, between 0 and 1, randomly cut from each delay. Values
+    /// which are not finite count as 0. Defaults to 0.2.
+    pub jitter: f64,
+    /// Maximum number of attempts, or `None` to retry until locked. Defaults
+    /// to `None`.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(1),
+            multiplier: 2.0,
+            max_delay: Duration::from_millis(50),
+            jitter: 0.2,
+            max_attempts: None,
+        }
+    }
+}
+
+/// State of a retry loop following a [`RetryPolicy`].
+#[derive(Debug)]
+pub(crate) struct Backoff<'policy> {
+    /// Policy being followed.
+    policy: &'policy RetryPolicy,
+    /// Delay before jitter of the next retry.
+    delay: Duration,
+    /// Attempts made so far.
+    attempts: u32,
+    /// Source of jitter.
+    hasher: DefaultHasher,
+}
+
+impl<'policy> Backoff<'policy> {
+    /// Starts a retry loop.
+    pub(crate) fn new(policy: &'policy RetryPolicy) -> Self {
+        Self {
+            policy,
+            delay: policy.initial_delay.min(policy.max_delay),
+            attempts: 0,
+            hasher: RandomState::new().build_hasher(),
+        }
+    }
+
+    /// Records a failed attempt, returning the delay until the next one, or
+    /// `None` if attempts are exhausted.
+    pub(crate) fn next_delay(&mut self) -> Option<Duration> {
+        self.attempts = self.attempts.saturating_add(1);
+        if self.policy.max_attempts.is_some_and(|max| self.attempts >= max) {
+            return None;
+        }
+
+        let delay = self.delay;
+        let multiplier = match self.policy.multiplier {
+            multiplier if multiplier.is_finite() => multiplier.max(1.0),
+            _ => 1.0,
+        };
+        self.delay =
+            Duration::try_from_secs_f64(delay.as_secs_f64() * multiplier)
+                .unwrap_or(self.policy.max_delay)
+                .min(self.policy.max_delay);
+
+        self.hasher.write_u32(self.attempts);
+        let random = (self.hasher.finish() >> 11) as f64 / (1u64 << 53) as f64;
+        let jitter = match self.policy.jitter {
+            jitter if jitter.is_finite() => jitter.clamp(0.0, 1.0),
+            _ => 0.0,
+        };
+        Some(delay.mul_f64(1.0 - jitter * random))
+    }
+}
+
+impl LockFile {
+    /// Locks this file, retrying while it is not possible to lock (i.e.
+    /// someone else already owns a lock) as configured by the given policy.
+    /// Returns whether the lock was acquired before attempts were exhausted.
+    /// After locked, if no attempt to unlock is made, it will be automatically
+    /// unlocked on the file handle drop.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns the file.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::{LockFile, RetryPolicy};
+    ///
+    /// let policy =
+    ///     RetryPolicy { max_attempts: Some(3), ..RetryPolicy::default() };
+    /// let mut file = LockFile::open("testfiles/lock_with_policy.lock")?;
+    /// if file.lock_with_policy(&policy)? {
+    ///     do_stuff();
+    ///     file.unlock()?;
+    /// }
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    ///
+    /// # Panicking Example
+    ///
+    /// ```should_panic
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::{LockFile, RetryPolicy};
+    ///
+    /// let mut file = LockFile::open("testfiles/lock_with_policy_panic.lock")?;
+    /// file.lock()?;
+    /// file.lock_with_policy(&RetryPolicy::default())?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn lock_with_policy(
+        &mut self,
+        policy: &RetryPolicy,
+    ) -> Result<bool, Error> {
+        self.waiting(|file| {
+            let mut backoff = Backoff::new(policy);
+            loop {
+                if file.try_lock()? {
+                    return Ok(true);
+                }
+                match backoff.next_delay() {
+                    Some(delay) => thread::sleep(delay),
+                    None => return Ok(false),
+                }
+            }
+        })
+    }
+}
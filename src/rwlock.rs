@@ -0,0 +1,133 @@
+//! This module implements a reader-writer lock across processes on top of
+//! shared and exclusive file locks.
+
+use crate::{Error, LockFile, ToOsStr};
+
+/// A file lock with multi-reader/single-writer semantics, mirroring
+/// `std::sync::RwLock` across processes: any number of handles may read at
+/// once, while a writer excludes everyone else. Unlike [`LockFile`], the file
+/// is never truncated on unlock, so it may be the protected file itself.
+///
+/// Like [`LockFile`], the lock is held by the handle, so acquiring it requires
+/// a mutable reference, and it is released when the returned guard is
+/// dropped.
+///
+/// # Example
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// use fslock::RwLockFile;
+///
+/// let mut reader = RwLockFile::open("testfiles/cache.lock")?;
+/// let mut other_reader = RwLockFile::open("testfiles/cache.lock")?;
+/// let mut writer = RwLockFile::open("testfiles/cache.lock")?;
+///
+/// let guard = reader.read()?;
+/// let other_guard = other_reader.read()?;
+/// assert!(writer.try_write()?.is_none());
+/// drop(guard);
+/// drop(other_guard);
+///
+/// let guard = writer.write()?;
+/// do_stuff();
+/// guard.unlock()?;
+///
+/// # Ok(())
+/// # }
+/// # fn do_stuff() {
+/// #    // doing stuff here.
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct RwLockFile {
+    file: LockFile,
+}
+
+impl RwLockFile {
+    /// Opens a file for reader-writer locking. On Unix, if the path is
+    /// nul-terminated (ends with 0), no extra allocation will be made.
+    ///
+    /// # Panics
+    /// Panics if the path contains a nul-byte in a place other than the end.
+    pub fn open<P>(path: &P) -> Result<Self, Error>
+    where
+        P: ToOsStr + ?Sized,
+    {
+        let mut file = LockFile::open(path)?;
+        file.truncate_on_close = false;
+        Ok(Self { file })
+    }
+
+    /// Locks this file for reading. Blocks while someone else owns the write
+    /// lock.
+    pub fn read(&mut self) -> Result<RwLockReadGuard<'_>, Error> {
+        self.file.lock_shared()?;
+        Ok(RwLockReadGuard { file: &mut self.file })
+    }
+
+    /// Locks this file for reading. Does NOT block if someone else owns the
+    /// write lock, returning `None` instead.
+    pub fn try_read(&mut self) -> Result<Option<RwLockReadGuard<'_>>, Error> {
+        if self.file.try_lock_shared()? {
+            Ok(Some(RwLockReadGuard { file: &mut self.file }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Locks this file for writing. Blocks while someone else owns either a
+    /// read or the write lock.
+    pub fn write(&mut self) -> Result<RwLockWriteGuard<'_>, Error> {
+        self.file.lock()?;
+        Ok(RwLockWriteGuard { file: &mut self.file })
+    }
+
+    /// Locks this file for writing. Does NOT block if someone else owns either
+    /// a read or the write lock, returning `None` instead.
+    pub fn try_write(&mut self) -> Result<Option<RwLockWriteGuard<'_>>, Error> {
+        if self.file.try_lock()? {
+            Ok(Some(RwLockWriteGuard { file: &mut self.file }))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// A read lock on a [`RwLockFile`], released on drop.
+#[derive(Debug)]
+pub struct RwLockReadGuard<'file> {
+    file: &'file mut LockFile,
+}
+
+impl RwLockReadGuard<'_> {
+    /// Releases the read lock, reporting errors that dropping would ignore.
+    pub fn unlock(self) -> Result<(), Error> {
+        let mut this = core::mem::ManuallyDrop::new(self);
+        this.file.unlock()
+    }
+}
+
+impl Drop for RwLockReadGuard<'_> {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+/// A write lock on a [`RwLockFile`], released on drop.
+#[derive(Debug)]
+pub struct RwLockWriteGuard<'file> {
+    file: &'file mut LockFile,
+}
+
+impl RwLockWriteGuard<'_> {
+    /// Releases the write lock, reporting errors that dropping would ignore.
+    pub fn unlock(self) -> Result<(), Error> {
+        let mut this = core::mem::ManuallyDrop::new(self);
+        this.file.unlock()
+    }
+}
+
+impl Drop for RwLockWriteGuard<'_> {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
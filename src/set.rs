@@ -0,0 +1,171 @@
+//! This module implements locking several files at once without deadlocks.
+
+use crate::{Error, LockFile, OsStr, ToOsStr};
+use std::path::PathBuf;
+
+/// A set of lock files locked and unlocked together. Files are locked in a
+/// canonical order (of their canonicalized paths), so processes locking
+/// overlapping sets never wait on each other in a cycle, whatever the order in
+/// which they listed the paths. Paths naming the same file are locked once.
+///
+/// # Example
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// use fslock::LockSet;
+///
+/// let paths = ["testfiles/set_b.lock", "testfiles/set_a.lock"];
+/// let mut set = LockSet::open(&paths)?;
+/// set.lock()?;
+/// do_stuff();
+/// set.unlock()?;
+///
+/// # Ok(())
+/// # }
+/// # fn do_stuff() {
+/// #    // doing stuff here.
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct LockSet {
+    /// Files in canonical order.
+    files: Vec<LockFile>,
+    /// Whether this set owns the locks of all of its files.
+    locked: bool,
+}
+
+impl LockSet {
+    /// Opens the files at the given paths, creating them if needed.
+    ///
+    /// # Panics
+    /// Panics if a path contains a nul-byte in a place other than the end.
+    pub fn open<P>(paths: &[&P]) -> Result<Self, Error>
+    where
+        P: ToOsStr + ?Sized,
+    {
+        let mut files = Vec::<(PathBuf, LockFile)>::with_capacity(paths.len());
+        for path in paths {
+            let file = LockFile::open(*path)?;
+            files.push((file.path().to_path_buf(), file));
+        }
+        files.sort_by(|(left, _), (right, _)| left.cmp(right));
+        files.dedup_by(|(left, _), (right, _)| left == right);
+        let files = files.into_iter().map(|(_, file)| file).collect();
+        Ok(Self { files, locked: false })
+    }
+
+    /// Returns the number of distinct files in this set.
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Returns whether this set has no files.
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Locks every file of this set, in canonical order. Blocks while it is
+    /// not possible to lock some file. If locking any file fails, the files
+    /// already locked are unlocked and the error is returned.
+    ///
+    /// # Panics
+    /// Panics if this set already owns the locks.
+    pub fn lock(&mut self) -> Result<(), Error> {
+        if self.locked {
+            panic!("Cannot lock if already owning a lock");
+        }
+        for index in 0 .. self.files.len() {
+            if let Err(error) = self.files[index].lock() {
+                self.rollback(index);
+                return Err(error);
+            }
+        }
+        self.locked = true;
+        Ok(())
+    }
+
+    /// Locks every file of this set, in canonical order, unless some file is
+    /// already locked by someone else, in which case the files already locked
+    /// are unlocked. Does NOT block. Returns whether all files were locked.
+    ///
+    /// # Panics
+    /// Panics if this set already owns the locks.
+    pub fn try_lock(&mut self) -> Result<bool, Error> {
+        Ok(self.try_lock_all()?.is_none())
+    }
+
+    /// Like [`LockSet::try_lock`], but if some file is already locked by
+    /// someone else, returns its path (see [`LockFile::path`]) after unlocking
+    /// the files already locked. Returns `None` if all files were
+    /// locked.
+    ///
+    /// # Panics
+    /// Panics if this set already owns the locks.
+    ///
+    /// # Example
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockSet;
+    ///
+    /// let paths = ["testfiles/all_a.lock", "testfiles/all_b.lock"];
+    /// let mut set = LockSet::open(&paths)?;
+    /// match set.try_lock_all()? {
+    ///     None => set.unlock()?,
+    ///     Some(path) => eprintln!("{} is locked", path),
+    /// }
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn try_lock_all(&mut self) -> Result<Option<&OsStr>, Error> {
+        if self.locked {
+            panic!("Cannot lock if already owning a lock");
+        }
+        for index in 0 .. self.files.len() {
+            match self.files[index].try_lock() {
+                Ok(true) => (),
+                Ok(false) => {
+                    self.rollback(index);
+                    return Ok(Some(self.files[index].path()));
+                },
+                Err(error) => {
+                    self.rollback(index);
+                    return Err(error);
+                },
+            }
+        }
+        self.locked = true;
+        Ok(None)
+    }
+
+    /// Unlocks the first files up to the given index, in reverse order.
+    fn rollback(&mut self, index: usize) {
+        for file in self.files[.. index].iter_mut().rev() {
+            let _ = file.unlock();
+        }
+    }
+
+    /// Returns whether this set owns the locks of all of its files.
+    pub fn owns_lock(&self) -> bool {
+        self.locked
+    }
+
+    /// Unlocks every file of this set, in reverse canonical order, reporting
+    /// the first error.
+    ///
+    /// # Panics
+    /// Panics if this set does not own the locks.
+    pub fn unlock(&mut self) -> Result<(), Error> {
+        if !self.locked {
+            panic!("Attempted to unlock already unlocked lockfile");
+        }
+        self.locked = false;
+        let mut result = Ok(());
+        for file in self.files.iter_mut().rev() {
+            let unlocked = file.unlock();
+            if result.is_ok() {
+                result = unlocked;
+            }
+        }
+        result
+    }
+}
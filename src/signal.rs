@@ -0,0 +1,283 @@
+//! This module implements an opt-in helper releasing lock files when the
+//! process is asked to terminate: `SIGINT` and `SIGTERM` on Unix, console
+//! control events on Windows.
+//!
+//! The kernel releases locks of dead processes by itself, but it does not
+//! truncate nor remove lock files, so a killed daemon would leave its PID
+//! behind. Handles registered through [`LockFile::release_on_signal`] or
+//! [`LockFile::remove_on_signal`] are truncated (if configured to), removed
+//! (if requested) and unlocked before the process terminates.
+//!
+//! Only requests which terminate the process release handles. On Unix, signals
+//! which the process ignores or already handles when the handlers are
+//! installed keep being ignored or handled as before, without releasing
+//! anything, since the process may keep running and still rely on its locks.
+//! On Windows, handlers run from the most recently installed, so handlers
+//! installed earlier must not keep the process running.
+//!
+//! With the opt-in feature `signals`, handles are registered automatically
+//! when they first lock exclusively, as if by [`LockFile::release_on_signal`],
+//! or by [`LockFile::remove_on_signal`] with their path for handles removing
+//! their file on unlock (see `LockFileOptions::remove_on_unlock` on Unix). This
+//! is best effort: handles beyond the capacity of the registry, or locked while
+//! the handlers cannot be installed, are not registered.
+
+use crate::{
+    sys,
+    Error,
+    FileDesc,
+    IntoOsString,
+    LockBackend,
+    LockFile,
+    OsString,
+    ToOsStr,
+};
+use core::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering},
+};
+
+/// Maximum number of simultaneously registered handles.
+const CAPACITY: usize = 64;
+
+/// The slot is not in use.
+const FREE: u8 = 0;
+/// The slot is being modified.
+const BUSY: u8 = 1;
+/// The slot holds a registered handle.
+const USED: u8 = 2;
+
+/// A registered handle.
+struct Slot {
+    /// One of [`FREE`], [`BUSY`] and [`USED`].
+    state: AtomicU8,
+    /// Descriptor of the handle.
+    desc: AtomicUsize,
+    /// Whether the handle currently owns the lock.
+    armed: AtomicBool,
+    /// Whether the file should be truncated.
+    truncate: AtomicBool,
+    /// Path to remove, only accessed by whoever moved the state out of
+    /// [`FREE`] or [`USED`].
+    path: UnsafeCell<Option<OsString>>,
+    /// Backend of the handle, accessed like the path.
+    backend: UnsafeCell<Option<LockBackend>>,
+}
+
+// Safe because the path is only accessed by whoever owns the slot's state.
+unsafe impl Sync for Slot {}
+
+impl Slot {
+    /// A free slot.
+    const fn new() -> Self {
+        Self {
+            state: AtomicU8::new(FREE),
+            desc: AtomicUsize::new(0),
+            armed: AtomicBool::new(false),
+            truncate: AtomicBool::new(false),
+            path: UnsafeCell::new(None),
+            backend: UnsafeCell::new(None),
+        }
+    }
+
+    /// Releases the handle of a slot in use, if it owns the lock.
+    fn release(&self) {
+        if self
+            .state
+            .compare_exchange(USED, BUSY, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return;
+        }
+        if self.armed.swap(false, Ordering::AcqRel) {
+            let desc = self.desc.load(Ordering::Relaxed) as FileDesc;
+            if self.truncate.load(Ordering::Relaxed) {
+                let _ = sys::truncate(desc);
+            }
+            if let Some(path) = unsafe { &*self.path.get() } {
+                let _ = sys::remove(path);
+            }
+            if let Some(backend) = unsafe { *self.backend.get() } {
+                let _ = sys::unlock(desc, backend);
+            }
+        }
+        self.state.store(USED, Ordering::Release);
+    }
+}
+
+/// Registered handles.
+static SLOTS: [Slot; CAPACITY] = [const { Slot::new() }; CAPACITY];
+
+/// Whether the handlers were installed.
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+/// Releases every registered handle. Called from the signal handler.
+fn release_all() {
+    for slot in &SLOTS {
+        slot.release();
+    }
+}
+
+/// Installs the handlers releasing registered lock files. Called implicitly on
+/// registration; calling it again has no effect.
+pub fn install() -> Result<(), Error> {
+    if INSTALLED.swap(true, Ordering::AcqRel) {
+        return Ok(());
+    }
+    let result = sys::on_termination(release_all);
+    if result.is_err() {
+        INSTALLED.store(false, Ordering::Release);
+    }
+    result
+}
+
+/// Occupies a free slot, returning its index.
+fn register(
+    desc: FileDesc,
+    backend: LockBackend,
+    armed: bool,
+    truncate: bool,
+    path: Option<OsString>,
+) -> Result<usize, Error> {
+    for (index, slot) in SLOTS.iter().enumerate() {
+        if slot
+            .state
+            .compare_exchange(FREE, BUSY, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            slot.desc.store(desc as usize, Ordering::Relaxed);
+            slot.armed.store(armed, Ordering::Relaxed);
+            slot.truncate.store(truncate, Ordering::Relaxed);
+            unsafe {
+                *slot.path.get() = path;
+                *slot.backend.get() = Some(backend);
+            }
+            slot.state.store(USED, Ordering::Release);
+            return Ok(index);
+        }
+    }
+    Err(sys::no_space())
+}
+
+/// Vacates the slot at the given index.
+pub(crate) fn unregister(index: usize) {
+    let slot = &SLOTS[index];
+    while slot
+        .state
+        .compare_exchange(USED, BUSY, Ordering::Acquire, Ordering::Relaxed)
+        .is_err()
+    {
+        core::hint::spin_loop();
+    }
+    slot.armed.store(false, Ordering::Relaxed);
+    let path = unsafe { (*slot.path.get()).take() };
+    slot.state.store(FREE, Ordering::Release);
+    drop(path);
+}
+
+/// Moves the registration at the given index to the given descriptor, which
+/// replaced the one of the handle.
+#[cfg(feature = "std")]
+pub(crate) fn set_desc(index: usize, desc: FileDesc) {
+    SLOTS[index].desc.store(desc as usize, Ordering::Release);
+}
+
+/// Records whether the handle registered at the given index owns the lock.
+pub(crate) fn set_armed(index: usize, armed: bool) {
+    SLOTS[index].armed.store(armed, Ordering::Release);
+}
+
+impl LockFile {
+    /// Registers this handle to be released if the process is asked to
+    /// terminate, installing the handlers if needed. If this handle owns an
+    /// exclusive lock at that time, the file is truncated (if
+    /// [`LockFile::truncate_on_close`] was set at registration) and unlocked,
+    /// unless the request is handled otherwise (see [the module
+    /// documentation](crate::signal)). The registration lasts until the handle
+    /// is dropped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let mut file = LockFile::open("testfiles/release_on_signal.lock")?;
+    /// file.release_on_signal()?;
+    /// file.lock_with_pid()?;
+    /// do_stuff();
+    /// file.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    pub fn release_on_signal(&mut self) -> Result<(), Error> {
+        self.register_signal(None)
+    }
+
+    /// Like [`LockFile::release_on_signal`], but also removes the file at the
+    /// given path (which should be the path of this lock file) before
+    /// unlocking it.
+    ///
+    /// # Panics
+    /// Panics if the path contains a nul-byte in a place other than the end.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let path = "testfiles/remove_on_signal.lock";
+    /// let mut file = LockFile::open(path)?;
+    /// file.remove_on_signal(path)?;
+    /// file.lock_with_pid()?;
+    /// do_stuff();
+    /// file.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    pub fn remove_on_signal<P>(&mut self, path: &P) -> Result<(), Error>
+    where
+        P: ToOsStr + ?Sized,
+    {
+        let path = path.to_os_str()?.into_os_string()?;
+        self.register_signal(Some(path))
+    }
+
+    /// Registers this handle, which just locked exclusively, ignoring
+    /// failures.
+    #[cfg(feature = "signals")]
+    pub(crate) fn register_automatically(&mut self) {
+        #[cfg(unix)]
+        let path = self.remove_on_unlock.then(|| self.path.clone());
+        #[cfg(windows)]
+        let path = None;
+        let _ = self.register_signal(path);
+    }
+
+    /// Registers this handle, replacing a previous registration.
+    fn register_signal(&mut self, path: Option<OsString>) -> Result<(), Error> {
+        debug_assert!(self.desc != sys::uninitialized_fd());
+        install()?;
+        let armed = self.locked && !self.shared;
+        let index = register(
+            self.desc,
+            self.backend,
+            armed,
+            self.truncate_on_close,
+            path,
+        )?;
+        if let Some(previous) = self.signal_slot.replace(index) {
+            unregister(previous);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,276 @@
+//! This module implements taking over locks left behind by dead processes.
+
+use crate::{
+    info::iso_timestamp,
+    lease::new_token,
+    owner::owner_dead,
+    sys,
+    Error,
+    LockFile,
+    LockFileOptions,
+    LockInfo,
+    LockOwner,
+    ToOsStr,
+};
+use std::{
+    fs,
+    io::{ErrorKind, Write},
+    path::PathBuf,
+    time::SystemTime,
+};
+
+/// How [`LockFile::lock_or_steal`] deals with stale locks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StalePolicy {
+    /// Waits for the lock like [`LockFile::lock`], then truncates the PID of
+    /// a dead previous owner. Locks are never broken.
+    Truncate,
+    /// Like [`StalePolicy::Truncate`], but if the lock is held while the PID
+    /// of its owner belongs to a dead process (e.g. the descriptor leaked into
+    /// a surviving child), removes the file and locks a new one in its place.
+    /// Handles still waiting on the removed file are not woken up, so every
+    /// process should lock the file through [`LockFile::lock_or_steal`].
+    /// Only supported on Unix: on Windows, open files cannot be replaced, so
+    /// this waits like [`StalePolicy::Truncate`].
+    Recreate,
+}
+
+/// The result of [`LockFile::lock_or_steal`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StealOutcome {
+    /// The lock was acquired without any stale owner.
+    Acquired,
+    /// The lock was acquired, and the PID of a dead previous owner was
+    /// truncated.
+    Recovered {
+        /// PID of the dead owner.
+        pid: u32,
+    },
+    /// The lock held on behalf of a dead owner was broken by recreating the
+    /// file, which this handle now refers to.
+    Stolen {
+        /// PID of the dead owner.
+        pid: u32,
+    },
+}
+
+/// What was known about the holder of a lock broken by
+/// [`LockFile::break_lock`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct BrokenLock {
+    /// Whether the lock was held when it was broken.
+    pub held: bool,
+    /// The process holding the lock, as far as it could be found out, if it
+    /// was held.
+    pub owner: Option<LockOwner>,
+    /// The record of the holder written into the file, if any, such as by
+    /// [`LockFile::lock_with_info`].
+    pub info: Option<LockInfo>,
+}
+
+impl LockFile {
+    /// Locks this file, blocking while it is not possible to lock, but taking
+    /// over stale locks according to the given policy. Stale PIDs written by
+    /// [`LockFile::lock_with_pid`] are truncated once the lock is acquired.
+    ///
+    /// Breaking locks ([`StalePolicy::Recreate`]) is serialized through a
+    /// companion lock file, whose path is this file's path followed by
+    /// `.steal`, and the file is only replaced after checking again under
+    /// that lock that it is still stale, so concurrent stealers never end up
+    /// owning different files.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns the file.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::{LockFile, StalePolicy, StealOutcome};
+    ///
+    /// let mut file = LockFile::open("testfiles/lock_or_steal.lock")?;
+    /// match file.lock_or_steal(StalePolicy::Recreate)? {
+    ///     StealOutcome::Acquired => (),
+    ///     StealOutcome::Recovered { pid } | StealOutcome::Stolen { pid } => {
+    ///         eprintln!("process {} died while owning the lock", pid);
+    ///     },
+    /// }
+    /// do_stuff();
+    /// file.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    pub fn lock_or_steal(
+        &mut self,
+        policy: StalePolicy,
+    ) -> Result<StealOutcome, Error> {
+        loop {
+            if self.try_lock()? {
+                return self.clear_stale();
+            }
+            let owner = LockOwner::of(self)?;
+            match owner.pid {
+                Some(pid)
+                    if cfg!(unix)
+                        && policy == StalePolicy::Recreate
+                        && owner_dead(pid, &self.read_contents()?)? =>
+                {
+                    if let Some(outcome) = self.steal(pid)? {
+                        return Ok(outcome);
+                    }
+                },
+                _ => {
+                    self.lock()?;
+                    return self.clear_stale();
+                },
+            }
+        }
+    }
+
+    /// Forcibly invalidates the lock on the file at the given path, for
+    /// administrative tools: the file is moved away and removed, whether
+    /// locked or not, and a new file is created in its place holding a
+    /// tombstone record of who broke the lock and when (`broken=`, `by=` and
+    /// `host=` lines). Handles opening the path afterwards lock the new file,
+    /// while the previous holder keeps a lock on the removed file, which no
+    /// longer excludes anyone; it can tell through [`LockFile::validate`].
+    /// Returns what was known about the previous holder.
+    ///
+    /// Breaking a lock whose holder is alive gives up mutual exclusion, so it
+    /// is only meant for holders known to be hung or gone. On Unix, `fcntl`
+    /// locks this process holds on the file are released.
+    ///
+    /// # Panics
+    /// Panics if the path contains a nul-byte in a place other than the end.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    ///
+    /// let path = "testfiles/break_lock.lock";
+    /// let mut file = LockFile::open(path)?;
+    /// file.lock_with_info()?;
+    /// let broken = LockFile::break_lock(path)?;
+    /// assert!(broken.held);
+    /// assert_eq!(broken.info.unwrap().pid, std::process::id());
+    /// assert!(!file.validate()?);
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn break_lock<P>(path: &P) -> Result<BrokenLock, Error>
+    where
+        P: ToOsStr + ?Sized,
+    {
+        let path = path.to_os_str()?.to_path_buf();
+        let mut options = LockFileOptions::new();
+        options.create(false).canonicalize(false).truncate_on_close(false);
+        let mut file = options.open(path.as_path())?;
+        let info = file.owner_info()?;
+        let held = !file.try_lock()?;
+        let owner = if held { Some(LockOwner::of(&mut file)?) } else { None };
+        drop(file);
+
+        // Files open elsewhere can only be removed once renamed on Windows.
+        let mut grave = path.clone().into_os_string();
+        grave.push(format!(".{}.broken", new_token()));
+        let grave = PathBuf::from(grave);
+        fs::rename(&path, &grave)?;
+        fs::remove_file(&grave)?;
+
+        match fs::OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut tombstone) => write!(
+                tombstone,
+                "broken={}\nby={}\nhost={}\n",
+                iso_timestamp(SystemTime::now()),
+                std::process::id(),
+                sys::hostname()?,
+            )?,
+            // Someone already locks a new file.
+            Err(error) if error.kind() == ErrorKind::AlreadyExists => (),
+            Err(error) => return Err(error),
+        }
+        Ok(BrokenLock { held, owner, info })
+    }
+
+    /// Truncates the PID of a dead previous owner of the lock, which this
+    /// handle now owns.
+    fn clear_stale(&mut self) -> Result<StealOutcome, Error> {
+        let stale = match LockInfo::parse(&self.read_contents()?) {
+            Some(recorded) if recorded.is_dead()? => recorded.pid,
+            _ => return Ok(StealOutcome::Acquired),
+        };
+        if let Err(error) = sys::truncate(self.desc) {
+            let _ = self.unlock();
+            return Err(error);
+        }
+        Ok(StealOutcome::Recovered { pid: stale })
+    }
+
+    /// Replaces the file whose lock is held on behalf of the given dead
+    /// process. Returns `None` if the file changed meanwhile, in which case
+    /// this handle is reopened and the caller should try again.
+    #[cfg(unix)]
+    fn steal(&mut self, pid: u32) -> Result<Option<StealOutcome>, Error> {
+        self.trace_steal(pid);
+        let mut guard_path = self.path().to_path_buf().into_os_string();
+        guard_path.push(".steal");
+        let mut guard = LockFile::open(guard_path.as_os_str())?;
+        guard.lock()?;
+
+        if !sys::same_file(self.desc, self.path())? {
+            self.reopen(false)?;
+            return Ok(None);
+        }
+        if self.try_lock()? {
+            return self.clear_stale().map(Some);
+        }
+        match LockOwner::of(self)?.pid {
+            Some(owner) if owner == pid => (),
+            _ => return Ok(None),
+        }
+
+        sys::remove(self.path())?;
+        match self.reopen(true) {
+            Ok(()) => (),
+            Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => {
+                self.reopen(false)?;
+                return Ok(None);
+            },
+            Err(error) => return Err(error),
+        }
+        if self.try_lock()? {
+            Ok(Some(StealOutcome::Stolen { pid }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Locks are never broken on Windows.
+    #[cfg(windows)]
+    fn steal(&mut self, _pid: u32) -> Result<Option<StealOutcome>, Error> {
+        Ok(None)
+    }
+
+    /// Replaces this handle with a new one for the same path and settings,
+    /// creating the file only if `create_new` is set.
+    #[cfg(unix)]
+    fn reopen(&mut self, create_new: bool) -> Result<(), Error> {
+        let mut options = self.same_options();
+        if create_new {
+            options.create_new(true);
+        } else {
+            options.create(false);
+        }
+        let file = options.open(self.path())?;
+        *self = file;
+        Ok(())
+    }
+}
@@ -86,7 +86,7 @@ impl<'str> IntoOsString for EitherOsStr<'str> {
 }
 
 #[cfg(feature = "std")]
-impl<'str> IntoOsString for &'str ffi::OsStr {
+impl IntoOsString for &ffi::OsStr {
     fn into_os_string(self) -> Result<OsString, Error> {
         self.to_os_str()?.into_os_string()
     }
@@ -100,7 +100,7 @@ impl IntoOsString for PathBuf {
 }
 
 #[cfg(feature = "std")]
-impl<'str> IntoOsString for &'str Path {
+impl IntoOsString for &Path {
     fn into_os_string(self) -> Result<OsString, Error> {
         AsRef::<ffi::OsStr>::as_ref(self).to_os_str()?.into_os_string()
     }
@@ -113,7 +113,7 @@ impl IntoOsString for ffi::OsString {
     }
 }
 
-impl<'str> IntoOsString for &'str str {
+impl IntoOsString for &str {
     fn into_os_string(self) -> Result<OsString, Error> {
         self.to_os_str()?.into_os_string()
     }
@@ -128,7 +128,7 @@ impl IntoOsString for String {
 
 #[cfg(feature = "std")]
 impl ToOsStr for String {
-    fn to_os_str(&self) -> Result<EitherOsStr, Error> {
+    fn to_os_str(&self) -> Result<EitherOsStr<'_>, Error> {
         (**self).to_os_str()
     }
 }
@@ -137,11 +137,11 @@ impl ToOsStr for String {
 /// allocation fails, an error shall be returned.
 pub trait ToOsStr {
     /// Converts with possible allocation error.
-    fn to_os_str(&self) -> Result<EitherOsStr, Error>;
+    fn to_os_str(&self) -> Result<EitherOsStr<'_>, Error>;
 }
 
 impl<'str> ToOsStr for EitherOsStr<'str> {
-    fn to_os_str(&self) -> Result<EitherOsStr, Error> {
+    fn to_os_str(&self) -> Result<EitherOsStr<'_>, Error> {
         Ok(match self {
             EitherOsStr::Owned(string) => {
                 EitherOsStr::Owned(string.to_os_str()?.into_os_string()?)
@@ -152,34 +152,34 @@ impl<'str> ToOsStr for EitherOsStr<'str> {
 }
 
 impl ToOsStr for OsStr {
-    fn to_os_str(&self) -> Result<EitherOsStr, Error> {
+    fn to_os_str(&self) -> Result<EitherOsStr<'_>, Error> {
         Ok(EitherOsStr::Borrowed(self))
     }
 }
 
 impl ToOsStr for OsString {
-    fn to_os_str(&self) -> Result<EitherOsStr, Error> {
+    fn to_os_str(&self) -> Result<EitherOsStr<'_>, Error> {
         Ok(EitherOsStr::Borrowed(self.as_ref()))
     }
 }
 
 #[cfg(feature = "std")]
 impl ToOsStr for ffi::OsString {
-    fn to_os_str(&self) -> Result<EitherOsStr, Error> {
+    fn to_os_str(&self) -> Result<EitherOsStr<'_>, Error> {
         (**self).to_os_str()
     }
 }
 
 #[cfg(feature = "std")]
 impl ToOsStr for PathBuf {
-    fn to_os_str(&self) -> Result<EitherOsStr, Error> {
+    fn to_os_str(&self) -> Result<EitherOsStr<'_>, Error> {
         (**self).to_os_str()
     }
 }
 
 #[cfg(feature = "std")]
 impl ToOsStr for Path {
-    fn to_os_str(&self) -> Result<EitherOsStr, Error> {
+    fn to_os_str(&self) -> Result<EitherOsStr<'_>, Error> {
         AsRef::<ffi::OsStr>::as_ref(self).to_os_str()
     }
 }
@@ -13,7 +13,7 @@ fn read_pid() -> Result<(), Error> {
     let content_a = read_to_string(path)?;
     let content_b = read_to_string(path)?;
 
-    assert!(content_a.trim().len() > 0);
+    assert!(!content_a.trim().is_empty());
     assert!(content_a.trim().chars().all(|ch| ch.is_ascii_digit()));
 
     assert_eq!(content_a, content_b);
@@ -32,7 +32,7 @@ fn try_read_pid() -> Result<(), Error> {
     let content_a = read_to_string(path)?;
     let content_b = read_to_string(path)?;
 
-    assert!(content_a.trim().len() > 0);
+    assert!(!content_a.trim().is_empty());
     assert!(content_a.trim().chars().all(|ch| ch.is_ascii_digit()));
 
     assert_eq!(content_a, content_b);
@@ -93,7 +93,7 @@ fn check_try_lock_with_pid_example(
     match expected {
         TryPidExpectedRes::Success { pid_to_differ: pid } => {
             let output = str::from_utf8(&output.stdout).unwrap();
-            assert!(output.trim().len() > 0);
+            assert!(!output.trim().is_empty());
             assert!(output.trim().chars().all(|ch| ch.is_ascii_digit()));
             assert_ne!(output.trim(), pid);
         },
@@ -126,18 +126,18 @@ fn other_process_pid() -> Result<(), Error> {
     assert!(file.try_lock_with_pid()?);
 
     let content = read_to_string(path)?;
-    assert!(content.trim().len() > 0);
+    assert!(!content.trim().is_empty());
     assert!(content.trim().chars().all(|ch| ch.is_ascii_digit()));
 
     check_try_lock_example(path, b"FAILURE\n")?;
     let content_again = read_to_string(path)?;
-    assert!(content_again.trim().len() > 0);
+    assert!(!content_again.trim().is_empty());
     assert!(content_again.trim().chars().all(|ch| ch.is_ascii_digit()));
     file.unlock()?;
     check_try_lock_example(path, b"SUCCESS\n")?;
 
     let child_content = read_to_string(path)?;
-    assert!(child_content.trim().len() == 0);
+    assert!(child_content.trim().is_empty());
 
     assert!(file.try_lock_with_pid()?);
 
@@ -146,7 +146,7 @@ fn other_process_pid() -> Result<(), Error> {
 
     check_try_lock_with_pid_example(path, TryPidExpectedRes::Failure)?;
     let content_again = read_to_string(path)?;
-    assert!(content_again.trim().len() > 0);
+    assert!(!content_again.trim().is_empty());
     assert!(content_again.trim().chars().all(|ch| ch.is_ascii_digit()));
     file.unlock()?;
     check_try_lock_with_pid_example(
@@ -155,7 +155,7 @@ fn other_process_pid() -> Result<(), Error> {
     )?;
 
     let child_content = read_to_string(path)?;
-    assert!(child_content.trim().len() == 0);
+    assert!(child_content.trim().is_empty());
 
     Ok(())
 }
@@ -177,3 +177,2566 @@ fn other_process_but_curr_reads() -> Result<(), Error> {
     check_try_lock_example(path, b"SUCCESS\n")?;
     Ok(())
 }
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    use std::{
+        pin::pin,
+        sync::Arc,
+        task::{Context, Poll, Wake},
+        thread,
+    };
+
+    struct Unpark(thread::Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark()
+        }
+    }
+
+    let waker = Arc::new(Unpark(thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => break output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+#[test]
+fn uring_lock_with_pid() -> Result<(), Error> {
+    use crate::uring::Ring;
+    use std::{fs::read_to_string, thread, time::Duration};
+
+    let ring = match Ring::new(8) {
+        Ok(ring) => ring,
+        Err(_) => return Ok(()),
+    };
+    let path = "testfiles/uring_lock_with_pid.lock";
+    let mut holder = LockFile::open(path)?;
+    holder.lock()?;
+
+    let releaser = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        holder.unlock()
+    });
+
+    block_on(async {
+        let mut file = ring.open(path).await?;
+        ring.lock_with_pid(&mut file, Duration::from_millis(5)).await?;
+        let content = read_to_string(path)?;
+        assert_eq!(content.trim(), std::process::id().to_string());
+        ring.close(file).await
+    })?;
+
+    releaser.join().unwrap()?;
+    assert!(read_to_string(path)?.is_empty());
+    Ok(())
+}
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+#[test]
+fn uring_close_removes_file() -> Result<(), Error> {
+    use crate::{uring::Ring, LockFileOptions};
+    use std::{path::Path, time::Duration};
+
+    let ring = match Ring::new(8) {
+        Ok(ring) => ring,
+        Err(_) => return Ok(()),
+    };
+    let path = "testfiles/uring_close_removes_file.lock";
+    let mut file = LockFileOptions::new().remove_on_unlock(true).open(path)?;
+    block_on(async {
+        ring.lock(&mut file, Duration::from_millis(10)).await?;
+        ring.close(file).await
+    })?;
+    assert!(!Path::new(path).exists());
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn pid_write_error_is_reported() -> Result<(), Error> {
+    let mut file = LockFile::open("/dev/full")?;
+    let error = file.lock_with_pid().unwrap_err();
+    assert_eq!(error.raw_os_error(), Some(libc::ENOSPC));
+    assert!(!file.owns_lock());
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn pid_written_to_unsyncable_file() -> Result<(), Error> {
+    // Character devices such as this one fail `fsync` and `ftruncate` with
+    // `EINVAL`.
+    let mut file = LockFile::open("/dev/null")?;
+    file.truncate_on_close = false;
+    file.lock_with_pid()?;
+    file.unlock()?;
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn removed_on_signal() -> Result<(), Error> {
+    use std::{
+        fs::read_to_string,
+        io::{BufRead, BufReader},
+        path::Path,
+        process::{Command, Stdio},
+    };
+
+    let path = "testfiles/removed_on_signal.lock";
+    let mut child = Command::new("cargo")
+        .arg("run")
+        .arg("-q")
+        .arg("--example")
+        .arg("remove_on_signal")
+        .arg("--")
+        .arg(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let mut line = String::new();
+    BufReader::new(child.stdout.take().unwrap()).read_line(&mut line)?;
+    assert_eq!(line, "LOCKED\n");
+
+    let pid: libc::pid_t = read_to_string(path)?.trim().parse().unwrap();
+    assert_eq!(unsafe { libc::kill(pid, libc::SIGTERM) }, 0);
+    assert!(!child.wait()?.success());
+    assert!(!Path::new(path).exists());
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn handled_signal_keeps_lock() -> Result<(), Error> {
+    use std::{
+        fs::read_to_string,
+        io::{BufRead, BufReader, Write},
+        path::Path,
+        process::{Command, Stdio},
+    };
+
+    let path = "testfiles/handled_signal_keeps_lock.lock";
+    let mut child = Command::new("cargo")
+        .arg("run")
+        .arg("-q")
+        .arg("--example")
+        .arg("signal_handled")
+        .arg("--")
+        .arg(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut line = String::new();
+    stdout.read_line(&mut line)?;
+    assert_eq!(line, "LOCKED\n");
+
+    // The handler of the process runs instead of releasing the lock.
+    let pid: libc::pid_t = read_to_string(path)?.trim().parse().unwrap();
+    assert_eq!(unsafe { libc::kill(pid, libc::SIGTERM) }, 0);
+    line.clear();
+    stdout.read_line(&mut line)?;
+    assert_eq!(line, "HANDLED\n");
+    assert!(Path::new(path).exists());
+    assert!(!LockFile::open(path)?.try_lock()?);
+
+    child.stdin.take().unwrap().write_all(b"\n")?;
+    assert!(child.wait()?.success());
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn append_locked_concurrently() -> Result<(), Error> {
+    use crate::append_locked;
+    use std::{fs, thread};
+
+    let path = "testfiles/append_locked_concurrently.log";
+    let _ = fs::remove_file(path);
+    let record = [b'x'; 4096];
+    let writers = (0 .. 4)
+        .map(|i| {
+            thread::spawn(move || -> Result<(), Error> {
+                let mut record = record.to_vec();
+                record[0] = b'0' + i;
+                record.push(b'\n');
+                for _ in 0 .. 16 {
+                    append_locked(path, &record)?;
+                }
+                Ok(())
+            })
+        })
+        .collect::<Vec<_>>();
+    for writer in writers {
+        writer.join().unwrap()?;
+    }
+
+    let content = fs::read_to_string(path)?;
+    assert_eq!(content.lines().count(), 64);
+    for line in content.lines() {
+        assert_eq!(line.len(), 4096);
+        assert!(line[1 ..].bytes().all(|byte| byte == b'x'));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn shared_excludes_exclusive() -> Result<(), Error> {
+    let path = "testfiles/shared_excludes_exclusive.lock";
+    let mut reader = LockFile::open(path)?;
+    let mut other_reader = LockFile::open(path)?;
+    let mut writer = LockFile::open(path)?;
+
+    reader.lock_shared()?;
+    assert!(other_reader.try_lock_shared()?);
+    assert!(!writer.try_lock()?);
+    reader.unlock()?;
+    assert!(!writer.try_lock()?);
+    other_reader.unlock()?;
+
+    assert!(writer.try_lock()?);
+    assert!(!reader.try_lock_shared()?);
+    writer.unlock()?;
+    assert!(reader.try_lock_shared()?);
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn rwlock_guards_release() -> Result<(), Error> {
+    use crate::RwLockFile;
+
+    let path = "testfiles/rwlock_guards_release.lock";
+    let mut reader = RwLockFile::open(path)?;
+    let mut writer = RwLockFile::open(path)?;
+
+    let guard = reader.try_read()?.unwrap();
+    assert!(writer.try_write()?.is_none());
+    drop(guard);
+
+    let guard = writer.try_write()?.unwrap();
+    assert!(reader.try_read()?.is_none());
+    guard.unlock()?;
+
+    assert!(reader.try_read()?.is_some());
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn ranges_exclude_overlapping() -> Result<(), Error> {
+    let path = "testfiles/ranges_exclude_overlapping.lock";
+    let file = LockFile::open(path)?;
+    let other = LockFile::open(path)?;
+
+    let mut first = file.range(0, 100);
+    first.lock()?;
+    assert!(!other.range(50, 100).try_lock()?);
+    let mut second = other.range(100, 100);
+    assert!(second.try_lock()?);
+    first.unlock()?;
+    assert!(other.range(0, 100).try_lock()?);
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn ranges_of_handle_exclude_overlapping() -> Result<(), Error> {
+    let path = "testfiles/ranges_of_handle_exclude_overlapping.lock";
+    let file = LockFile::open(path)?;
+    let other = LockFile::open(path)?;
+
+    let mut first = file.range(0, 100);
+    first.lock()?;
+    let mut overlapping = file.range(50, 100);
+    assert!(!overlapping.try_lock()?);
+    let error = overlapping.lock().unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::Deadlock);
+
+    // Unlocking the first range unlocks the whole of it.
+    first.unlock()?;
+    assert!(other.range(0, 50).try_lock()?);
+    assert!(overlapping.try_lock()?);
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn backends_exclude_across_handles() -> Result<(), Error> {
+    use crate::LockBackend;
+
+    let path = "testfiles/backends_exclude_across_handles.lock";
+    assert_eq!(LockFile::open(path)?.backend(), LockBackend::Ofd);
+    for backend in [LockBackend::Flock, LockBackend::Ofd] {
+        let mut file = LockFile::open_with_backend(path, backend)?;
+        let mut other = LockFile::open_with_backend(path, backend)?;
+        file.lock()?;
+        assert!(!other.try_lock()?);
+        assert!(!other.try_lock_shared()?);
+        file.unlock()?;
+        assert!(other.try_lock_shared()?);
+        assert!(file.try_lock_shared()?);
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn process_backends_exclude_ofd() -> Result<(), Error> {
+    use crate::LockBackend;
+
+    let path = "testfiles/process_backends_exclude_ofd.lock";
+    let mut other = LockFile::open_with_backend(path, LockBackend::Ofd)?;
+    for backend in [LockBackend::Fcntl, LockBackend::Lockf] {
+        let mut file = LockFile::open_with_backend(path, backend)?;
+        file.lock_with_pid()?;
+        assert!(!other.try_lock()?);
+        file.unlock()?;
+        assert!(other.try_lock()?);
+        other.unlock()?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn os_str_excludes_nul() -> Result<(), Error> {
+    use crate::{IntoOsString, ToOsStr};
+
+    for path in ["testfiles/os_str.lock\0", "testfiles/os_str.lock"] {
+        let borrowed = path.to_os_str()?;
+        assert_eq!(borrowed.as_ref().to_string(), "testfiles/os_str.lock");
+        let owned = borrowed.as_ref().into_os_string()?;
+        assert_eq!(owned.as_ref().to_string(), "testfiles/os_str.lock");
+    }
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn lock_timeout_expires() -> Result<(), Error> {
+    use std::time::{Duration, Instant};
+
+    let path = "testfiles/lock_timeout_expires.lock";
+    let mut file = LockFile::open(path)?;
+    let mut other = LockFile::open(path)?;
+    file.lock()?;
+
+    let start = Instant::now();
+    assert!(!other.lock_timeout(Duration::from_millis(100))?);
+    assert!(start.elapsed() >= Duration::from_millis(100));
+
+    assert!(!other.try_lock_until(Instant::now())?);
+    file.unlock()?;
+    assert!(other.lock_timeout(Duration::from_millis(100))?);
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn lock_with_policy_gives_up() -> Result<(), Error> {
+    use crate::RetryPolicy;
+    use std::time::Duration;
+
+    let path = "testfiles/lock_with_policy_gives_up.lock";
+    let mut file = LockFile::open(path)?;
+    let mut other = LockFile::open(path)?;
+    let policy = RetryPolicy {
+        initial_delay: Duration::from_millis(1),
+        max_attempts: Some(3),
+        ..RetryPolicy::default()
+    };
+
+    file.lock()?;
+    assert!(!other.lock_with_policy(&policy)?);
+    file.unlock()?;
+    assert!(other.lock_with_policy(&policy)?);
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn lock_with_policy_ignores_non_finite() -> Result<(), Error> {
+    use crate::RetryPolicy;
+    use std::time::Duration;
+
+    let path = "testfiles/lock_with_policy_ignores_non_finite.lock";
+    let mut file = LockFile::open(path)?;
+    let mut other = LockFile::open(path)?;
+    let policy = RetryPolicy {
+        initial_delay: Duration::from_millis(1),
+        multiplier: f64::INFINITY,
+        jitter: f64::NAN,
+        max_attempts: Some(3),
+        ..RetryPolicy::default()
+    };
+
+    file.lock()?;
+    assert!(!other.lock_with_policy(&policy)?);
+    let policy = RetryPolicy { multiplier: f64::NAN, ..policy };
+    assert!(!other.lock_with_policy(&policy)?);
+    Ok(())
+}
+
+#[cfg(all(target_os = "linux", feature = "std"))]
+#[test]
+fn watch_wakes_on_close() -> Result<(), Error> {
+    use crate::sys::Watch;
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    let path = "testfiles/watch_wakes_on_close.lock";
+    let file = LockFile::open(path)?;
+    let watch = Watch::new(file.desc)?;
+    let closer = thread::spawn(move || -> Result<(), Error> {
+        thread::sleep(Duration::from_millis(50));
+        drop(LockFile::open(path)?);
+        Ok(())
+    });
+
+    let start = Instant::now();
+    watch.wait(Duration::from_secs(10))?;
+    assert!(start.elapsed() < Duration::from_secs(5));
+    closer.join().unwrap()?;
+    Ok(())
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn tokio_lock_timeout() -> Result<(), Error> {
+    use std::time::Duration;
+
+    let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+    runtime.block_on(async {
+        let path = "testfiles/tokio_lock_timeout.lock";
+        let mut file = crate::tokio::LockFile::open(path).await?;
+        let mut other = crate::tokio::LockFile::open(path).await?;
+
+        file.lock().await?;
+        assert!(file.owns_lock());
+        assert!(!other.try_lock().await?);
+        assert!(!other.lock_timeout(Duration::from_millis(50)).await?);
+        file.unlock().await?;
+        assert!(other.lock_timeout(Duration::from_millis(50)).await?);
+        Ok(())
+    })
+}
+
+#[cfg(feature = "unblock")]
+#[test]
+fn unblock_lock_timeout() -> Result<(), Error> {
+    use std::time::Duration;
+
+    futures_lite::future::block_on(async {
+        let path = "testfiles/unblock_lock_timeout.lock";
+        let mut file = crate::unblock::LockFile::open(path).await?;
+        let mut other = crate::unblock::LockFile::open(path).await?;
+
+        file.lock().await?;
+        assert!(file.owns_lock());
+        assert!(!other.try_lock().await?);
+        assert!(!other.lock_timeout(Duration::from_millis(50)).await?);
+        file.unlock().await?;
+        assert!(other.lock_timeout(Duration::from_millis(50)).await?);
+        Ok(())
+    })
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn tokio_owns_lock_while_waiting() -> Result<(), Error> {
+    use std::{thread, time::Duration};
+
+    let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+    runtime.block_on(async {
+        let path = "testfiles/tokio_owns_lock_while_waiting.lock";
+        let mut file = crate::tokio::LockFile::open(path).await?;
+        let mut other = crate::tokio::LockFile::open(path).await?;
+
+        file.lock().await?;
+        // The dropped future keeps waiting on the blocking pool.
+        assert!(futures_lite::future::poll_once(other.lock()).await.is_none());
+        assert!(!other.owns_lock());
+        file.unlock().await?;
+        while !other.owns_lock() {
+            thread::sleep(Duration::from_millis(10));
+        }
+        other.unlock().await?;
+        Ok(())
+    })
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn guard_unlocks_on_early_return() -> Result<(), Error> {
+    fn fail_while_locked(file: &mut LockFile) -> Result<(), Error> {
+        let guard = file.lock_guard()?;
+        assert!(guard.owns_lock());
+        Err(std::io::Error::other("early return"))
+    }
+
+    let path = "testfiles/guard_unlocks_on_early_return.lock";
+    let mut file = LockFile::open(path)?;
+    let mut other = LockFile::open(path)?;
+    assert!(fail_while_locked(&mut file).is_err());
+    assert!(!file.owns_lock());
+    assert!(other.try_lock_guard()?.is_some());
+    assert!(!other.owns_lock());
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn owned_guard_moves_across_threads() -> Result<(), Error> {
+    use std::thread;
+
+    let path = "testfiles/owned_guard_moves_across_threads.lock";
+    let guard = LockFile::open(path)?.lock_owned()?;
+    let mut other = LockFile::open(path)?;
+    assert!(!other.try_lock()?);
+
+    let mut file = thread::spawn(move || guard.unlock()).join().unwrap()?;
+    assert!(!file.owns_lock());
+    assert!(other.try_lock()?);
+    assert!(!file.try_lock()?);
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn with_lock_releases() -> Result<(), Error> {
+    let path = "testfiles/with_lock_releases.lock";
+    let mut other = LockFile::open(path)?;
+    let locked_inside = LockFile::with_lock(path, |_| other.try_lock())??;
+    assert!(!locked_inside);
+    LockFile::with_lock(path, |file| file.unlock())??;
+    assert!(other.try_lock()?);
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn options_configure_open() -> Result<(), Error> {
+    use crate::LockFileOptions;
+    use std::fs;
+
+    let path = "testfiles/options_configure_open.lock";
+    let _ = fs::remove_file(path);
+    assert!(LockFileOptions::new().create(false).open(path).is_err());
+
+    let mut options = LockFileOptions::new();
+    options.truncate_on_close(false);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(path)?;
+    assert!(!file.truncate_on_close);
+    file.lock_with_pid()?;
+    file.unlock()?;
+    assert!(!fs::read_to_string(path)?.is_empty());
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(fs::metadata(path)?.permissions().mode() & 0o077, 0);
+    }
+
+    assert!(LockFileOptions::new().create(false).open(path).is_ok());
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn pid_preserving_keeps_content() -> Result<(), Error> {
+    use std::fs;
+
+    let path = "testfiles/pid_preserving_keeps_content.lock";
+    fs::write(path, "owner=test\n")?;
+    let mut file = LockFile::open(path)?;
+    file.truncate_on_close = false;
+    assert!(file.try_lock_with_pid_preserving()?);
+    file.unlock()?;
+
+    let content = fs::read_to_string(path)?;
+    assert_eq!(content, format!("owner=test\n{}\n", std::process::id()));
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn truncate_guard_is_thread_local() -> Result<(), Error> {
+    use crate::TruncateGuard;
+    use std::thread;
+
+    let path = "testfiles/truncate_guard_is_thread_local.lock";
+    let _guard = TruncateGuard::new(false);
+    {
+        let _inner = TruncateGuard::new(true);
+        assert!(LockFile::open(path)?.truncate_on_close);
+    }
+    assert!(!LockFile::open(path)?.truncate_on_close);
+    let other = thread::spawn(move || LockFile::open(path)).join().unwrap()?;
+    assert!(other.truncate_on_close);
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn open_existing_not_found() -> Result<(), Error> {
+    use std::{fs, io::ErrorKind};
+
+    let path = "testfiles/open_existing_not_found.lock";
+    let _ = fs::remove_file(path);
+    let error = LockFile::open_existing(path).err().unwrap();
+    assert_eq!(error.kind(), ErrorKind::NotFound);
+    assert!(fs::metadata(path).is_err());
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn create_new_first_wins() -> Result<(), Error> {
+    use std::{fs, io::ErrorKind};
+
+    let path = "testfiles/create_new_first_wins.lock";
+    let _ = fs::remove_file(path);
+    let _file = LockFile::create_new(path)?;
+    let error = LockFile::create_new(path).err().unwrap();
+    assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn symlinks_not_followed() -> Result<(), Error> {
+    use crate::LockFileOptions;
+    use std::{fs, os::unix::fs::symlink};
+
+    let target = "testfiles/symlinks_not_followed.target";
+    let path = "testfiles/symlinks_not_followed.lock";
+    let _ = fs::remove_file(path);
+    symlink("symlinks_not_followed.target", path)?;
+    assert!(LockFileOptions::new().follow_symlinks(false).open(path).is_err());
+    assert!(fs::metadata(target).is_err());
+    LockFileOptions::new().open(path)?;
+    assert!(fs::metadata(target).is_ok());
+    fs::remove_file(target)?;
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn mode_ignores_umask() -> Result<(), Error> {
+    use crate::LockFileOptions;
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    let path = "testfiles/mode_ignores_umask.lock";
+    let _ = fs::remove_file(path);
+    LockFileOptions::new().mode(0o666).open(path)?;
+    assert_eq!(fs::metadata(path)?.permissions().mode() & 0o777, 0o666);
+
+    // Existing files keep their permissions.
+    LockFileOptions::new().mode(0o600).open(path)?;
+    assert_eq!(fs::metadata(path)?.permissions().mode() & 0o777, 0o666);
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn create_dirs_creates_parents() -> Result<(), Error> {
+    use crate::LockFileOptions;
+    use std::fs;
+
+    let dir = "testfiles/create_dirs_creates_parents";
+    let path = "testfiles/create_dirs_creates_parents/nested/main.lock";
+    let _ = fs::remove_dir_all(dir);
+    assert!(LockFile::open(path).is_err());
+    let mut file = LockFileOptions::new().create_dirs(true).open(path)?;
+    assert!(file.try_lock()?);
+    drop(file);
+    fs::remove_dir_all(dir)?;
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn was_created_once() -> Result<(), Error> {
+    use std::fs;
+
+    let path = "testfiles/was_created_once.lock";
+    let _ = fs::remove_file(path);
+    assert!(LockFile::open(path)?.was_created());
+    assert!(!LockFile::open(path)?.was_created());
+    assert!(!LockFile::open_existing(path)?.was_created());
+    fs::remove_file(path)?;
+    assert!(LockFile::create_new(path)?.was_created());
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn read_contents_keeps_position() -> Result<(), Error> {
+    use std::fs;
+
+    let path = "testfiles/read_contents_keeps_position.lock";
+    fs::write(path, vec![b'x'; 1000])?;
+    let mut file = LockFile::open(path)?;
+    file.truncate_on_close = false;
+    assert_eq!(file.read_contents()?, vec![b'x'; 1000]);
+    file.lock_with_pid()?;
+    file.unlock()?;
+
+    let contents = file.read_contents()?;
+    let pid = format!("{}\n", std::process::id());
+    assert_eq!(contents.len(), 1000);
+    assert!(contents.starts_with(pid.as_bytes()));
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn write_contents_replaces() -> Result<(), Error> {
+    use std::fs;
+
+    let path = "testfiles/write_contents_replaces.lock";
+    let mut file = LockFile::open(path)?;
+    file.lock_with_pid()?;
+    file.write_contents(b"socket=/run/app.sock\n")?;
+    assert_eq!(fs::read(path)?, b"socket=/run/app.sock\n");
+    file.write_contents(b"port=80\n")?;
+    assert_eq!(file.read_contents()?, b"port=80\n");
+    file.unlock()?;
+    assert!(fs::read(path)?.is_empty());
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn io_write_follows_pid() -> Result<(), Error> {
+    use std::{fs, io::Write};
+
+    let path = "testfiles/io_write_follows_pid.lock";
+    let mut file = LockFile::open(path)?;
+    file.lock_with_pid()?;
+    writeln!(&mut file, "port={}", 8080)?;
+    let expected = format!("{}\nport=8080\n", std::process::id());
+    assert_eq!(fs::read_to_string(path)?, expected);
+    file.unlock()?;
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn io_read_seek_state() -> Result<(), Error> {
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    let path = "testfiles/io_read_seek_state.lock";
+    let mut file = LockFile::open(path)?;
+    file.lock()?;
+    file.write_all(b"count=1")?;
+    assert_eq!(file.seek(SeekFrom::Current(-1))?, 6);
+    file.write_all(b"2")?;
+    assert_eq!(file.seek(SeekFrom::Start(0))?, 0);
+    let mut state = String::new();
+    file.read_to_string(&mut state)?;
+    assert_eq!(state, "count=2");
+    file.unlock()?;
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn synced_pid_written() -> Result<(), Error> {
+    use crate::LockFileOptions;
+    use std::fs;
+
+    let path = "testfiles/synced_pid_written.lock";
+    let mut file = LockFileOptions::new().sync(true).open(path)?;
+    file.lock_with_pid()?;
+    let content = fs::read_to_string(path)?;
+    assert_eq!(content.trim(), std::process::id().to_string());
+    file.unlock()?;
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn sync_dir_on_creation() -> Result<(), Error> {
+    use crate::LockFileOptions;
+    use std::fs;
+
+    let path = "testfiles/sync_dir_on_creation.lock";
+    let _ = fs::remove_file(path);
+    let file = LockFileOptions::new().sync_dir(true).open(path)?;
+    assert!(file.was_created());
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn peek_owner_pid_does_not_create() -> Result<(), Error> {
+    use std::fs;
+
+    let path = "testfiles/peek_owner_pid_does_not_create.lock";
+    let _ = fs::remove_file(path);
+    assert_eq!(LockFile::peek_owner_pid(path)?, None);
+    assert!(fs::metadata(path).is_err());
+
+    let mut file = LockFile::open(path)?;
+    file.lock_with_pid()?;
+    assert_eq!(LockFile::peek_owner_pid(path)?, Some(std::process::id()));
+    file.unlock()?;
+    assert_eq!(LockFile::peek_owner_pid(path)?, None);
+    Ok(())
+}
+
+#[cfg(all(target_os = "linux", feature = "std"))]
+#[test]
+fn blocking_owner_flock() -> Result<(), Error> {
+    use crate::LockBackend;
+
+    let path = "testfiles/blocking_owner_flock.lock";
+    let mut file = LockFile::open_with_backend(path, LockBackend::Flock)?;
+    let mut other = LockFile::open_with_backend(path, LockBackend::Flock)?;
+    assert_eq!(other.blocking_owner()?, None);
+    file.lock()?;
+    assert!(!other.try_lock()?);
+    assert_eq!(other.blocking_owner()?, Some(std::process::id()));
+    file.unlock()?;
+    assert_eq!(other.blocking_owner()?, None);
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn try_lock_detailed_reports_owner() -> Result<(), Error> {
+    use crate::TryLockResult;
+
+    let path = "testfiles/try_lock_detailed_reports_owner.lock";
+    let mut file = LockFile::open(path)?;
+    let mut other = LockFile::open(path)?;
+    file.lock_with_pid()?;
+    let owner = match other.try_lock_detailed()? {
+        TryLockResult::HeldBy(owner) => owner,
+        TryLockResult::Acquired => panic!("lock acquired twice"),
+    };
+    assert_eq!(owner.pid, Some(std::process::id()));
+    #[cfg(target_os = "linux")]
+    assert!(owner.name.is_some());
+    file.unlock()?;
+    assert!(other.try_lock_detailed()?.is_acquired());
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn stale_pid_detected() -> Result<(), Error> {
+    use std::{fs, process::Command};
+
+    let path = "testfiles/stale_pid_detected.lock";
+    let mut child = Command::new("true").spawn()?;
+    let dead = child.id();
+    child.wait()?;
+    fs::write(path, format!("{}\n", dead))?;
+    assert!(LockFile::is_stale_at(path)?);
+    assert!(LockFile::open(path)?.is_stale()?);
+
+    fs::write(path, format!("{}\n", std::process::id()))?;
+    assert!(!LockFile::is_stale_at(path)?);
+    fs::write(path, "")?;
+    assert!(!LockFile::is_stale_at(path)?);
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn lock_or_steal_recreates() -> Result<(), Error> {
+    use crate::{StalePolicy, StealOutcome};
+    use std::{fs, process::Command};
+
+    let path = "testfiles/lock_or_steal_recreates.lock";
+    let mut child = Command::new("true").spawn()?;
+    let dead = child.id();
+    child.wait()?;
+
+    // Stale content left behind by a dead owner.
+    let _ = fs::remove_file(path);
+    fs::write(path, format!("{}\n", dead))?;
+    let mut file = LockFile::open(path)?;
+    let outcome = file.lock_or_steal(StalePolicy::Truncate)?;
+    assert_eq!(outcome, StealOutcome::Recovered { pid: dead });
+    assert!(fs::read(path)?.is_empty());
+
+    // A lock leaked on behalf of a dead owner.
+    fs::write(path, format!("{}\n", dead))?;
+    let mut other = LockFile::open(path)?;
+    let outcome = other.lock_or_steal(StalePolicy::Recreate)?;
+    assert_eq!(outcome, StealOutcome::Stolen { pid: dead });
+    assert!(other.owns_lock());
+    assert!(!LockFile::open(path)?.try_lock()?);
+    file.unlock()?;
+    assert!(!LockFile::open(path)?.try_lock()?);
+    other.unlock()?;
+    Ok(())
+}
+
+#[cfg(all(target_os = "linux", feature = "std"))]
+#[test]
+fn recycled_pid_is_stale() -> Result<(), Error> {
+    use crate::LockFileOptions;
+    use std::fs;
+
+    let path = "testfiles/recycled_pid_is_stale.lock";
+    let mut file = LockFileOptions::new().record_start_time(true).open(path)?;
+    file.truncate_on_close = false;
+    file.lock_with_pid()?;
+    file.unlock()?;
+    let content = fs::read_to_string(path)?;
+    assert!(content.lines().nth(1).unwrap().starts_with("start="));
+    assert!(!LockFile::is_stale_at(path)?);
+    assert_eq!(LockFile::peek_owner_pid(path)?, Some(std::process::id()));
+
+    // Our PID, but recorded for a process started at another time.
+    fs::write(path, format!("{}\nstart=1\n", std::process::id()))?;
+    assert!(LockFile::is_stale_at(path)?);
+    Ok(())
+}
+
+#[cfg(all(target_os = "linux", feature = "std"))]
+#[test]
+fn wait_for_owner_exit_wakes() -> Result<(), Error> {
+    use std::{
+        fs,
+        process::Command,
+        time::{Duration, Instant},
+    };
+
+    let path = "testfiles/wait_for_owner_exit_wakes.lock";
+    let mut holder = LockFile::open(path)?;
+    holder.lock()?;
+    let mut child = Command::new("sleep").arg("0.2").spawn()?;
+    fs::write(path, format!("{}\n", child.id()))?;
+
+    let mut file = LockFile::open(path)?;
+    let start = Instant::now();
+    assert!(!file.try_lock()?);
+    assert!(file.wait_for_owner_exit()?);
+    assert!(start.elapsed() >= Duration::from_millis(100));
+    child.wait()?;
+    holder.unlock()?;
+    Ok(())
+}
+
+#[cfg(all(target_os = "linux", feature = "std"))]
+#[test]
+fn previous_boot_is_stale() -> Result<(), Error> {
+    use std::fs;
+
+    let path = "testfiles/previous_boot_is_stale.lock";
+    let pid = std::process::id();
+    fs::write(path, format!("{}\nboot=00000000-0000\n", pid))?;
+    assert!(LockFile::is_stale_at(path)?);
+    let boot = fs::read_to_string("/proc/sys/kernel/random/boot_id")?;
+    fs::write(path, format!("{}\nboot={}", pid, boot))?;
+    assert!(!LockFile::is_stale_at(path)?);
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn lock_with_info_records_host() -> Result<(), Error> {
+    use std::fs::read_to_string;
+
+    let path = "testfiles/lock_with_info_records_host.lock";
+    let mut file = LockFile::open(path)?;
+    file.lock_with_info()?;
+    let content = read_to_string(path)?;
+    let mut lines = content.lines();
+    assert_eq!(lines.next(), Some(std::process::id().to_string().as_str()));
+    let host = lines.next().and_then(|line| line.strip_prefix("host="));
+    assert_eq!(host, Some(crate::sys::hostname()?.as_str()));
+    let time = lines.next().and_then(|line| line.strip_prefix("time="));
+    let time = time.unwrap().as_bytes();
+    assert_eq!(
+        (time.len(), time[4], time[10], time[19]),
+        (20, b'-', b'T', b'Z')
+    );
+    assert_eq!(LockFile::peek_owner_pid(path)?, Some(std::process::id()));
+    file.unlock()?;
+    assert_eq!(read_to_string(path)?, "");
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn lock_info_parses_record() -> Result<(), Error> {
+    use crate::LockInfo;
+    use std::{
+        fs,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    let path = "testfiles/lock_info_parses_record.lock";
+    let contents =
+        "42\nhost=elsewhere\ntime=2021-03-14T15:09:26Z\nexe=daemon\n";
+    fs::write(path, contents)?;
+    let info = LockInfo::read_from(path)?.unwrap();
+    assert_eq!(info.pid, 42);
+    assert_eq!(info.hostname.as_deref(), Some("elsewhere"));
+    let time = UNIX_EPOCH + Duration::from_secs(1615734566);
+    assert_eq!(info.started_at, Some(time));
+    assert_eq!(info.executable.as_deref(), Some("daemon"));
+    assert!(!LockFile::is_stale_at(path)?);
+
+    let mut file = LockFile::open(path)?;
+    file.lock_with_info()?;
+    let info = file.owner_info()?.unwrap();
+    let elapsed = SystemTime::now().duration_since(info.started_at.unwrap());
+    assert!(elapsed.unwrap() < Duration::from_secs(60));
+    file.unlock()?;
+    assert_eq!(file.owner_info()?, None);
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn lock_with_unlocks_on_failure() -> Result<(), Error> {
+    use std::io;
+
+    let path = "testfiles/lock_with_unlocks_on_failure.lock";
+    let mut file = LockFile::open(path)?;
+    let error = file
+        .lock_with(|out| {
+            out.write_all(b"partial")?;
+            Err(io::Error::other("payload failed"))
+        })
+        .unwrap_err();
+    assert_eq!(error.to_string(), "payload failed");
+    assert!(!file.owns_lock());
+    assert_eq!(file.read_contents()?, b"");
+
+    let mut other = LockFile::open(path)?;
+    assert!(other.try_lock()?);
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn json_metadata_round_trip() -> Result<(), Error> {
+    use std::{collections::BTreeMap, io::ErrorKind};
+
+    let path = "testfiles/json_metadata_round_trip.lock";
+    let mut metadata = BTreeMap::new();
+    metadata.insert("note".to_owned(), "two\nlines".to_owned());
+    let mut file = LockFile::open(path)?;
+    file.lock_with_json(&metadata)?;
+    assert_eq!(LockFile::peek_owner_pid(path)?, Some(std::process::id()));
+    let read: Option<BTreeMap<String, String>> = file.owner_info_json()?;
+    assert_eq!(read, Some(metadata));
+    let error = file.owner_info_json::<u32>().unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+    file.unlock()?;
+    assert_eq!(file.owner_info_json::<BTreeMap<String, String>>()?, None);
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn heartbeat_refreshes_while_locked() -> Result<(), Error> {
+    use std::{fs::File, thread, time::Duration};
+
+    let path = "testfiles/heartbeat_refreshes_while_locked.lock";
+    let mut file = LockFile::open(path)?;
+    let old = std::time::SystemTime::now() - Duration::from_secs(3600);
+    File::options().write(true).open(path)?.set_modified(old)?;
+    assert!(
+        LockFile::heartbeat_age_at(path)?.unwrap() > Duration::from_secs(60)
+    );
+
+    file.set_heartbeat(Some(Duration::from_millis(20)))?;
+    thread::sleep(Duration::from_millis(60));
+    assert!(
+        LockFile::heartbeat_age_at(path)?.unwrap() > Duration::from_secs(60)
+    );
+
+    file.lock()?;
+    thread::sleep(Duration::from_millis(60));
+    assert!(
+        LockFile::heartbeat_age_at(path)?.unwrap() < Duration::from_secs(60)
+    );
+    file.unlock()?;
+    file.set_heartbeat(None)?;
+    assert_eq!(
+        LockFile::heartbeat_age_at("testfiles/missing.heartbeat")?,
+        None
+    );
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn lease_taken_over_once_expired() -> Result<(), Error> {
+    use crate::LeaseLock;
+    use std::{thread, time::Duration};
+
+    let path = "testfiles/lease_taken_over_once_expired.lock";
+    let _ = std::fs::remove_file(path);
+    let mut first = LeaseLock::new(path, Duration::from_millis(100))?;
+    let mut second = LeaseLock::new(path, Duration::from_secs(60))?;
+    assert!(first.try_lock()?);
+    assert!(!second.try_lock()?);
+    assert!(first.renew()?);
+    assert_eq!(LockFile::peek_owner_pid(path)?, Some(std::process::id()));
+
+    thread::sleep(Duration::from_millis(150));
+    assert!(second.try_lock()?);
+    assert!(!first.renew()?);
+    assert!(!first.owns_lock());
+    assert!(second.renew()?);
+    second.unlock()?;
+    assert!(first.try_lock()?);
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn lease_lost_once_expired() -> Result<(), Error> {
+    use crate::LeaseLock;
+    use std::{path::Path, thread, time::Duration};
+
+    let path = "testfiles/lease_lost_once_expired.lock";
+    let _ = std::fs::remove_file(path);
+    let mut first = LeaseLock::new(path, Duration::from_millis(50))?;
+    let mut second = LeaseLock::new(path, Duration::from_secs(60))?;
+    assert!(first.try_lock()?);
+    thread::sleep(Duration::from_millis(100));
+
+    // Expired leases are left in place for others to take over.
+    assert!(!first.renew()?);
+    assert!(Path::new(path).exists());
+    assert!(first.try_lock()?);
+    thread::sleep(Duration::from_millis(100));
+    first.unlock()?;
+    assert!(Path::new(path).exists());
+    assert!(second.try_lock()?);
+    second.unlock()?;
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn lease_taken_over_once_corrupted_long_ago() -> Result<(), Error> {
+    use crate::LeaseLock;
+    use std::{fs, thread, time::Duration};
+
+    let path = "testfiles/lease_taken_over_once_corrupted_long_ago.lock";
+    fs::write(path, "garbage\n")?;
+    let mut lease = LeaseLock::new(path, Duration::from_millis(50))?;
+    assert!(!lease.try_lock()?);
+    thread::sleep(Duration::from_millis(100));
+    assert!(lease.try_lock()?);
+    assert!(lease.renew()?);
+    lease.unlock()?;
+    assert!(fs::metadata(path).is_err());
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn leader_elected_after_resignation() -> Result<(), Error> {
+    use crate::{Leader, LeaderEvent};
+    use std::time::Duration;
+
+    let path = "testfiles/leader_elected_after_resignation.lock";
+    let _ = std::fs::remove_file(path);
+    let ttl = Duration::from_millis(300);
+    let timeout = Duration::from_secs(5);
+    let first = Leader::campaign(path, ttl)?;
+    assert_eq!(first.events().recv_timeout(timeout), Ok(LeaderEvent::Elected));
+    assert!(first.is_leader());
+
+    let second = Leader::campaign(path, ttl)?;
+    assert!(second.events().recv_timeout(ttl).is_err());
+    assert!(!second.is_leader());
+    first.resign();
+    assert_eq!(second.events().recv_timeout(timeout), Ok(LeaderEvent::Elected));
+    assert!(second.is_leader());
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn run_once_across_threads() -> Result<(), Error> {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+    };
+
+    let path = "testfiles/run_once_across_threads.once";
+    let _ = std::fs::remove_file(path);
+    let runs = AtomicUsize::new(0);
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0 .. 4)
+            .map(|_| {
+                scope.spawn(|| {
+                    crate::run_once(path, || {
+                        runs.fetch_add(1, Ordering::SeqCst)
+                    })
+                })
+            })
+            .collect();
+        let ran: Vec<_> =
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+        assert_eq!(
+            ran.iter().filter(|ran| matches!(ran, Ok(Some(_)))).count(),
+            1
+        );
+        assert!(ran.iter().all(Result::is_ok));
+    });
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+    assert_eq!(crate::run_once(path, || ())?, None);
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn file_counter_unique_values() -> Result<(), Error> {
+    use crate::FileCounter;
+    use std::thread;
+
+    let path = "testfiles/file_counter_unique_values";
+    FileCounter::open(path)?.store(10)?;
+    let values: Result<Vec<Vec<u64>>, Error> = thread::scope(|scope| {
+        let handles: Vec<_> = (0 .. 4)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut counter = FileCounter::open(path)?;
+                    (0 .. 25).map(|_| counter.fetch_add(1)).collect()
+                })
+            })
+            .collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    });
+    let mut values = values?.concat();
+    values.sort_unstable();
+    assert_eq!(values, (10 .. 110).collect::<Vec<_>>());
+    assert_eq!(FileCounter::open(path)?.load()?, 110);
+
+    std::fs::write(path, "not a number")?;
+    let error = FileCounter::open(path)?.load().unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn lock_set_rolls_back() -> Result<(), Error> {
+    use crate::LockSet;
+
+    let first = "testfiles/lock_set_rolls_back_1.lock";
+    let second = "testfiles/lock_set_rolls_back_2.lock";
+    let mut set = LockSet::open(&[
+        second,
+        first,
+        "testfiles/../testfiles/lock_set_rolls_back_1.lock",
+    ])?;
+    assert_eq!(set.len(), 2);
+
+    let mut blocker = LockFile::open(second)?;
+    blocker.lock()?;
+    assert!(!set.try_lock()?);
+    assert!(!set.owns_lock());
+    let mut other = LockFile::open(first)?;
+    assert!(other.try_lock()?);
+    other.unlock()?;
+
+    blocker.unlock()?;
+    assert!(set.try_lock()?);
+    assert!(!other.try_lock()?);
+    set.unlock()?;
+    assert!(other.try_lock()?);
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn try_lock_all_reports_conflict() -> Result<(), Error> {
+    use crate::LockSet;
+
+    let first = "testfiles/try_lock_all_reports_conflict_1.lock";
+    let second = "testfiles/try_lock_all_reports_conflict_2.lock";
+    let mut set = LockSet::open(&[first, second])?;
+    let mut blocker = LockFile::open(second)?;
+    blocker.lock()?;
+    let conflict = set.try_lock_all()?.map(|path| path.to_path_buf());
+    assert_eq!(conflict, Some(std::fs::canonicalize(second)?));
+    assert!(LockFile::open(first)?.try_lock()?);
+
+    blocker.unlock()?;
+    assert!(set.try_lock_all()?.is_none());
+    assert!(set.owns_lock());
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn process_wide_backends_exclude_handles() -> Result<(), Error> {
+    use crate::LockBackend;
+    use std::{sync::mpsc, thread, time::Duration};
+
+    for backend in [LockBackend::Fcntl, LockBackend::Lockf] {
+        let path = "testfiles/process_wide_backends_exclude_handles.lock";
+        let mut first = LockFile::open_with_backend(path, backend)?;
+        let mut second = LockFile::open_with_backend(path, backend)?;
+        first.lock()?;
+        assert!(!second.try_lock()?);
+
+        let (sender, receiver) = mpsc::channel();
+        let waiter = thread::spawn(move || -> Result<(), Error> {
+            second.lock()?;
+            sender.send(()).unwrap();
+            second.unlock()
+        });
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+        first.unlock()?;
+        assert!(receiver.recv_timeout(Duration::from_secs(5)).is_ok());
+        waiter.join().unwrap()?;
+    }
+
+    let path = "testfiles/process_wide_backends_share.lock";
+    let mut first = LockFile::open_with_backend(path, LockBackend::Fcntl)?;
+    let mut second = LockFile::open_with_backend(path, LockBackend::Fcntl)?;
+    let mut writer = LockFile::open_with_backend(path, LockBackend::Fcntl)?;
+    assert!(first.try_lock_shared()?);
+    assert!(second.try_lock_shared()?);
+    first.unlock()?;
+    assert!(!writer.try_lock()?);
+    second.unlock()?;
+    assert!(writer.try_lock()?);
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn paths_canonicalized() -> Result<(), Error> {
+    use crate::LockFileOptions;
+    use std::{fs, os::unix::fs::symlink};
+
+    let path = "testfiles/paths_canonicalized.lock";
+    let link = "testfiles/paths_canonicalized.link";
+    let _ = fs::remove_file(link);
+    symlink("paths_canonicalized.lock", link)?;
+    let canonical = LockFile::open(path)?.path().to_path_buf();
+    assert!(canonical.is_absolute());
+    let dotted = LockFile::open("testfiles/./paths_canonicalized.lock")?;
+    assert_eq!(dotted.path().to_path_buf(), canonical);
+    assert_eq!(LockFile::open(link)?.path().to_path_buf(), canonical);
+
+    let file = LockFileOptions::new().canonicalize(false).open(link)?;
+    assert_eq!(file.path().to_string(), link);
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn named_locks_share_sanitized_name() -> Result<(), Error> {
+    let path = LockFile::named_path("fslock test/named:lock");
+    let name = path.file_name().unwrap().to_str().unwrap();
+    assert_eq!(name, "fslock_test_named_lock.lock");
+    assert!(path.is_absolute());
+
+    let mut first = LockFile::named("fslock test/named:lock")?;
+    let mut second = LockFile::named("fslock_test_named_lock")?;
+    assert!(first.try_lock()?);
+    assert!(!second.try_lock()?);
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn user_lock_dir_is_private() -> Result<(), Error> {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    let dir = crate::user_lock_dir()?;
+    let metadata = std::fs::metadata(&dir)?;
+    assert_eq!(metadata.uid(), crate::sys::user_id());
+    if std::env::var_os("XDG_RUNTIME_DIR").is_none() {
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o700);
+    }
+
+    let mut first = LockFile::user_scoped("fslock test user")?;
+    let mut second = LockFile::user_scoped("fslock_test_user")?;
+    assert!(first.path().to_path_buf().starts_with(&dir));
+    assert!(first.try_lock()?);
+    assert!(!second.try_lock()?);
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn system_locks_follow_fhs() -> Result<(), Error> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = LockFile::system_path("fslock test/system");
+    let parent = path.parent().unwrap();
+    assert!(parent == "/run/lock" || parent == "/var/lock");
+    assert_eq!(path.file_name().unwrap(), "fslock_test_system.lock");
+
+    // The directory is only writable by privileged users.
+    let file = match LockFile::system("fslock test/system") {
+        Ok(file) => file,
+        Err(error) => {
+            assert!(matches!(
+                error.kind(),
+                std::io::ErrorKind::PermissionDenied
+                    | std::io::ErrorKind::NotFound
+            ));
+            return Ok(());
+        },
+    };
+    let mode = std::fs::metadata(file.path().to_path_buf())?.permissions();
+    if file.was_created() {
+        assert_eq!(mode.mode() & 0o777, 0o644);
+    }
+    drop(file);
+    let _ = std::fs::remove_file(path);
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn single_instance_forwards_args() -> Result<(), Error> {
+    use crate::{Instance, SingleInstance};
+    use std::thread;
+
+    let name = "fslock-test-single-instance";
+    let Instance::Primary(primary) = SingleInstance::acquire(name)? else {
+        panic!("no other instance should be running");
+    };
+    let receiver = thread::spawn(move || primary.accept_args());
+
+    let Instance::Secondary(running) = SingleInstance::acquire(name)? else {
+        panic!("the primary instance should be running");
+    };
+    assert_eq!(running.pid(), Some(std::process::id()));
+    running.forward_args(&["open", "some file.ufo"])?;
+    assert_eq!(receiver.join().unwrap()?, ["open", "some file.ufo"]);
+    Ok(())
+}
+
+#[cfg(all(windows, feature = "std"))]
+#[test]
+fn named_mutex_excludes_threads() -> Result<(), Error> {
+    use crate::NamedMutex;
+    use std::thread;
+
+    let name = "fslock-test-named-mutex";
+    let mut mutex = NamedMutex::open_local(name)?;
+    assert_eq!(mutex.name(), "Local\\fslock-test-named-mutex");
+    mutex.lock()?;
+    let locked =
+        thread::spawn(move || NamedMutex::open_local(name)?.try_lock());
+    assert!(!locked.join().unwrap()?);
+    mutex.unlock()?;
+    let locked =
+        thread::spawn(move || NamedMutex::open_local(name)?.try_lock());
+    assert!(locked.join().unwrap()?);
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn named_semaphore_excludes_handles() -> Result<(), Error> {
+    use crate::NamedSemaphore;
+
+    let name = format!("fslock-test-semaphore-{}", std::process::id());
+    let mut semaphore = NamedSemaphore::open(&name)?;
+    let mut other = NamedSemaphore::open(&name)?;
+    assert_eq!(semaphore.name(), format!("/{}", name));
+    assert!(semaphore.try_lock()?);
+    assert!(!other.try_lock()?);
+    semaphore.unlock()?;
+    assert!(other.try_lock()?);
+    drop(other);
+    assert!(semaphore.try_lock()?);
+    NamedSemaphore::remove(&name)?;
+    assert!(NamedSemaphore::open("invalid/name").is_err());
+    Ok(())
+}
+
+#[cfg(all(target_os = "linux", feature = "std"))]
+#[test]
+fn robust_mutex_reports_dead_owner() -> Result<(), Error> {
+    use crate::{RobustLockResult, RobustMutex};
+    use std::thread;
+
+    let path = "testfiles/robust_mutex_reports_dead_owner.mutex";
+    let mut mutex = RobustMutex::open(path)?;
+    assert!(mutex.try_lock()?.is_some());
+    let locked = thread::spawn(move || RobustMutex::open(path)?.try_lock());
+    assert_eq!(locked.join().unwrap()?, None);
+    mutex.unlock()?;
+
+    // A thread exiting while owning the mutex makes it die as an owner.
+    thread::spawn(move || -> Result<(), Error> {
+        let mut mutex = RobustMutex::open(path)?;
+        mutex.lock()?;
+        std::mem::forget(mutex);
+        Ok(())
+    })
+    .join()
+    .unwrap()?;
+    assert_eq!(mutex.lock()?, RobustLockResult::OwnerDied);
+    mutex.unlock()?;
+    assert_eq!(mutex.lock()?, RobustLockResult::Acquired);
+    mutex.unlock()?;
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn dir_lock_takes_over_stale() -> Result<(), Error> {
+    use crate::DirLock;
+    use std::{fs, time::Duration};
+
+    let path = "testfiles/dir_lock_takes_over_stale.lock";
+    let mut lock = DirLock::new(path)?;
+    let mut other = DirLock::new(path)?;
+    assert!(lock.try_lock()?);
+    assert_eq!(other.owner_info()?.unwrap().pid, std::process::id());
+    assert!(!other.try_lock()?);
+    lock.unlock()?;
+    assert!(!lock.path().exists());
+
+    // Owned by a process which no longer runs.
+    fs::create_dir(path)?;
+    fs::write(format!("{}/owner", path), format!("{}\n", u32::MAX - 1))?;
+    assert!(other.try_lock()?);
+    lock.set_stale_after(Some(Duration::from_secs(60)));
+    assert!(!lock.try_lock()?);
+    other.refresh()?;
+    lock.set_stale_after(Some(Duration::ZERO));
+    assert!(lock.try_lock()?);
+    other.unlock()?;
+    assert!(lock.path().exists());
+    lock.unlock()?;
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn link_lock_takes_over_stale() -> Result<(), Error> {
+    use crate::LinkLock;
+    use std::fs;
+
+    let path = "testfiles/link_lock_takes_over_stale.lock";
+    let mut lock = LinkLock::new(path)?;
+    let mut other = LinkLock::new(path)?;
+    assert!(lock.try_lock()?);
+    assert_eq!(other.owner_info()?.unwrap().pid, std::process::id());
+    assert!(!other.try_lock()?);
+    lock.unlock()?;
+    assert!(!lock.path().exists());
+
+    // Owned by a process which no longer runs.
+    fs::write(path, format!("{}\n", u32::MAX - 1))?;
+    assert!(other.try_lock()?);
+    assert!(!lock.try_lock()?);
+    other.unlock()?;
+    assert!(lock.try_lock()?);
+    lock.unlock()?;
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn dot_lock_compatible_with_liblockfile() -> Result<(), Error> {
+    use crate::DotLock;
+    use std::fs;
+
+    let mut lock = DotLock::for_file("testfiles/dot_lock_mailbox")?;
+    let mut other = DotLock::new("testfiles/dot_lock_mailbox.lock")?;
+    assert!(lock.try_lock()?);
+    let contents = fs::read_to_string(lock.path())?;
+    assert_eq!(contents, format!("{}\n", std::process::id()));
+    assert!(!other.lock_with_retries(0)?);
+    lock.refresh()?;
+    lock.unlock()?;
+    assert!(!lock.path().exists());
+
+    // Left behind by a process which no longer runs.
+    fs::write(other.path(), format!("{}\n", u32::MAX - 1))?;
+    assert!(other.try_lock()?);
+    other.unlock()?;
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn atomic_pid_replaces_file() -> Result<(), Error> {
+    use crate::LockFileOptions;
+    use std::fs;
+
+    let path = "testfiles/atomic_pid_replaces_file.lock";
+    let mut options = LockFileOptions::new();
+    options.atomic_pid(true);
+    let mut file = options.open(path)?;
+    let mut other = options.open(path)?;
+    file.lock_with_pid()?;
+    let contents = fs::read_to_string(path)?;
+    assert_eq!(contents.trim(), std::process::id().to_string());
+    assert!(crate::sys::same_file(file.desc, file.path())?);
+
+    // The other handle still refers to the replaced file, which is unlocked.
+    assert!(!other.try_lock()?);
+    assert!(crate::sys::same_file(other.desc, other.path())?);
+    file.unlock()?;
+    assert!(other.try_lock_with_pid()?);
+    assert!(!file.try_lock()?);
+    other.unlock()?;
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn atomic_pid_keeps_options() -> Result<(), Error> {
+    use crate::LockFileOptions;
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    let path = "testfiles/atomic_pid_keeps_options.lock";
+    let _ = fs::remove_file(path);
+    let mut file =
+        LockFileOptions::new().mode(0o600).atomic_pid(true).open(path)?;
+    file.lock_with_pid()?;
+    assert_eq!(fs::metadata(path)?.permissions().mode() & 0o777, 0o600);
+    file.unlock()?;
+
+    // Reopening the replaced file keeps refusing symbolic links.
+    let link = "testfiles/atomic_pid_keeps_options.link";
+    let _ = fs::remove_file(link);
+    let mut file = LockFileOptions::new()
+        .atomic_pid(true)
+        .follow_symlinks(false)
+        .canonicalize(false)
+        .open(link)?;
+    fs::remove_file(link)?;
+    std::os::unix::fs::symlink("atomic_pid_keeps_options.lock", link)?;
+    assert!(file.relock_fresh().is_err());
+    fs::remove_file(link)?;
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn sidecar_named_after_file() -> Result<(), Error> {
+    use std::fs;
+
+    let file = LockFile::sidecar("testfiles/sidecar_named_after_file.db")?;
+    let expected =
+        fs::canonicalize("testfiles")?.join("sidecar_named_after_file.db.lock");
+    assert_eq!(file.path().to_path_buf(), expected);
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn remove_on_unlock_by_last_holder() -> Result<(), Error> {
+    use crate::LockFileOptions;
+    use std::path::Path;
+
+    let path = "testfiles/remove_on_unlock_by_last_holder.lock";
+    let mut options = LockFileOptions::new();
+    options.remove_on_unlock(true);
+    let mut file = options.open(path)?;
+    let mut other = options.open(path)?;
+    file.lock()?;
+    file.unlock()?;
+    assert!(!Path::new(path).exists());
+
+    // The other handle refers to the removed file, and reopens the path.
+    assert!(other.try_lock()?);
+    assert!(Path::new(path).exists());
+    assert!(!file.try_lock()?);
+    other.unlock()?;
+    assert!(!Path::new(path).exists());
+
+    file.lock_shared()?;
+    other.lock_shared()?;
+    file.unlock()?;
+    assert!(Path::new(path).exists());
+    other.unlock()?;
+    assert!(!Path::new(path).exists());
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn reopen_after_removal_counts_one_acquisition() -> Result<(), Error> {
+    use crate::LockFileOptions;
+
+    let path = "testfiles/reopen_after_removal_counts_one_acquisition.lock";
+    let mut options = LockFileOptions::new();
+    options.remove_on_unlock(true);
+    let mut file = options.open(path)?;
+    let mut other = options.open(path)?;
+    file.lock()?;
+    file.unlock()?;
+
+    other.lock()?;
+    assert_eq!(other.acquisitions(), 1);
+    other.unlock()?;
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn relock_fresh_after_recreation() -> Result<(), Error> {
+    use std::fs;
+
+    let path = "testfiles/relock_fresh_after_recreation.lock";
+    let mut file = LockFile::open(path)?;
+    file.lock_shared()?;
+    assert!(!file.relock_fresh()?);
+    fs::remove_file(path)?;
+    let mut other = LockFile::open(path)?;
+    assert!(!file.validate()?);
+    assert!(other.validate()?);
+
+    // The lock on the removed file no longer excludes the new file.
+    assert!(other.try_lock()?);
+    other.unlock()?;
+    assert!(file.relock_fresh()?);
+    assert!(file.validate()?);
+    assert!(file.owns_lock());
+    assert!(!other.try_lock()?);
+    file.unlock()?;
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn break_lock_leaves_tombstone() -> Result<(), Error> {
+    use crate::LockInfo;
+    use std::fs;
+
+    let path = "testfiles/break_lock_leaves_tombstone.lock";
+    let mut file = LockFile::open(path)?;
+    file.lock_with_pid()?;
+    let broken = LockFile::break_lock(path)?;
+    assert!(broken.held);
+    assert_eq!(broken.info.map(|info| info.pid), Some(std::process::id()));
+
+    let tombstone = fs::read_to_string(path)?;
+    assert!(tombstone.starts_with("broken="));
+    assert_eq!(LockInfo::read_from(path)?, None);
+    let mut other = LockFile::open(path)?;
+    assert!(other.try_lock()?);
+    other.unlock()?;
+    file.unlock()?;
+
+    let broken = LockFile::break_lock(path)?;
+    assert!(!broken.held);
+    assert_eq!(broken.owner, None);
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn open_dir_locks_directory() -> Result<(), Error> {
+    let path = "testfiles/open_dir_locks_directory";
+    std::fs::create_dir_all(path)?;
+    let mut dir = LockFile::open_dir(path)?;
+    let mut other = LockFile::open_dir(path)?;
+    dir.lock()?;
+    assert!(!other.try_lock()?);
+    assert!(!other.try_lock_shared()?);
+    dir.unlock()?;
+    assert!(other.try_lock_shared()?);
+    other.unlock()?;
+    assert!(LockFile::open_dir("testfiles/open_dir_locks_directory/missing")
+        .is_err());
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn tree_lock_intentions() -> Result<(), Error> {
+    use crate::TreeLock;
+
+    let path = "testfiles/tree_lock_intentions";
+    std::fs::create_dir_all(path)?;
+    let mut tree = TreeLock::open(path)?;
+    let mut other_tree = TreeLock::open(path)?;
+    let mut glyph = tree.child("a.glif")?;
+    let mut other_glyph = tree.child("b.glif")?;
+    let mut same_glyph = other_tree.child("a.glif")?;
+    assert!(tree.child("../a.glif").is_err());
+
+    glyph.lock()?;
+    assert!(other_glyph.try_lock()?);
+    assert!(!same_glyph.try_lock_shared()?);
+    assert!(!tree.try_lock_shared()?);
+    assert!(!tree.try_lock()?);
+    glyph.unlock()?;
+    other_glyph.unlock()?;
+
+    tree.lock_shared()?;
+    assert!(other_tree.try_lock_shared()?);
+    assert!(glyph.try_lock_shared()?);
+    assert!(!other_glyph.try_lock()?);
+    glyph.unlock()?;
+    other_tree.unlock()?;
+    tree.unlock()?;
+
+    tree.lock()?;
+    assert!(!glyph.try_lock_shared()?);
+    assert!(!other_tree.try_lock_shared()?);
+    tree.unlock()?;
+    assert!(glyph.try_lock()?);
+    glyph.unlock()?;
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn file_ext_locks_open_file() -> Result<(), Error> {
+    use crate::FileExt;
+    use std::fs::File;
+
+    let path = "testfiles/file_ext_locks_open_file.dat";
+    let file = File::create(path)?;
+    let other = File::open(path)?;
+    file.lock_exclusive()?;
+    assert!(!other.try_lock_exclusive()?);
+    assert!(!FileExt::try_lock_shared(&other)?);
+    FileExt::unlock(&file)?;
+    assert!(FileExt::try_lock_shared(&file)?);
+    assert!(FileExt::try_lock_shared(&other)?);
+    #[cfg(unix)]
+    {
+        let backend = crate::LockBackend::Flock;
+        assert!(!LockFile::open_with_backend(path, backend)?.try_lock()?);
+    }
+    FileExt::unlock(&file)?;
+    FileExt::unlock(&other)?;
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn raw_locks_borrowed_descriptors() -> Result<(), Error> {
+    use crate::raw;
+    use std::{fs::File, sync::Arc};
+
+    let path = "testfiles/raw_locks_borrowed_descriptors.dat";
+    let file = Arc::new(File::create(path)?);
+    let other = File::open(path)?;
+    raw::lock_shared(&*file)?;
+    assert!(raw::try_lock_shared(&other)?);
+    assert!(!raw::try_lock_exclusive(&other)?);
+    raw::unlock(&*file)?;
+    assert!(raw::try_lock_exclusive(&other)?);
+    raw::unlock(&other)?;
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn lock_file_into_owned_file() -> Result<(), Error> {
+    use std::{fs::File, io::Write};
+
+    let path = "testfiles/lock_file_into_owned_file.lock";
+    let mut lock = LockFile::open(path)?;
+    let mut other = LockFile::open(path)?;
+    lock.lock()?;
+    let mut file = File::from(lock);
+    file.write_all(b"kept")?;
+    assert!(!other.try_lock()?);
+    drop(file);
+    assert!(other.try_lock()?);
+    assert_eq!(other.read_contents()?, b"kept");
+    other.unlock()?;
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn lock_file_borrows_fd() -> Result<(), Error> {
+    use crate::sys;
+    use std::os::unix::io::{AsFd, AsRawFd};
+
+    let path = "testfiles/lock_file_borrows_fd.lock";
+    let mut file = LockFile::open(path)?;
+    file.lock()?;
+    let borrowed = file.as_fd().try_clone_to_owned()?;
+    assert_eq!(sys::file_id(borrowed.as_raw_fd())?, sys::file_id(file.desc)?);
+    assert_eq!(file.as_raw_fd(), file.desc);
+    drop(borrowed);
+    assert!(file.owns_lock());
+    file.unlock()?;
+    Ok(())
+}
+
+#[test]
+fn into_raw_round_trip() -> Result<(), Error> {
+    let path = "testfiles/into_raw_round_trip.lock";
+    let mut file = LockFile::open(path)?;
+    let mut other = LockFile::open(path)?;
+    file.lock()?;
+    let desc = file.into_raw();
+    assert!(!other.try_lock()?);
+    let mut file = unsafe { LockFile::from_raw(desc, path, true)? };
+    assert!(file.owns_lock());
+    assert!(!other.try_lock()?);
+    file.unlock()?;
+    let desc = file.into_raw();
+    assert!(other.try_lock()?);
+    other.unlock()?;
+    let file = unsafe { LockFile::from_raw(desc, path, false)? };
+    assert!(!file.owns_lock());
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn from_raw_restores_lock_state() -> Result<(), Error> {
+    let path = "testfiles/from_raw_restores_lock_state.lock";
+    let mut file = LockFile::open(path)?;
+    file.lock()?;
+    let desc = file.into_raw();
+    let mut file = unsafe { LockFile::from_raw(desc, path, true)? };
+    assert_eq!(file.acquisitions(), 1);
+    file.unlock()?;
+    assert!(file.time_held() > std::time::Duration::ZERO);
+    Ok(())
+}
+
+#[test]
+fn try_clone_observes_lock() -> Result<(), Error> {
+    let path = "testfiles/try_clone_observes_lock.lock";
+    let mut file = LockFile::open(path)?;
+    let mut other = LockFile::open(path)?;
+    file.lock()?;
+    let observer = file.try_clone()?;
+    assert!(!observer.owns_lock());
+    drop(observer);
+    assert!(!other.try_lock()?);
+    file.unlock()?;
+    assert!(other.try_lock()?);
+    other.unlock()?;
+    Ok(())
+}
+
+#[test]
+fn checked_lock_rejects_observer() -> Result<(), Error> {
+    use crate::LockError;
+
+    let path = "testfiles/checked_lock_rejects_observer.lock";
+    let file = LockFile::open(path)?;
+    let mut observer = file.try_clone()?;
+    assert!(matches!(observer.checked_lock(), Err(LockError::Observer)));
+    assert!(matches!(observer.checked_try_lock(), Err(LockError::Observer)));
+    assert!(!observer.owns_lock());
+    Ok(())
+}
+
+#[test]
+fn disown_keeps_lock() -> Result<(), Error> {
+    let path = "testfiles/disown_keeps_lock.lock";
+    let mut file = LockFile::open(path)?;
+    let mut other = LockFile::open(path)?;
+    file.lock()?;
+    file.disown();
+    assert!(!other.try_lock()?);
+    assert!(!other.try_lock_shared()?);
+    Ok(())
+}
+
+#[test]
+fn keep_locked_on_drop_leaks_lock() -> Result<(), Error> {
+    let path = "testfiles/keep_locked_on_drop_leaks_lock.lock";
+    let mut other = LockFile::open(path)?;
+    let mut file = LockFile::open(path)?;
+    file.keep_locked_on_drop(true);
+    file.lock()?;
+    file.unlock()?;
+    drop(file);
+    assert!(other.try_lock()?);
+    other.unlock()?;
+
+    let mut file = LockFile::open(path)?;
+    file.keep_locked_on_drop(true);
+    file.lock()?;
+    drop(file);
+    assert!(!other.try_lock()?);
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn spawn_holding_passes_lock() -> Result<(), Error> {
+    use std::process::{Command, Stdio};
+
+    let path = "testfiles/spawn_holding_passes_lock.lock";
+    let mut file = LockFile::open(path)?;
+    file.lock()?;
+    let mut command = Command::new("cargo");
+    command
+        .arg("run")
+        .arg("-q")
+        .arg("--example")
+        .arg("inherit")
+        .stdout(Stdio::piped());
+    let output = file.spawn_holding(&mut command)?.wait_with_output()?;
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"INHERITED\n");
+    assert!(!LockFile::open(path)?.try_lock()?);
+    file.unlock()?;
+    assert!(LockFile::open(path)?.try_lock()?);
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn after_fork_child_keeps_parent_lock() -> Result<(), Error> {
+    use crate::LockBackend;
+
+    let path = "testfiles/after_fork_child_keeps_parent_lock.lock";
+    let mut file = LockFile::open_with_backend(path, LockBackend::Flock)?;
+    file.lock()?;
+    match unsafe { libc::fork() } {
+        -1 => return Err(Error::last_os_error()),
+        0 => {
+            let detached = file.after_fork_child(false).is_ok()
+                && !file.owns_lock()
+                && file.try_lock().is_ok_and(|locked| !locked);
+            drop(file);
+            unsafe { libc::_exit(if detached { 0 } else { 1 }) };
+        },
+        child => {
+            let mut status = 0;
+            assert_eq!(unsafe { libc::waitpid(child, &mut status, 0) }, child);
+            assert!(libc::WIFEXITED(status));
+            assert_eq!(libc::WEXITSTATUS(status), 0);
+        },
+    }
+    let mut other = LockFile::open_with_backend(path, LockBackend::Flock)?;
+    assert!(!other.try_lock()?);
+    file.unlock()?;
+    assert!(other.try_lock()?);
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn lock_retries_interrupted() -> Result<(), Error> {
+    use crate::LockFileOptions;
+    use std::{
+        io::ErrorKind,
+        os::unix::thread::JoinHandleExt,
+        thread,
+        time::Duration,
+    };
+
+    extern "C" fn ignore(_: libc::c_int) {}
+
+    // Without SA_RESTART, blocked system calls fail with EINTR.
+    unsafe {
+        let mut action: libc::sigaction = core::mem::zeroed();
+        action.sa_sigaction = ignore as extern "C" fn(libc::c_int) as usize;
+        libc::sigaction(libc::SIGUSR1, &action, core::ptr::null_mut());
+    }
+    let path = "testfiles/lock_retries_interrupted.lock";
+    let mut holder = LockFile::open(path)?;
+    holder.lock()?;
+    let spawn = |retry: bool| {
+        thread::spawn(move || {
+            let mut file =
+                LockFileOptions::new().retry_interrupted(retry).open(path)?;
+            file.lock()
+        })
+    };
+
+    let waiter = spawn(false);
+    while !waiter.is_finished() {
+        unsafe { libc::pthread_kill(waiter.as_pthread_t(), libc::SIGUSR1) };
+        thread::sleep(Duration::from_millis(20));
+    }
+    let error = waiter.join().unwrap().unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::Interrupted);
+
+    let waiter = spawn(true);
+    for _ in 0 .. 5 {
+        thread::sleep(Duration::from_millis(20));
+        unsafe { libc::pthread_kill(waiter.as_pthread_t(), libc::SIGUSR1) };
+    }
+    assert!(!waiter.is_finished());
+    holder.unlock()?;
+    waiter.join().unwrap()?;
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn lock_cancelable_stops_waiting() -> Result<(), Error> {
+    use crate::CancelToken;
+    use std::{thread, time::Duration};
+
+    let path = "testfiles/lock_cancelable_stops_waiting.lock";
+    let mut holder = LockFile::open(path)?;
+    let mut file = LockFile::open(path)?;
+    let token = CancelToken::new();
+    assert!(file.lock_cancelable(&token)?);
+    file.unlock()?;
+
+    holder.lock()?;
+    let canceler = token.clone();
+    let thread = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        canceler.cancel();
+    });
+    assert!(!file.lock_cancelable(&token)?);
+    assert!(token.is_canceled());
+    thread.join().unwrap();
+    holder.unlock()?;
+    assert!(!file.lock_cancelable(&token)?);
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "std"))]
+#[test]
+fn lock_interruptible_gives_up() -> Result<(), Error> {
+    use crate::InterruptibleResult;
+    use std::{os::unix::thread::JoinHandleExt, thread, time::Duration};
+
+    extern "C" fn ignore(_: libc::c_int) {}
+
+    unsafe {
+        let mut action: libc::sigaction = core::mem::zeroed();
+        action.sa_sigaction = ignore as extern "C" fn(libc::c_int) as usize;
+        libc::sigaction(libc::SIGUSR2, &action, core::ptr::null_mut());
+    }
+    let path = "testfiles/lock_interruptible_gives_up.lock";
+    let mut holder = LockFile::open(path)?;
+    holder.lock()?;
+    let waiter = thread::spawn(move || {
+        let mut file = LockFile::open(path)?;
+        let result = file.lock_interruptible()?;
+        Ok::<_, Error>((result, file.owns_lock()))
+    });
+    while !waiter.is_finished() {
+        unsafe { libc::pthread_kill(waiter.as_pthread_t(), libc::SIGUSR2) };
+        thread::sleep(Duration::from_millis(20));
+    }
+    let (result, owned) = waiter.join().unwrap()?;
+    assert_eq!(result, InterruptibleResult::Interrupted);
+    assert!(!owned);
+    holder.unlock()?;
+    let mut file = LockFile::open(path)?;
+    assert!(file.lock_interruptible()?.is_acquired());
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "signals"))]
+#[test]
+fn signals_feature_removes_on_termination() -> Result<(), Error> {
+    use std::{
+        fs::read_to_string,
+        io::{BufRead, BufReader},
+        path::Path,
+        process::{Command, Stdio},
+    };
+
+    let path = "testfiles/signals_feature_removes_on_termination.lock";
+    let mut child = Command::new("cargo")
+        .arg("run")
+        .arg("-q")
+        .arg("--features")
+        .arg("signals")
+        .arg("--example")
+        .arg("signals")
+        .arg("--")
+        .arg(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let mut line = String::new();
+    BufReader::new(child.stdout.take().unwrap()).read_line(&mut line)?;
+    assert_eq!(line, "LOCKED\n");
+
+    let pid: libc::pid_t = read_to_string(path)?.trim().parse().unwrap();
+    assert_eq!(unsafe { libc::kill(pid, libc::SIGTERM) }, 0);
+    assert!(!child.wait()?.success());
+    assert!(!Path::new(path).exists());
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn poison_on_panic_marks_file() -> Result<(), Error> {
+    use std::{fs::read_to_string, thread};
+
+    let path = "testfiles/poison_on_panic_marks_file.lock";
+    let result = thread::spawn(move || -> Result<(), Error> {
+        let mut file = LockFile::open(path)?;
+        file.poison_on_panic(true);
+        file.lock_with_pid()?;
+        panic!("corrupting the protected state");
+    })
+    .join();
+    assert!(result.is_err());
+
+    let contents = read_to_string(path)?;
+    assert!(contents.starts_with(&format!("{}\n", std::process::id())));
+    assert!(contents.ends_with("poisoned\n"));
+
+    let mut file = LockFile::open(path)?;
+    file.poison_on_panic(true);
+    file.lock_with_pid()?;
+    file.unlock()?;
+    assert_eq!(read_to_string(path)?, "");
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn clear_poison_after_repair() -> Result<(), Error> {
+    use std::{fs::read_to_string, thread};
+
+    let path = "testfiles/clear_poison_after_repair.lock";
+    let result = thread::spawn(move || -> Result<(), Error> {
+        let mut file = LockFile::open(path)?;
+        file.poison_on_panic(true);
+        file.lock_with_pid()?;
+        panic!("corrupting the protected state");
+    })
+    .join();
+    assert!(result.is_err());
+
+    let mut file = LockFile::open(path)?;
+    file.poison_on_panic(true);
+    assert!(file.try_lock_with_pid()?);
+    assert!(file.is_poisoned()?);
+    let pid = std::process::id();
+    assert_eq!(read_to_string(path)?, format!("{}\npoisoned\n", pid));
+    file.clear_poison()?;
+    assert!(!file.is_poisoned()?);
+    assert_eq!(read_to_string(path)?, format!("{}\n", pid));
+    file.unlock()?;
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn poison_on_panic_ignores_other_threads() -> Result<(), Error> {
+    use std::{fs, thread};
+
+    let path = "testfiles/poison_on_panic_ignores_other_threads.lock";
+    let _ = fs::remove_file(path);
+    let mut file = LockFile::open(path)?;
+    file.poison_on_panic(true);
+    file.lock_with_pid()?;
+    let result = thread::spawn(|| panic!("unrelated failure")).join();
+    assert!(result.is_err());
+    assert!(!file.is_poisoned()?);
+    file.unlock()?;
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn lock_on_contention_reports_owner() -> Result<(), Error> {
+    use crate::LockOwner;
+    use std::{thread, time::Duration};
+
+    let path = "testfiles/lock_on_contention_reports_owner.lock";
+    let mut file = LockFile::open(path)?;
+    let mut other = LockFile::open(path)?;
+    other.lock_on_contention(None, |_| panic!("lock is not contended"))?;
+    other.unlock()?;
+
+    file.lock_with_pid()?;
+    let waiter = thread::spawn(move || -> Result<Vec<LockOwner>, Error> {
+        let mut owners = Vec::new();
+        let interval = Some(Duration::from_millis(20));
+        other
+            .lock_on_contention(interval, |owner| owners.push(owner.clone()))?;
+        other.unlock()?;
+        Ok(owners)
+    });
+    thread::sleep(Duration::from_millis(200));
+    file.unlock()?;
+
+    let owners = waiter.join().unwrap()?;
+    assert!(owners.len() >= 2);
+    assert_eq!(owners[0].pid, Some(std::process::id()));
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn lock_with_progress_reports_elapsed() -> Result<(), Error> {
+    use std::{thread, time::Duration};
+
+    let path = "testfiles/lock_with_progress_reports_elapsed.lock";
+    let mut file = LockFile::open(path)?;
+    let mut other = LockFile::open(path)?;
+    let interval = Duration::from_millis(20);
+    other.lock_with_progress(interval, |_| panic!("lock is not contended"))?;
+    other.unlock()?;
+
+    file.lock()?;
+    let waiter = thread::spawn(move || -> Result<Vec<Duration>, Error> {
+        let mut reports = Vec::new();
+        other.lock_with_progress(interval, |elapsed| reports.push(elapsed))?;
+        other.unlock()?;
+        Ok(reports)
+    });
+    thread::sleep(Duration::from_millis(200));
+    file.unlock()?;
+
+    let reports = waiter.join().unwrap()?;
+    assert!(reports.len() >= 2);
+    assert!(reports[0] >= interval);
+    assert!(reports.windows(2).all(|pair| pair[1] >= pair[0] + interval));
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn lock_with_progress_clamps_zero_interval() -> Result<(), Error> {
+    use std::{thread, time::Duration};
+
+    let path = "testfiles/lock_with_progress_clamps_zero_interval.lock";
+    let mut file = LockFile::open(path)?;
+    let mut other = LockFile::open(path)?;
+    file.lock()?;
+    let waiter = thread::spawn(move || -> Result<Vec<Duration>, Error> {
+        let mut reports = Vec::new();
+        other.lock_with_progress(Duration::ZERO, |elapsed| {
+            reports.push(elapsed)
+        })?;
+        other.unlock()?;
+        Ok(reports)
+    });
+    thread::sleep(Duration::from_millis(100));
+    file.unlock()?;
+
+    let reports = waiter.join().unwrap()?;
+    let interval = Duration::from_millis(1);
+    assert!(!reports.is_empty());
+    assert!(reports.windows(2).all(|pair| pair[1] >= pair[0] + interval));
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn metrics_account_waits_and_holds() -> Result<(), Error> {
+    use crate::metrics;
+    use std::{thread, time::Duration};
+
+    metrics::enable_registry();
+    let path = "testfiles/metrics_account_waits_and_holds.lock";
+    let mut file = LockFile::open(path)?;
+    let mut other = LockFile::open(path)?;
+    file.lock()?;
+    let waiter = thread::spawn(move || -> Result<LockFile, Error> {
+        other.lock()?;
+        other.unlock()?;
+        Ok(other)
+    });
+    thread::sleep(Duration::from_millis(100));
+    assert!(file.time_held() >= Duration::from_millis(100));
+    file.unlock()?;
+    let other = waiter.join().unwrap()?;
+
+    assert_eq!(file.acquisitions(), 1);
+    assert!(file.time_waited() < Duration::from_millis(50));
+    assert!(file.time_held() >= Duration::from_millis(100));
+    assert_eq!(other.acquisitions(), 1);
+    assert!(other.time_waited() >= Duration::from_millis(50));
+
+    let path = file.path().to_path_buf();
+    let (_, totals) = metrics::snapshot()
+        .into_iter()
+        .find(|(registered, _)| *registered == path)
+        .unwrap();
+    assert_eq!(totals.acquisitions, 2);
+    assert!(totals.time_waited >= other.time_waited());
+    assert!(totals.time_held >= file.time_held());
+    Ok(())
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn tracing_reports_contention() -> Result<(), Error> {
+    use std::{
+        fmt,
+        sync::{Arc, Mutex},
+    };
+    use tracing::{
+        field::{Field, Visit},
+        span,
+        Event,
+        Metadata,
+        Subscriber,
+    };
+
+    /// Records the messages of events.
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    /// Visits the message of an event.
+    struct Message<'messages>(&'messages Mutex<Vec<String>>);
+
+    impl Visit for Message<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            if field.name() == "message" {
+                self.0.lock().unwrap().push(format!("{:?}", value));
+            }
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, _: &span::Attributes<'_>) -> span::Id {
+            span::Id::from_u64(1)
+        }
+
+        fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+
+        fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            event.record(&mut Message(&self.0));
+        }
+
+        fn enter(&self, _: &span::Id) {}
+
+        fn exit(&self, _: &span::Id) {}
+    }
+
+    let messages = Arc::new(Mutex::new(Vec::new()));
+    let recorder = Recorder(messages.clone());
+    tracing::subscriber::with_default(recorder, || -> Result<(), Error> {
+        let path = "testfiles/tracing_reports_contention.lock";
+        let mut file = LockFile::open(path)?;
+        let mut other = LockFile::open(path)?;
+        file.lock()?;
+        assert!(!other.try_lock()?);
+        file.unlock()?;
+        Ok(())
+    })?;
+
+    let messages = messages.lock().unwrap();
+    assert_eq!(
+        *messages,
+        [
+            "opened",
+            "opened",
+            "locking",
+            "acquired",
+            "locking",
+            "contended",
+            "released",
+        ]
+    );
+    Ok(())
+}
+
+#[cfg(feature = "log")]
+#[test]
+fn log_reports_contention() -> Result<(), Error> {
+    use log::{LevelFilter, Log, Metadata, Record};
+    use std::sync::Mutex;
+
+    /// Records the messages about the lock file of this test.
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl Log for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record<'_>) {
+            let message = record.args().to_string();
+            if message.contains("log_reports_contention") {
+                self.0.lock().unwrap().push(message);
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static RECORDER: Recorder = Recorder(Mutex::new(Vec::new()));
+    log::set_logger(&RECORDER).unwrap();
+    log::set_max_level(LevelFilter::Debug);
+
+    let path = "testfiles/log_reports_contention.lock";
+    let mut file = LockFile::open(path)?;
+    let mut other = LockFile::open(path)?;
+    file.lock()?;
+    assert!(!other.try_lock()?);
+    file.unlock()?;
+
+    let messages = RECORDER.0.lock().unwrap();
+    assert_eq!(messages.len(), 2);
+    assert!(messages[0].starts_with("acquired exclusive lock on "));
+    assert!(messages[1].contains(" is locked by "));
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn audit_log_records_holders() -> Result<(), Error> {
+    use crate::LockFileOptions;
+    use std::fs;
+
+    let path = "testfiles/audit_log_records_holders.lock";
+    let history = "testfiles/audit_log_records_holders.lock.history";
+    let _ = fs::remove_file(history);
+    let mut options = LockFileOptions::new();
+    options.audit_log(true);
+    let mut file = options.open(path)?;
+    let mut other = options.open(path)?;
+    file.lock_with_pid()?;
+    assert!(!other.try_lock()?);
+    file.unlock()?;
+    file.lock_shared()?;
+    other.lock_shared()?;
+    other.unlock()?;
+    file.unlock()?;
+    LockFile::open(path)?.lock()?;
+
+    let history = fs::read_to_string(history)?;
+    let host = crate::sys::hostname()?;
+    let suffix = format!(" pid={} host={} action=", std::process::id(), host);
+    let actions: Vec<_> = history
+        .lines()
+        .map(|line| line.split_once(&suffix).unwrap().1)
+        .collect();
+    assert_eq!(
+        actions,
+        [
+            "acquire-exclusive",
+            "release",
+            "acquire-shared",
+            "acquire-shared",
+            "release",
+            "release",
+        ]
+    );
+    Ok(())
+}
@@ -0,0 +1,231 @@
+//! This module implements lock acquisition bounded in time.
+
+use crate::{retry::Backoff, Error, LockFile, RetryPolicy};
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Shortest interval between progress reports, so short intervals do not
+/// spin.
+const MIN_PROGRESS_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Waits between attempts of a timed lock. On Linux, waiting is done on an
+/// inotify watch, waking up early when the file is closed or modified, which
+/// happens when lock owners drop their handles or truncate their PIDs.
+/// Elsewhere, or if the watch cannot be created, waiting is plain sleeping.
+#[derive(Debug)]
+struct Waiter {
+    /// Watch over the lock file.
+    #[cfg(target_os = "linux")]
+    watch: Option<crate::sys::Watch>,
+}
+
+impl Waiter {
+    /// Prepares to wait on the given file.
+    #[allow(unused_variables)]
+    fn new(file: &LockFile) -> Self {
+        Self {
+            #[cfg(target_os = "linux")]
+            watch: crate::sys::Watch::new(file.desc).ok(),
+        }
+    }
+
+    /// Blocks until the timeout elapses, or possibly earlier if the file
+    /// changes.
+    fn wait(&self, timeout: Duration) {
+        #[cfg(target_os = "linux")]
+        if let Some(watch) = &self.watch {
+            if watch.wait(timeout).is_ok() {
+                return;
+            }
+        }
+        thread::sleep(timeout);
+    }
+}
+
+impl LockFile {
+    /// Locks this file, blocking while it is not possible to lock (i.e.
+    /// someone else already owns a lock), but for no longer than the given
+    /// timeout. Returns whether the lock was acquired. After locked, if no
+    /// attempt to unlock is made, it will be automatically unlocked on the file
+    /// handle drop.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns the file.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    /// use std::time::Duration;
+    ///
+    /// let mut file = LockFile::open("testfiles/timeout.lock")?;
+    /// if file.lock_timeout(Duration::from_secs(1))? {
+    ///     do_stuff();
+    ///     file.unlock()?;
+    /// }
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    ///
+    /// # Panicking Example
+    ///
+    /// ```should_panic
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    /// use std::time::Duration;
+    ///
+    /// let mut file = LockFile::open("testfiles/timeout_panic.lock")?;
+    /// file.lock()?;
+    /// file.lock_timeout(Duration::from_secs(1))?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn lock_timeout(&mut self, timeout: Duration) -> Result<bool, Error> {
+        match Instant::now().checked_add(timeout) {
+            Some(deadline) => self.try_lock_until(deadline),
+            None => {
+                self.lock()?;
+                Ok(true)
+            },
+        }
+    }
+
+    /// Locks this file, blocking while it is not possible to lock (i.e.
+    /// someone else already owns a lock), but not past the given deadline.
+    /// Returns whether the lock was acquired. If the deadline has already
+    /// passed, a single attempt is made, like [`LockFile::try_lock`]. After
+    /// locked, if no attempt to unlock is made, it will be automatically
+    /// unlocked on the file handle drop.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns the file.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let deadline = Instant::now() + Duration::from_secs(1);
+    /// let mut file = LockFile::open("testfiles/deadline.lock")?;
+    /// if file.try_lock_until(deadline)? {
+    ///     do_stuff();
+    ///     file.unlock()?;
+    /// }
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    ///
+    /// # Panicking Example
+    ///
+    /// ```should_panic
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    /// use std::time::Instant;
+    ///
+    /// let mut file = LockFile::open("testfiles/deadline_panic.lock")?;
+    /// file.lock()?;
+    /// file.try_lock_until(Instant::now())?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn try_lock_until(&mut self, deadline: Instant) -> Result<bool, Error> {
+        self.waiting(|file| {
+            let policy = RetryPolicy::default();
+            let mut backoff = Backoff::new(&policy);
+            let mut waiter = None;
+            loop {
+                if file.try_lock()? {
+                    return Ok(true);
+                }
+                let now = Instant::now();
+                let Some(delay) =
+                    backoff.next_delay().filter(|_| now < deadline)
+                else {
+                    return Ok(false);
+                };
+                waiter
+                    .get_or_insert_with(|| Waiter::new(file))
+                    .wait(delay.min(deadline - now));
+            }
+        })
+    }
+
+    /// Locks this file like [`LockFile::lock`], calling the given callback
+    /// with the time spent waiting so far after every interval while it is
+    /// not possible to lock, such as to render a spinner. The callback is not
+    /// called if the lock is acquired immediately. While waiting, attempts are
+    /// retried every few milliseconds. Intervals shorter than a millisecond,
+    /// including zero, are treated as a millisecond.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns the file.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fslock::Error> {
+    /// use fslock::LockFile;
+    /// use std::time::Duration;
+    ///
+    /// let mut file = LockFile::open("testfiles/lock_with_progress.lock")?;
+    /// let interval = Duration::from_millis(100);
+    /// file.lock_with_progress(interval, |elapsed| {
+    ///     eprint!("\rWaiting for the lock... {}s", elapsed.as_secs());
+    /// })?;
+    /// do_stuff();
+    /// file.unlock()?;
+    ///
+    /// # Ok(())
+    /// # }
+    /// # fn do_stuff() {
+    /// #    // doing stuff here.
+    /// # }
+    /// ```
+    pub fn lock_with_progress<F>(
+        &mut self,
+        interval: Duration,
+        mut callback: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(Duration),
+    {
+        let interval = interval.max(MIN_PROGRESS_INTERVAL);
+        self.waiting(|file| {
+            let policy = RetryPolicy::default();
+            let mut backoff = Backoff::new(&policy);
+            let mut waiter = None;
+            let start = Instant::now();
+            let mut report = interval;
+            loop {
+                if file.try_lock()? {
+                    return Ok(());
+                }
+                let mut elapsed = start.elapsed();
+                if elapsed >= report {
+                    callback(elapsed);
+                    elapsed = start.elapsed();
+                    report = elapsed.saturating_add(interval);
+                }
+                let delay = backoff.next_delay().unwrap_or(policy.max_delay);
+                waiter
+                    .get_or_insert_with(|| Waiter::new(file))
+                    .wait(delay.min(report - elapsed));
+            }
+        })
+    }
+}
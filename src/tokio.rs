@@ -0,0 +1,42 @@
+//! This module implements an asynchronous lock file for the Tokio runtime.
+//! Blocking system calls are offloaded to Tokio's blocking thread pool, so
+//! they do not stall the runtime's workers.
+//!
+//! # Example
+//! ```
+//! # fn main() -> Result<(), fslock::Error> {
+//! # let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+//! # runtime.block_on(async {
+//! use fslock::tokio::LockFile;
+//!
+//! let mut file = LockFile::open("testfiles/tokio.lock").await?;
+//! file.lock().await?;
+//! do_stuff();
+//! file.unlock().await?;
+//! # Ok(())
+//! # })
+//! # }
+//! # fn do_stuff() {
+//! #    // doing stuff here.
+//! # }
+//! ```
+
+use crate::Error;
+use std::{io, panic};
+
+crate::async_file::async_lock_file!();
+
+/// Runs a blocking function on the blocking pool, resuming its panics.
+async fn blocking<F, T>(function: F) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error> + Send + 'static,
+    T: Send + 'static,
+{
+    match ::tokio::task::spawn_blocking(function).await {
+        Ok(result) => result,
+        Err(error) if error.is_panic() => {
+            panic::resume_unwind(error.into_panic())
+        },
+        Err(error) => Err(io::Error::other(error)),
+    }
+}
@@ -0,0 +1,125 @@
+//! This module implements reporting of what handles do. With the feature
+//! `tracing`, events report opening, lock attempts, acquisitions, contention,
+//! releases, steals and errors, with the path and the PID of the owner as
+//! fields. With the lighter feature `log`, records report acquisitions,
+//! contention, steals and failures to lock or unlock. Without either, the
+//! hooks do nothing.
+
+#[cfg(any(feature = "tracing", feature = "log"))]
+use crate::sys;
+use crate::{Error, LockFile, OsStr};
+
+/// Reports that the file at the given path could not be opened, returning
+/// the error.
+#[allow(unused_variables)]
+pub(crate) fn open_failed(path: &OsStr, error: Error) -> Error {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(path = %path, %error, "failed to open lock file");
+    error
+}
+
+impl LockFile {
+    /// Reports that this handle was opened.
+    pub(crate) fn trace_opened(&self) {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(path = %self.path(), created = self.created, "opened");
+    }
+
+    /// Reports an attempt to lock, exclusively or shared, blocking or not.
+    #[allow(unused_variables)]
+    pub(crate) fn trace_attempt(&self, shared: bool, blocking: bool) {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(path = %self.path(), shared, blocking, "locking");
+    }
+
+    /// Reports that this handle acquired or released its lock.
+    #[allow(unused_variables)]
+    pub(crate) fn trace_locked(&self, locked: bool) {
+        #[cfg(feature = "tracing")]
+        if locked {
+            tracing::debug!(
+                path = %self.path(),
+                shared = self.shared,
+                pid = sys::pid(),
+                "acquired",
+            );
+        } else {
+            tracing::debug!(path = %self.path(), "released");
+        }
+        #[cfg(feature = "log")]
+        if locked {
+            log::debug!(
+                "acquired {} lock on {} (pid {})",
+                if self.shared { "shared" } else { "exclusive" },
+                self.path(),
+                sys::pid(),
+            );
+        }
+    }
+
+    /// Reports the outcome of a non-blocking attempt, unless it succeeded.
+    pub(crate) fn trace_outcome(&self, result: &Result<bool, Error>) {
+        match result {
+            Ok(true) => {},
+            Ok(false) => self.trace_contended(),
+            Err(error) => self.trace_failure("lock", error),
+        }
+    }
+
+    /// Reports that the lock is owned by someone else.
+    pub(crate) fn trace_contended(&self) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            path = %self.path(),
+            owner = ?self.blocking_owner().ok().flatten(),
+            "contended",
+        );
+        #[cfg(feature = "log")]
+        if log::log_enabled!(log::Level::Debug) {
+            match self.blocking_owner() {
+                Ok(Some(owner)) => {
+                    log::debug!(
+                        "{} is locked by process {}",
+                        self.path(),
+                        owner
+                    )
+                },
+                _ => log::debug!("{} is locked by someone else", self.path()),
+            }
+        }
+    }
+
+    /// Reports an attempt to steal the lock held on behalf of the given dead
+    /// process.
+    #[cfg(all(unix, feature = "std"))]
+    #[allow(unused_variables)]
+    pub(crate) fn trace_steal(&self, pid: u32) {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(path = %self.path(), owner = pid, "stealing");
+        #[cfg(feature = "log")]
+        log::warn!(
+            "stealing lock on {} from dead process {}",
+            self.path(),
+            pid
+        );
+    }
+
+    /// Reports that the given operation failed, returning the error.
+    pub(crate) fn trace_error(
+        &self,
+        operation: &'static str,
+        error: Error,
+    ) -> Error {
+        self.trace_failure(operation, &error);
+        error
+    }
+
+    /// Reports that the given operation failed with the given error.
+    #[allow(unused_variables)]
+    fn trace_failure(&self, operation: &'static str, error: &Error) {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(path = %self.path(), operation, %error, "failed");
+        #[cfg(feature = "log")]
+        log::warn!("failed to {} {}: {}", operation, self.path(), error);
+    }
+}
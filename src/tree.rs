@@ -0,0 +1,392 @@
+//! This module implements hierarchical locking of directory trees, where locks
+//! on a whole tree and locks on files within it cooperate through intention
+//! locks, like in databases locking tables and rows.
+//!
+//! Locking a child first takes an intention lock on the tree: intention to
+//! share (IS) for shared child locks, and intention to lock exclusively (IX)
+//! for exclusive ones. The compatibility of tree locks is the usual one:
+//!
+//! | held \ wanted | IS  | IX  | S   | X   |
+//! |---------------|-----|-----|-----|-----|
+//! | IS            | yes | yes | yes | no  |
+//! | IX            | yes | yes | no  | no  |
+//! | S             | yes | no  | yes | no  |
+//! | X             | no  | no  | no  | no  |
+//!
+//! Every mode but X holds the directory shared (see [`LockFile::open_dir`]),
+//! which X holds exclusively. S and IX holders additionally hold shared locks
+//! on the `.tree-shared.lock` and `.tree-intent.lock` files inside the
+//! directory, respectively. Joining either group requires an exclusive lock on
+//! the file of the other group, briefly taken under the `.tree-gate.lock`
+//! file, so no one joins a group while another checks that it is empty.
+
+use crate::{Error, LockFile, ToOsStr};
+use std::{
+    io::ErrorKind,
+    path::{Component, Path, PathBuf},
+};
+
+/// Modes of locks on a tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// Intention to share some children (IS).
+    IntentShared,
+    /// Intention to lock some children exclusively (IX).
+    IntentExclusive,
+    /// The whole tree, shared (S).
+    Shared,
+    /// The whole tree, exclusively (X).
+    Exclusive,
+}
+
+/// Locks of a single file, blocking or not, shared or not.
+fn lock(file: &mut LockFile, shared: bool, block: bool) -> Result<bool, Error> {
+    match (shared, block) {
+        (false, false) => file.try_lock(),
+        (false, true) => file.lock().map(|()| true),
+        (true, false) => file.try_lock_shared(),
+        (true, true) => file.lock_shared().map(|()| true),
+    }
+}
+
+/// Files locked to lock a tree in some mode.
+#[derive(Debug)]
+struct Root {
+    /// The directory itself.
+    tree: LockFile,
+    /// Serializes joining the shared or intent groups.
+    gate: LockFile,
+    /// Locked shared by S holders.
+    readers: LockFile,
+    /// Locked shared by IX holders.
+    writers: LockFile,
+}
+
+impl Root {
+    /// Opens the files locking the tree at the given directory.
+    fn open(root: &Path) -> Result<Self, Error> {
+        Ok(Self {
+            tree: LockFile::open_dir(root)?,
+            gate: LockFile::open(root.join(".tree-gate.lock").as_path())?,
+            readers: LockFile::open(root.join(".tree-shared.lock").as_path())?,
+            writers: LockFile::open(root.join(".tree-intent.lock").as_path())?,
+        })
+    }
+
+    /// Locks the tree in the given mode, blocking if `block` is set. Returns
+    /// whether it was locked.
+    fn acquire(&mut self, mode: Mode, block: bool) -> Result<bool, Error> {
+        if !lock(&mut self.tree, mode != Mode::Exclusive, block)? {
+            return Ok(false);
+        }
+        let result = match mode {
+            Mode::IntentShared | Mode::Exclusive => Ok(true),
+            Mode::IntentExclusive => self.join(false, block),
+            Mode::Shared => self.join(true, block),
+        };
+        if !matches!(result, Ok(true)) {
+            let _ = self.tree.unlock();
+        }
+        result
+    }
+
+    /// Joins the S holders if `readers` is set, or the IX holders otherwise,
+    /// once no one holds the tree in the other mode.
+    fn join(&mut self, readers: bool, block: bool) -> Result<bool, Error> {
+        if !lock(&mut self.gate, false, block)? {
+            return Ok(false);
+        }
+        let (own, other) = if readers {
+            (&mut self.readers, &mut self.writers)
+        } else {
+            (&mut self.writers, &mut self.readers)
+        };
+        let result = lock(other, false, block).and_then(|empty| {
+            if !empty {
+                return Ok(false);
+            }
+            // Only entrants holding the gate lock either file exclusively, so
+            // this never blocks.
+            let joined = own.lock_shared();
+            let _ = other.unlock();
+            joined.map(|()| true)
+        });
+        let _ = self.gate.unlock();
+        result
+    }
+
+    /// Unlocks the tree locked in the given mode.
+    fn release(&mut self, mode: Mode) -> Result<(), Error> {
+        match mode {
+            Mode::IntentExclusive => self.writers.unlock()?,
+            Mode::Shared => self.readers.unlock()?,
+            Mode::IntentShared | Mode::Exclusive => (),
+        }
+        self.tree.unlock()
+    }
+}
+
+/// A lock on a directory tree, such as a font package, which may be locked as
+/// a whole, or file by file through [`TreeLock::child`] handles. Locks on the
+/// whole tree and locks on its files cooperate: locking the whole tree
+/// exclusively waits for and excludes all locks on its files, locking it
+/// shared waits for and excludes exclusive locks on its files, and files are
+/// otherwise locked independently of each other.
+///
+/// Handles are independent of each other, even in the same process: a process
+/// holding the whole tree exclusively and locking one of its files deadlocks.
+///
+/// # Example
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// use fslock::TreeLock;
+///
+/// # std::fs::create_dir_all("testfiles/tree.ufo/glyphs")?;
+/// let mut font = TreeLock::open("testfiles/tree.ufo")?;
+/// let mut glyph = font.child("glyphs/a.glif")?;
+///
+/// glyph.lock()?;
+/// assert!(!font.try_lock_shared()?);
+/// glyph.unlock()?;
+///
+/// font.lock()?;
+/// do_stuff();
+/// font.unlock()?;
+///
+/// # Ok(())
+/// # }
+/// # fn do_stuff() {
+/// #    // doing stuff here.
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct TreeLock {
+    /// Path of the root directory.
+    path: PathBuf,
+    /// Files locking the tree.
+    root: Root,
+    /// Mode of the lock owned by this handle.
+    mode: Option<Mode>,
+}
+
+impl TreeLock {
+    /// Opens the tree rooted at the given directory for locking, creating the
+    /// lock files of the tree inside it if needed. The directory must exist.
+    ///
+    /// # Panics
+    /// Panics if the path contains a nul-byte in a place other than the end.
+    pub fn open<P>(path: &P) -> Result<Self, Error>
+    where
+        P: ToOsStr + ?Sized,
+    {
+        let path = path.to_os_str()?.to_path_buf();
+        let root = Root::open(&path)?;
+        Ok(Self { path, root, mode: None })
+    }
+
+    /// Returns the path of the root directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns whether this handle owns a lock on the whole tree.
+    pub fn owns_lock(&self) -> bool {
+        self.mode.is_some()
+    }
+
+    /// Opens a handle to lock the file at the given path, relative to the root
+    /// directory, through its companion lock file (see [`LockFile::sidecar`]).
+    /// The file itself need not exist. Fails with
+    /// [`std::io::ErrorKind::InvalidInput`] if the path is absolute or leaves
+    /// the tree.
+    ///
+    /// # Panics
+    /// Panics if the path contains a nul-byte in a place other than the end.
+    pub fn child<P>(&self, path: &P) -> Result<TreeChild, Error>
+    where
+        P: ToOsStr + ?Sized,
+    {
+        let relative = path.to_os_str()?.to_path_buf();
+        let inside = relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+        if !inside {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "child path must be relative and inside the tree",
+            ));
+        }
+        let path = self.path.join(relative);
+        Ok(TreeChild {
+            file: LockFile::sidecar(path.as_path())?,
+            root: Root::open(&self.path)?,
+            path,
+            mode: None,
+        })
+    }
+
+    /// Locks this handle's lock on the whole tree in the given mode.
+    fn acquire(&mut self, mode: Mode, block: bool) -> Result<bool, Error> {
+        if self.mode.is_some() {
+            panic!("Cannot lock if already owning a lock");
+        }
+        let locked = self.root.acquire(mode, block)?;
+        if locked {
+            self.mode = Some(mode);
+        }
+        Ok(locked)
+    }
+
+    /// Locks the whole tree exclusively. Blocks while someone else owns a lock
+    /// on the tree or on any of its files.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns a lock on the tree.
+    pub fn lock(&mut self) -> Result<(), Error> {
+        self.acquire(Mode::Exclusive, true)?;
+        Ok(())
+    }
+
+    /// Locks the whole tree exclusively, unless someone else owns a lock on
+    /// the tree or on any of its files. Does NOT block. Returns whether the
+    /// tree was locked.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns a lock on the tree.
+    pub fn try_lock(&mut self) -> Result<bool, Error> {
+        self.acquire(Mode::Exclusive, false)
+    }
+
+    /// Locks the whole tree shared. Blocks while someone else owns an
+    /// exclusive lock on the tree or on any of its files.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns a lock on the tree.
+    pub fn lock_shared(&mut self) -> Result<(), Error> {
+        self.acquire(Mode::Shared, true)?;
+        Ok(())
+    }
+
+    /// Locks the whole tree shared, unless someone else owns an exclusive lock
+    /// on the tree or on any of its files. Does NOT block. Returns whether the
+    /// tree was locked.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns a lock on the tree.
+    pub fn try_lock_shared(&mut self) -> Result<bool, Error> {
+        self.acquire(Mode::Shared, false)
+    }
+
+    /// Unlocks the whole tree.
+    ///
+    /// # Panics
+    /// Panics if this handle does not own a lock on the tree.
+    pub fn unlock(&mut self) -> Result<(), Error> {
+        let Some(mode) = self.mode.take() else {
+            panic!("Attempted to unlock already unlocked lockfile");
+        };
+        self.root.release(mode)
+    }
+}
+
+/// A handle to lock a file within a [`TreeLock`], obtained through
+/// [`TreeLock::child`]. Locking the file also takes the matching intention
+/// lock on the tree, so it waits for and excludes conflicting locks on the
+/// whole tree, but not locks on other files.
+#[derive(Debug)]
+pub struct TreeChild {
+    /// Path of the file.
+    path: PathBuf,
+    /// Companion lock file of the file.
+    file: LockFile,
+    /// Files locking the tree.
+    root: Root,
+    /// Mode of the intention lock owned on the tree.
+    mode: Option<Mode>,
+}
+
+impl TreeChild {
+    /// Returns the path of the file, joined to the root directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns whether this handle owns a lock on the file.
+    pub fn owns_lock(&self) -> bool {
+        self.mode.is_some()
+    }
+
+    /// Takes the given intention lock on the tree, and locks the file.
+    fn acquire(&mut self, mode: Mode, block: bool) -> Result<bool, Error> {
+        if self.mode.is_some() {
+            panic!("Cannot lock if already owning a lock");
+        }
+        if !self.root.acquire(mode, block)? {
+            return Ok(false);
+        }
+        let shared = mode == Mode::IntentShared;
+        match lock(&mut self.file, shared, block) {
+            Ok(true) => {
+                self.mode = Some(mode);
+                Ok(true)
+            },
+            result => {
+                let _ = self.root.release(mode);
+                result
+            },
+        }
+    }
+
+    /// Locks the file exclusively. Blocks while someone else owns a lock on
+    /// the file, or on the whole tree.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns a lock on the file.
+    pub fn lock(&mut self) -> Result<(), Error> {
+        self.acquire(Mode::IntentExclusive, true)?;
+        Ok(())
+    }
+
+    /// Locks the file exclusively, unless someone else owns a lock on the
+    /// file, or on the whole tree. Does NOT block. Returns whether the file
+    /// was locked.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns a lock on the file.
+    pub fn try_lock(&mut self) -> Result<bool, Error> {
+        self.acquire(Mode::IntentExclusive, false)
+    }
+
+    /// Locks the file shared. Blocks while someone else owns an exclusive
+    /// lock on the file, or on the whole tree.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns a lock on the file.
+    pub fn lock_shared(&mut self) -> Result<(), Error> {
+        self.acquire(Mode::IntentShared, true)?;
+        Ok(())
+    }
+
+    /// Locks the file shared, unless someone else owns an exclusive lock on
+    /// the file, or on the whole tree. Does NOT block. Returns whether the
+    /// file was locked.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns a lock on the file.
+    pub fn try_lock_shared(&mut self) -> Result<bool, Error> {
+        self.acquire(Mode::IntentShared, false)
+    }
+
+    /// Unlocks the file, and the intention lock on the tree.
+    ///
+    /// # Panics
+    /// Panics if this handle does not own a lock on the file.
+    pub fn unlock(&mut self) -> Result<(), Error> {
+        let Some(mode) = self.mode.take() else {
+            panic!("Attempted to unlock already unlocked lockfile");
+        };
+        let unlocked = self.file.unlock();
+        self.root.release(mode)?;
+        unlocked
+    }
+}
@@ -0,0 +1,136 @@
+//! This module implements a typestate API over [`crate::LockFile`], where
+//! whether the handle owns the lock is part of its type. Locking consumes a
+//! [`LockFile<Unlocked>`] and gives a [`LockFile<Locked>`] back, and only the
+//! latter can be unlocked, so locking twice or unlocking without locking are
+//! compile-time errors instead of panics.
+//!
+//! # Example
+//! ```
+//! # fn main() -> Result<(), fslock::Error> {
+//! use fslock::typestate::LockFile;
+//!
+//! let file = LockFile::open("testfiles/typestate.lock")?;
+//! let file = file.lock()?;
+//! do_stuff();
+//! let _file = file.unlock()?;
+//!
+//! # Ok(())
+//! # }
+//! # fn do_stuff() {
+//! #    // doing stuff here.
+//! # }
+//! ```
+//!
+//! ```compile_fail
+//! # fn main() -> Result<(), fslock::Error> {
+//! use fslock::typestate::LockFile;
+//!
+//! let file = LockFile::open("testfiles/typestate_fail.lock")?;
+//! file.lock()?.lock()?;
+//!
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Error, ToOsStr};
+use core::marker::PhantomData;
+
+/// State of a handle which does not own the lock.
+#[derive(Debug)]
+pub enum Unlocked {}
+
+/// State of a handle which owns the lock.
+#[derive(Debug)]
+pub enum Locked {}
+
+/// A handle to a lockable file, either [`Unlocked`] or [`Locked`]. Dropping a
+/// locked handle unlocks and closes the file, like [`crate::LockFile`].
+#[derive(Debug)]
+pub struct LockFile<State> {
+    file: crate::LockFile,
+    _state: PhantomData<State>,
+}
+
+impl<State> LockFile<State> {
+    /// Wraps a handle in the given state.
+    fn wrap(file: crate::LockFile) -> Self {
+        Self { file, _state: PhantomData }
+    }
+
+    /// Gives the underlying handle back.
+    pub fn into_inner(self) -> crate::LockFile {
+        self.file
+    }
+}
+
+impl LockFile<Unlocked> {
+    /// Opens a file for locking, like [`crate::LockFile::open`].
+    ///
+    /// # Panics
+    /// Panics if the path contains a nul-byte in a place other than the end.
+    pub fn open<P>(path: &P) -> Result<Self, Error>
+    where
+        P: ToOsStr + ?Sized,
+    {
+        crate::LockFile::open(path).map(Self::wrap)
+    }
+
+    /// Locks this file, like [`crate::LockFile::lock`].
+    pub fn lock(mut self) -> Result<LockFile<Locked>, Error> {
+        self.file.lock()?;
+        Ok(LockFile::wrap(self.file))
+    }
+
+    /// Locks this file and writes this process's PID into it, like
+    /// [`crate::LockFile::lock_with_pid`].
+    pub fn lock_with_pid(mut self) -> Result<LockFile<Locked>, Error> {
+        self.file.lock_with_pid()?;
+        Ok(LockFile::wrap(self.file))
+    }
+
+    /// Locks this file if possible, like [`crate::LockFile::try_lock`],
+    /// giving the unlocked handle back if someone else owns the lock.
+    pub fn try_lock(
+        mut self,
+    ) -> Result<Result<LockFile<Locked>, LockFile<Unlocked>>, Error> {
+        if self.file.try_lock()? {
+            Ok(Ok(LockFile::wrap(self.file)))
+        } else {
+            Ok(Err(self))
+        }
+    }
+}
+
+impl LockFile<Locked> {
+    /// Unlocks this file, like [`crate::LockFile::unlock`].
+    pub fn unlock(mut self) -> Result<LockFile<Unlocked>, Error> {
+        self.file.unlock()?;
+        Ok(LockFile::wrap(self.file))
+    }
+}
+
+impl TryFrom<crate::LockFile> for LockFile<Unlocked> {
+    type Error = crate::LockFile;
+
+    /// Wraps an unlocked handle, giving it back if it owns the lock.
+    fn try_from(file: crate::LockFile) -> Result<Self, crate::LockFile> {
+        if file.owns_lock() {
+            Err(file)
+        } else {
+            Ok(Self::wrap(file))
+        }
+    }
+}
+
+impl TryFrom<crate::LockFile> for LockFile<Locked> {
+    type Error = crate::LockFile;
+
+    /// Wraps a locked handle, giving it back if it does not own the lock.
+    fn try_from(file: crate::LockFile) -> Result<Self, crate::LockFile> {
+        if file.owns_lock() {
+            Ok(Self::wrap(file))
+        } else {
+            Err(file)
+        }
+    }
+}
@@ -0,0 +1,35 @@
+//! This module implements an asynchronous lock file independent of any async
+//! runtime, usable with smol, async-std or plain futures executors. Blocking
+//! system calls are offloaded to the thread pool of the `blocking` crate.
+//!
+//! # Example
+//! ```
+//! # fn main() -> Result<(), fslock::Error> {
+//! # futures_lite::future::block_on(async {
+//! use fslock::unblock::LockFile;
+//!
+//! let mut file = LockFile::open("testfiles/unblock.lock").await?;
+//! file.lock().await?;
+//! do_stuff();
+//! file.unlock().await?;
+//! # Ok(())
+//! # })
+//! # }
+//! # fn do_stuff() {
+//! #    // doing stuff here.
+//! # }
+//! ```
+
+use crate::Error;
+
+crate::async_file::async_lock_file!();
+
+/// Runs a blocking function on the blocking pool. Its panics are resumed by
+/// the pool's task.
+async fn blocking<F, T>(function: F) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error> + Send + 'static,
+    T: Send + 'static,
+{
+    ::blocking::unblock(function).await
+}
@@ -1,8 +1,26 @@
 #[cfg(feature = "std")]
+mod as_fd;
+#[cfg(feature = "std")]
 mod into_file;
+#[cfg(feature = "std")]
+mod semaphore;
+#[cfg(feature = "std")]
+pub use semaphore::NamedSemaphore;
+#[cfg(all(target_os = "linux", feature = "std"))]
+mod robust;
+#[cfg(all(target_os = "linux", feature = "std"))]
+pub use robust::{RobustLockResult, RobustMutex};
 
-use crate::{EitherOsStr, IntoOsString, ToOsStr};
-use core::{fmt, mem::transmute, ptr::NonNull, slice, str};
+use crate::{EitherOsStr, IntoOsString, LockFileOptions, ToOsStr};
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    mem::{self, transmute, MaybeUninit},
+    ptr::{self, NonNull},
+    slice,
+    str,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
 
 #[cfg(feature = "std")]
 use std::{ffi, os::unix::ffi::OsStrExt};
@@ -40,18 +58,26 @@ extern "C" {
 }
 
 #[cfg(not(feature = "std"))]
-fn errno() -> libc::c_int {
+pub(crate) fn errno() -> libc::c_int {
     unsafe { *errno_location() }
 }
 
 #[cfg(feature = "std")]
-fn errno() -> libc::c_int {
+pub(crate) fn errno() -> libc::c_int {
     Error::last_os_error().raw_os_error().unwrap_or(0) as libc::c_int
 }
 
 /// A type representing file descriptor on Unix.
 pub type FileDesc = libc::c_int;
 
+/// A type representing an owned file descriptor on Unix.
+#[cfg(feature = "std")]
+pub type OwnedDesc = std::os::unix::io::OwnedFd;
+
+/// A type representing an owned file descriptor on Unix.
+#[cfg(not(feature = "std"))]
+pub type OwnedDesc = FileDesc;
+
 /// A type representing Process ID on Unix.
 pub type Pid = libc::pid_t;
 
@@ -102,6 +128,12 @@ pub struct OsString {
     len: usize,
 }
 
+// Safe because the allocation is uniquely owned and never mutated.
+unsafe impl Send for OsString {}
+
+// Safe because the allocation is never mutated through shared references.
+unsafe impl Sync for OsString {}
+
 impl Drop for OsString {
     fn drop(&mut self) {
         let ptr = self.alloc.as_ptr() as *mut libc::c_void;
@@ -127,15 +159,33 @@ pub struct OsStr {
 }
 
 impl OsStr {
-    /// Unsafe cause sequence needs to end with 0.
+    /// Unsafe cause sequence needs to be followed by 0, which is not part of
+    /// the slice.
     unsafe fn from_slice(slice: &[libc::c_char]) -> &Self {
         transmute(slice)
     }
+
+    /// Pointer to the nul-terminated sequence, suitable for C APIs.
+    pub(crate) fn as_ptr(&self) -> *const libc::c_char {
+        self.bytes.as_ptr()
+    }
+
+    /// Converts into a standard library path.
+    #[cfg(feature = "std")]
+    pub(crate) fn to_path_buf(&self) -> std::path::PathBuf {
+        let bytes = unsafe {
+            slice::from_raw_parts(
+                self.bytes.as_ptr() as *const u8,
+                self.bytes.len(),
+            )
+        };
+        ffi::OsStr::from_bytes(bytes).into()
+    }
 }
 
 impl fmt::Debug for OsStr {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        let mut first = false;
+        let mut first = true;
         write!(fmt, "[")?;
 
         for &signed in &self.bytes {
@@ -165,7 +215,7 @@ impl fmt::Display for OsStr {
 
         let mut sub = slice;
 
-        while sub.len() > 0 {
+        while !sub.is_empty() {
             match str::from_utf8(sub) {
                 Ok(string) => {
                     write!(fmt, "{}", string)?;
@@ -185,22 +235,23 @@ impl fmt::Display for OsStr {
     }
 }
 
-impl<'str> IntoOsString for &'str OsStr {
+impl IntoOsString for &OsStr {
     fn into_os_string(self) -> Result<OsString, Error> {
         let len = self.bytes.len();
         let alloc = unsafe { libc::malloc(len + 1) };
         let alloc = match NonNull::new(alloc as *mut libc::c_char) {
             Some(alloc) => alloc,
             None => {
-                return Err(Error::last_os_error());
+                return Err(Error::from_raw_os_error(libc::ENOMEM));
             },
         };
         unsafe {
             libc::memcpy(
                 alloc.as_ptr() as *mut libc::c_void,
                 self.bytes.as_ptr() as *const libc::c_void,
-                len + 1,
+                len,
             );
+            *alloc.as_ptr().add(len) = 0;
         }
 
         Ok(OsString { alloc, len })
@@ -208,14 +259,14 @@ impl<'str> IntoOsString for &'str OsStr {
 }
 
 impl ToOsStr for str {
-    fn to_os_str(&self) -> Result<EitherOsStr, Error> {
+    fn to_os_str(&self) -> Result<EitherOsStr<'_>, Error> {
         make_os_str(self.as_bytes())
     }
 }
 
 #[cfg(feature = "std")]
 impl ToOsStr for ffi::OsStr {
-    fn to_os_str(&self) -> Result<EitherOsStr, Error> {
+    fn to_os_str(&self) -> Result<EitherOsStr<'_>, Error> {
         make_os_str(self.as_bytes())
     }
 }
@@ -223,13 +274,15 @@ impl ToOsStr for ffi::OsStr {
 /// Path must not contain a nul-byte in the middle, but a nul-byte in the end
 /// (and only in the end) is allowed, which in this case no extra allocation
 /// will be made. Otherwise, an extra allocation is made.
-fn make_os_str(slice: &[u8]) -> Result<EitherOsStr, Error> {
+fn make_os_str(slice: &[u8]) -> Result<EitherOsStr<'_>, Error> {
     if let Some((&last, init)) = slice.split_last() {
         if init.contains(&0) {
             panic!("Path to file cannot contain nul-byte in the middle");
         }
         if last == 0 {
-            let str = unsafe { OsStr::from_slice(transmute(slice)) };
+            let str = unsafe {
+                OsStr::from_slice(transmute::<&[u8], &[libc::c_char]>(init))
+            };
             return Ok(EitherOsStr::Borrowed(str));
         }
     }
@@ -238,7 +291,7 @@ fn make_os_str(slice: &[u8]) -> Result<EitherOsStr, Error> {
     let alloc = match NonNull::new(alloc as *mut libc::c_char) {
         Some(alloc) => alloc,
         None => {
-            return Err(Error::last_os_error());
+            return Err(Error::from_raw_os_error(libc::ENOMEM));
         },
     };
     unsafe {
@@ -253,108 +306,888 @@ fn make_os_str(slice: &[u8]) -> Result<EitherOsStr, Error> {
     Ok(EitherOsStr::Owned(OsString { alloc, len: slice.len() }))
 }
 
+/// A raw return value of a libc call, which signals failure with `-1`.
+trait RawResult: Copy {
+    /// Whether the call failed.
+    fn is_failure(self) -> bool;
+}
+
+macro_rules! impl_raw_result {
+    ($($ty:ty),*) => {
+        $(
+            impl RawResult for $ty {
+                fn is_failure(self) -> bool {
+                    self == -1
+                }
+            }
+        )*
+    };
+}
+
+impl_raw_result!(i32, i64, isize);
+
+/// Checks the return value of a libc call. On failure, `errno` is captured
+/// right away, before anything else (formatting, allocation, cleanup) gets the
+/// chance to clobber it, and the error is returned by value.
+fn cvt<T>(res: T) -> Result<T, Error>
+where
+    T: RawResult,
+{
+    if res.is_failure() {
+        Err(Error::from_raw_os_error(errno()))
+    } else {
+        Ok(res)
+    }
+}
+
+/// Whether the error has the given raw OS code.
+fn is_code(error: &Error, code: libc::c_int) -> bool {
+    error.raw_os_error() == Some(code)
+}
+
 /// Returns the ID of the current process.
 pub fn pid() -> Pid {
     unsafe { libc::getpid() }
 }
 
-/// Opens a file with only purpose of locking it. Creates it if it does not
-/// exist. Path must not contain a nul-byte in the middle, but a nul-byte in the
-/// end (and only in the end) is allowed, which in this case no extra allocation
-/// will be made. Otherwise, an extra allocation is made.
-pub fn open(path: &OsStr) -> Result<FileDesc, Error> {
-    let fd = unsafe {
-        libc::open(
-            path.bytes.as_ptr(),
-            libc::O_RDWR | libc::O_CLOEXEC | libc::O_CREAT,
-            (libc::S_IRUSR | libc::S_IWUSR | libc::S_IRGRP | libc::S_IROTH)
-                as libc::c_int,
-        )
+/// Returns the host name of the machine.
+#[cfg(feature = "std")]
+pub fn hostname() -> Result<String, Error> {
+    let mut buf = [0u8; 256];
+    cvt(unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) })?;
+    let len = buf.iter().position(|&byte| byte == 0).unwrap_or(buf.len());
+    Ok(String::from_utf8_lossy(&buf[.. len]).into_owned())
+}
+
+/// Returns the real user ID of the current process.
+#[cfg(feature = "std")]
+pub fn user_id() -> u32 {
+    unsafe { libc::getuid() }
+}
+
+/// Returns whether a process with the given PID exists.
+#[cfg(feature = "std")]
+pub fn process_alive(pid: u32) -> Result<bool, Error> {
+    let Ok(pid) = Pid::try_from(pid) else {
+        return Ok(false);
     };
+    match cvt(unsafe { libc::kill(pid, 0) }) {
+        Ok(_) => Ok(true),
+        Err(error) if is_code(&error, libc::EPERM) => Ok(true),
+        Err(error) if is_code(&error, libc::ESRCH) => Ok(false),
+        Err(error) => Err(error),
+    }
+}
 
-    if fd >= 0 {
-        Ok(fd)
-    } else {
-        Err(Error::last_os_error())
+/// Returns the start time of the process with the given PID, in clock ticks
+/// since boot, if it exists.
+#[cfg(all(target_os = "linux", feature = "std"))]
+pub fn process_start_time(pid: u32) -> Option<u64> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The name in parentheses may contain spaces, and the start time is the
+    // 22nd field, counting the name as the 2nd.
+    let fields = &stat[stat.rfind(')')? + 1 ..];
+    fields.split_whitespace().nth(19)?.parse().ok()
+}
+
+/// Start times of processes are not looked up outside of Linux.
+#[cfg(all(not(target_os = "linux"), feature = "std"))]
+pub fn process_start_time(_pid: u32) -> Option<u64> {
+    None
+}
+
+/// Returns an identifier of the current boot of the machine.
+#[cfg(all(target_os = "linux", feature = "std"))]
+pub fn boot_id() -> Option<String> {
+    let id = std::fs::read_to_string("/proc/sys/kernel/random/boot_id").ok()?;
+    Some(id.trim().to_owned())
+}
+
+/// Boots are not identified outside of Linux.
+#[cfg(all(not(target_os = "linux"), feature = "std"))]
+pub fn boot_id() -> Option<String> {
+    None
+}
+
+/// Returns whether the given boot identifiers refer to the same boot.
+#[cfg(feature = "std")]
+pub fn same_boot(recorded: &str, current: &str) -> bool {
+    recorded == current
+}
+
+/// Blocks until the process with the given PID exits, holding a pidfd on it.
+/// Kernels older than 5.3, without `pidfd_open`, are polled instead.
+#[cfg(all(target_os = "linux", feature = "std"))]
+pub fn wait_process_exit(pid: u32) -> Result<(), Error> {
+    let Ok(pid) = Pid::try_from(pid) else {
+        return Ok(());
+    };
+    let pidfd =
+        match cvt(unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) }) {
+            Ok(pidfd) => pidfd as FileDesc,
+            Err(error) if is_code(&error, libc::ESRCH) => return Ok(()),
+            Err(error) if is_code(&error, libc::ENOSYS) => {
+                while process_alive(pid as u32)? {
+                    std::thread::sleep(core::time::Duration::from_millis(10));
+                }
+                return Ok(());
+            },
+            Err(error) => return Err(error),
+        };
+    let mut poll_fd =
+        libc::pollfd { fd: pidfd, events: libc::POLLIN, revents: 0 };
+    let result = loop {
+        match cvt(unsafe { libc::poll(&mut poll_fd, 1, -1) }) {
+            Err(error) if is_code(&error, libc::EINTR) => {},
+            result => break result.map(|_| ()),
+        }
+    };
+    close(pidfd);
+    result
+}
+
+/// Opens a file with only purpose of locking it, creating it if it does not
+/// exist and the options ask to. Path must not contain a nul-byte in the
+/// middle, but a nul-byte in the end (and only in the end) is allowed, which in
+/// this case no extra allocation will be made. Otherwise, an extra allocation
+/// is made. Returns the descriptor and whether this call created the file.
+pub fn open(
+    path: &OsStr,
+    options: &LockFileOptions,
+) -> Result<(FileDesc, bool), Error> {
+    let mut flags = libc::O_CLOEXEC;
+    flags |= match (options.read, options.write) {
+        (_, false) => libc::O_RDONLY,
+        (true, true) => libc::O_RDWR,
+        (false, true) => libc::O_WRONLY,
+    };
+    if !options.follow_symlinks {
+        flags |= libc::O_NOFOLLOW;
+    }
+    if options.directory {
+        flags |= libc::O_DIRECTORY;
+    }
+    let create = flags | libc::O_CREAT;
+    let create_new = create | libc::O_EXCL;
+
+    if !options.create_new {
+        match open_raw(path, flags, 0) {
+            Err(error) if options.create && is_code(&error, libc::ENOENT) => {},
+            result => return result.map(|fd| (fd, false)),
+        }
+    }
+    let mode = options.mode.map_or(0o644, |mode| mode as libc::mode_t);
+    match open_raw(path, create_new, mode) {
+        Ok(fd) => {
+            // Created by this call, so permissions are ours to set.
+            if options.mode.is_some() {
+                if let Err(error) = cvt(unsafe { libc::fchmod(fd, mode) }) {
+                    close(fd);
+                    return Err(error);
+                }
+            }
+            Ok((fd, true))
+        },
+        // Either someone else created it in the meantime, or the path is a
+        // dangling symbolic link, whose target is created without fchmod.
+        Err(error) if !options.create_new && is_code(&error, libc::EEXIST) => {
+            open_raw(path, create, mode).map(|fd| (fd, false))
+        },
+        Err(error) => Err(error),
     }
 }
 
+/// Calls `open(2)` with the given flags and creation permissions.
+fn open_raw(
+    path: &OsStr,
+    flags: libc::c_int,
+    mode: libc::mode_t,
+) -> Result<FileDesc, Error> {
+    cvt(unsafe { libc::open(path.as_ptr(), flags, mode as libc::c_uint) })
+}
+
 /// Writes data into the given open file.
 pub fn write(fd: FileDesc, mut bytes: &[u8]) -> Result<(), Error> {
-    while bytes.len() > 0 {
-        let written = unsafe {
+    while !bytes.is_empty() {
+        let res = cvt(unsafe {
             libc::write(fd, bytes.as_ptr() as *const libc::c_void, bytes.len())
-        };
-        if written < 0 && errno() != libc::EAGAIN {
-            return Err(Error::last_os_error());
+        });
+        match res {
+            Ok(0) => return Err(write_zero()),
+            Ok(written) => bytes = &bytes[written as usize ..],
+            Err(error)
+                if is_code(&error, libc::EAGAIN)
+                    || is_code(&error, libc::EINTR) => {},
+            Err(error) => return Err(error),
         }
-        bytes = &bytes[written as usize ..];
     }
 
     Ok(())
 }
 
+/// Error reported when a write makes no progress.
+#[cfg(feature = "std")]
+pub(crate) fn write_zero() -> Error {
+    Error::new(std::io::ErrorKind::WriteZero, "failed to write whole buffer")
+}
+
+/// Error reported when a write makes no progress.
+#[cfg(not(feature = "std"))]
+fn write_zero() -> Error {
+    Error::from_raw_os_error(libc::EIO)
+}
+
+/// Flushes the data and metadata of the file to the disk.
 pub fn fsync(fd: FileDesc) -> Result<(), Error> {
-    let result = unsafe { libc::fsync(fd) };
+    cvt(unsafe { libc::fsync(fd) })?;
+    Ok(())
+}
 
-    if result >= 0 {
-        Ok(())
-    } else {
-        Err(Error::last_os_error())
+/// Flushes the data of the file to the disk, and metadata only if needed to
+/// read the data back.
+pub fn sync_data(fd: FileDesc) -> Result<(), Error> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    cvt(unsafe { libc::fdatasync(fd) })?;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    cvt(unsafe { libc::fsync(fd) })?;
+    Ok(())
+}
+
+/// Flushes the directory entry of the file at the given path to the disk, by
+/// syncing its parent directory.
+pub fn sync_parent(path: &OsStr) -> Result<(), Error> {
+    let bytes = unsafe { transmute::<&[libc::c_char], &[u8]>(&path.bytes) };
+    let parent = match bytes.iter().rposition(|&byte| byte == b'/') {
+        Some(0) => &b"/"[..],
+        Some(index) => &bytes[.. index],
+        None => &b"."[..],
+    };
+    let parent = make_os_str(parent)?;
+    let fd = cvt(unsafe {
+        libc::open(
+            parent.as_ptr(),
+            libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC,
+        )
+    })?;
+    let result = fsync(fd);
+    close(fd);
+    result
+}
+
+/// Seeks the file referenced by the given file descriptor to its end.
+pub fn seek_end(fd: FileDesc) -> Result<(), Error> {
+    cvt(unsafe { libc::lseek(fd, 0, libc::SEEK_END) })?;
+    Ok(())
+}
+
+/// Reads data from the given offset of the file referenced by the given file
+/// descriptor, keeping its current position. Returns the number of bytes read,
+/// which is zero at the end of the file.
+#[cfg(feature = "std")]
+pub fn read_at(
+    fd: FileDesc,
+    buf: &mut [u8],
+    offset: u64,
+) -> Result<usize, Error> {
+    loop {
+        let res = cvt(unsafe {
+            libc::pread(
+                fd,
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+                offset as libc::off_t,
+            )
+        });
+        match res {
+            Ok(read) => break Ok(read as usize),
+            Err(error) if is_code(&error, libc::EINTR) => {},
+            Err(error) => break Err(error),
+        }
+    }
+}
+
+/// Reads data from the current position of the file referenced by the given
+/// file descriptor. Returns the number of bytes read, which is zero at the end
+/// of the file.
+#[cfg(feature = "std")]
+pub fn read(fd: FileDesc, buf: &mut [u8]) -> Result<usize, Error> {
+    loop {
+        let res = cvt(unsafe {
+            libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len())
+        });
+        match res {
+            Ok(read) => break Ok(read as usize),
+            Err(error) if is_code(&error, libc::EINTR) => {},
+            Err(error) => break Err(error),
+        }
     }
 }
 
+/// Seeks the file referenced by the given file descriptor, returning the new
+/// position from its start.
+#[cfg(feature = "std")]
+pub fn seek(fd: FileDesc, pos: std::io::SeekFrom) -> Result<u64, Error> {
+    let (offset, whence) = match pos {
+        std::io::SeekFrom::Start(offset) => (offset as i64, libc::SEEK_SET),
+        std::io::SeekFrom::Current(offset) => (offset, libc::SEEK_CUR),
+        std::io::SeekFrom::End(offset) => (offset, libc::SEEK_END),
+    };
+    let position =
+        cvt(unsafe { libc::lseek(fd, offset as libc::off_t, whence) })?;
+    Ok(position as u64)
+}
+
 /// Truncates the file referenced by the given file descriptor and seeks it to
 /// the start.
 pub fn truncate(fd: FileDesc) -> Result<(), Error> {
-    let res = unsafe { libc::lseek(fd, 0, libc::SEEK_SET) };
-    if res < 0 {
-        return Err(Error::last_os_error());
-    }
+    cvt(unsafe { libc::lseek(fd, 0, libc::SEEK_SET) })?;
+    cvt(unsafe { libc::ftruncate(fd, 0) })?;
+    Ok(())
+}
 
-    let res = unsafe { libc::ftruncate(fd, 0) };
-    if res < 0 {
-        Err(Error::last_os_error())
-    } else {
-        Ok(())
+/// Mechanism used to lock whole files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum LockBackend {
+    /// `flock(2)` locks, held by the open file description, so each handle
+    /// owns its lock. Over NFS, some kernels emulate them with `fcntl` locks,
+    /// or do not support them at all. The default outside of Linux.
+    #[cfg_attr(not(target_os = "linux"), default)]
+    Flock,
+    /// POSIX `fcntl(2)` record locks over the whole file. They are held by the
+    /// process: closing any handle to the file releases every lock of the
+    /// process on it. Handles of the same process exclude each other only with
+    /// feature `std`, through a process-wide registry. Usually supported over
+    /// NFS.
+    Fcntl,
+    /// `lockf(3)` locks, usually implemented over `fcntl` record locks, with
+    /// the same per-process semantics. Shared locks are not supported.
+    Lockf,
+    /// Linux open file description `fcntl` locks over the whole file, held by
+    /// the open file description like `flock` locks, but sharing the
+    /// `fcntl` locks' NFS support. The default on Linux. On kernels older than
+    /// 3.15, which do not support them, `flock` locks are used instead.
+    #[cfg(target_os = "linux")]
+    #[cfg_attr(target_os = "linux", default)]
+    Ofd,
+}
+
+#[cfg(feature = "std")]
+impl LockBackend {
+    /// Whether locks of this backend are held by the process rather than by
+    /// the handle, so handles of the same process never exclude each other.
+    pub(crate) fn is_process_wide(self) -> bool {
+        matches!(self, LockBackend::Fcntl | LockBackend::Lockf)
     }
 }
 
+/// Whether the kernel rejected open file description locks, in which case
+/// [`LockBackend::Ofd`] falls back to `flock`, and range locks to `fcntl`.
+#[cfg(target_os = "linux")]
+static OFD_UNSUPPORTED: AtomicBool = AtomicBool::new(false);
+
+/// Operation applied by a lock backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LockKind {
+    /// Acquires an exclusive lock.
+    Exclusive,
+    /// Acquires a shared lock.
+    Shared,
+    /// Releases the lock.
+    Unlock,
+}
+
 /// Tries to lock a file and blocks until it is possible to lock.
-pub fn lock(fd: FileDesc) -> Result<(), Error> {
-    let res = unsafe { libc::flock(fd, libc::LOCK_EX) };
-    if res >= 0 {
-        Ok(())
-    } else {
-        Err(Error::last_os_error())
-    }
+pub fn lock(fd: FileDesc, backend: LockBackend) -> Result<(), Error> {
+    apply(fd, backend, LockKind::Exclusive, true)
 }
 
 /// Tries to lock a file but returns as soon as possible if already locked.
-pub fn try_lock(fd: FileDesc) -> Result<bool, Error> {
-    let res = unsafe { libc::flock(fd, libc::LOCK_EX | libc::LOCK_NB) };
-    if res >= 0 {
-        Ok(true)
-    } else {
-        let err = errno();
-        if err == libc::EWOULDBLOCK || err == libc::EINTR {
+pub fn try_lock(fd: FileDesc, backend: LockBackend) -> Result<bool, Error> {
+    acquired(apply(fd, backend, LockKind::Exclusive, false))
+}
+
+/// Tries to lock a file in shared mode and blocks until it is possible to
+/// lock.
+pub fn lock_shared(fd: FileDesc, backend: LockBackend) -> Result<(), Error> {
+    apply(fd, backend, LockKind::Shared, true)
+}
+
+/// Tries to lock a file in shared mode but returns as soon as possible if
+/// already exclusively locked.
+pub fn try_lock_shared(
+    fd: FileDesc,
+    backend: LockBackend,
+) -> Result<bool, Error> {
+    acquired(apply(fd, backend, LockKind::Shared, false))
+}
+
+/// Unlocks the file.
+pub fn unlock(fd: FileDesc, backend: LockBackend) -> Result<(), Error> {
+    apply(fd, backend, LockKind::Unlock, false)
+}
+
+/// Applies the given operation over the whole file with the given backend.
+fn apply(
+    fd: FileDesc,
+    backend: LockBackend,
+    kind: LockKind,
+    block: bool,
+) -> Result<(), Error> {
+    let record_kind = match kind {
+        LockKind::Exclusive => libc::F_WRLCK,
+        LockKind::Shared => libc::F_RDLCK,
+        LockKind::Unlock => libc::F_UNLCK,
+    };
+    match backend {
+        LockBackend::Flock => {
+            let mut operation = match kind {
+                LockKind::Exclusive => libc::LOCK_EX,
+                LockKind::Shared => libc::LOCK_SH,
+                LockKind::Unlock => libc::LOCK_UN,
+            };
+            if !block {
+                operation |= libc::LOCK_NB;
+            }
+            cvt(unsafe { libc::flock(fd, operation) })?;
+            Ok(())
+        },
+        LockBackend::Fcntl => {
+            let command = if block { libc::F_SETLKW } else { libc::F_SETLK };
+            fcntl_range(fd, command, record_kind, 0, 0)
+        },
+        LockBackend::Lockf => lockf(fd, kind, block),
+        #[cfg(target_os = "linux")]
+        LockBackend::Ofd => {
+            if OFD_UNSUPPORTED.load(Ordering::Relaxed) {
+                return apply(fd, LockBackend::Flock, kind, block);
+            }
+            let command =
+                if block { libc::F_OFD_SETLKW } else { libc::F_OFD_SETLK };
+            match fcntl_range(fd, command, record_kind, 0, 0) {
+                Err(error) if is_code(&error, libc::EINVAL) => {
+                    OFD_UNSUPPORTED.store(true, Ordering::Relaxed);
+                    apply(fd, LockBackend::Flock, kind, block)
+                },
+                result => result,
+            }
+        },
+    }
+}
+
+/// Applies the given operation with `lockf`, which works from the file cursor,
+/// so the cursor is moved to the start and then restored.
+fn lockf(fd: FileDesc, kind: LockKind, block: bool) -> Result<(), Error> {
+    let command = match kind {
+        LockKind::Exclusive if block => libc::F_LOCK,
+        LockKind::Exclusive => libc::F_TLOCK,
+        LockKind::Shared => return Err(Error::from_raw_os_error(libc::EINVAL)),
+        LockKind::Unlock => libc::F_ULOCK,
+    };
+    let position = cvt(unsafe { libc::lseek(fd, 0, libc::SEEK_CUR) })?;
+    cvt(unsafe { libc::lseek(fd, 0, libc::SEEK_SET) })?;
+    let result = cvt(unsafe { libc::lockf(fd, command, 0) });
+    let restored = cvt(unsafe { libc::lseek(fd, position, libc::SEEK_SET) });
+    result?;
+    restored?;
+    Ok(())
+}
+
+/// Whether the error is a system call interrupted by a signal.
+pub(crate) fn is_interrupted(error: &Error) -> bool {
+    is_code(error, libc::EINTR)
+}
+
+/// Converts the result of a non-blocking lock attempt into whether the lock
+/// was acquired.
+fn acquired(result: Result<(), Error>) -> Result<bool, Error> {
+    match result {
+        Ok(()) => Ok(true),
+        Err(error)
+            if is_code(&error, libc::EWOULDBLOCK)
+                || is_code(&error, libc::EAGAIN)
+                || is_code(&error, libc::EACCES)
+                || is_code(&error, libc::EINTR) =>
+        {
             Ok(false)
-        } else {
-            Err(Error::from_raw_os_error(err as i32))
+        },
+        Err(error) => Err(error),
+    }
+}
+
+/// Returns the PID of a process owning a lock on the file which conflicts with
+/// an exclusive lock, as tracked by the kernel for the given backend. Returns
+/// `None` if there is no such lock or the kernel does not track its owner, as
+/// for open file description locks.
+#[cfg(feature = "std")]
+pub fn lock_owner(
+    fd: FileDesc,
+    backend: LockBackend,
+) -> Result<Option<u32>, Error> {
+    match backend {
+        LockBackend::Fcntl | LockBackend::Lockf => {
+            let mut lock: libc::flock = unsafe { mem::zeroed() };
+            lock.l_type = libc::F_WRLCK as _;
+            lock.l_whence = libc::SEEK_SET as _;
+            cvt(unsafe { libc::fcntl(fd, libc::F_GETLK, &mut lock) })?;
+            if libc::c_int::from(lock.l_type) == libc::F_UNLCK
+                || lock.l_pid <= 0
+            {
+                Ok(None)
+            } else {
+                Ok(Some(lock.l_pid as u32))
+            }
+        },
+        LockBackend::Flock => flock_owner(fd),
+        #[cfg(target_os = "linux")]
+        LockBackend::Ofd if OFD_UNSUPPORTED.load(Ordering::Relaxed) => {
+            flock_owner(fd)
+        },
+        #[cfg(target_os = "linux")]
+        LockBackend::Ofd => Ok(None),
+    }
+}
+
+/// Finds the owner of a `flock` lock on the file in `/proc/locks`, the only
+/// place where Linux reports it.
+#[cfg(all(target_os = "linux", feature = "std"))]
+fn flock_owner(fd: FileDesc) -> Result<Option<u32>, Error> {
+    let mut stat: libc::stat = unsafe { mem::zeroed() };
+    cvt(unsafe { libc::fstat(fd, &mut stat) })?;
+    let file = format!(
+        "{:02x}:{:02x}:{}",
+        libc::major(stat.st_dev),
+        libc::minor(stat.st_dev),
+        stat.st_ino
+    );
+    let locks = std::fs::read_to_string("/proc/locks")?;
+    let owner = locks.lines().find_map(|line| {
+        // id: FLOCK ADVISORY WRITE pid dev:ino start end, without waiters.
+        let mut fields = line.split_whitespace().skip(1);
+        if fields.next()? != "FLOCK" {
+            return None;
+        }
+        let pid = fields.nth(2)?.parse::<u32>().ok()?;
+        (fields.next()? == file).then_some(pid)
+    });
+    Ok(owner)
+}
+
+/// Owners of `flock` locks are not reported outside of Linux.
+#[cfg(all(not(target_os = "linux"), feature = "std"))]
+fn flock_owner(_fd: FileDesc) -> Result<Option<u32>, Error> {
+    Ok(None)
+}
+
+/// Applies a record lock of the given type over a byte range of a file.
+/// Open file description locks are used on Linux, so they are held by the
+/// handle, unless the kernel rejects them like for [`LockBackend::Ofd`], in
+/// which case, like elsewhere, `fcntl` locks held by the process are used.
+fn record_range(
+    fd: FileDesc,
+    block: bool,
+    kind: libc::c_int,
+    offset: u64,
+    len: u64,
+) -> Result<(), Error> {
+    let command = if block { libc::F_SETLKW } else { libc::F_SETLK };
+    #[cfg(target_os = "linux")]
+    if !OFD_UNSUPPORTED.load(Ordering::Relaxed) {
+        let ofd = if block { libc::F_OFD_SETLKW } else { libc::F_OFD_SETLK };
+        match fcntl_range(fd, ofd, kind, offset, len) {
+            // Invalid ranges are rejected by both commands.
+            Err(error) if is_code(&error, libc::EINVAL) => {
+                fcntl_range(fd, command, kind, offset, len)?;
+                OFD_UNSUPPORTED.store(true, Ordering::Relaxed);
+                return Ok(());
+            },
+            result => return result,
         }
     }
+    fcntl_range(fd, command, kind, offset, len)
 }
 
-/// Unlocks the file.
-pub fn unlock(fd: FileDesc) -> Result<(), Error> {
-    let res = unsafe { libc::flock(fd, libc::LOCK_UN) };
-    if res >= 0 {
-        Ok(())
-    } else {
-        Err(Error::last_os_error())
+/// Applies a record lock of the given type over a byte range with `fcntl`.
+fn fcntl_range(
+    fd: FileDesc,
+    command: libc::c_int,
+    kind: libc::c_int,
+    offset: u64,
+    len: u64,
+) -> Result<(), Error> {
+    let (Ok(start), Ok(len)) =
+        (libc::off_t::try_from(offset), libc::off_t::try_from(len))
+    else {
+        return Err(Error::from_raw_os_error(libc::EINVAL));
+    };
+    let mut lock: libc::flock = unsafe { mem::zeroed() };
+    lock.l_type = kind as _;
+    lock.l_whence = libc::SEEK_SET as _;
+    lock.l_start = start;
+    lock.l_len = len;
+    cvt(unsafe { libc::fcntl(fd, command, &lock) })?;
+    Ok(())
+}
+
+/// Tries to lock a byte range of a file and blocks until it is possible to
+/// lock. Range locks only interact with whole-file locks of the `fcntl`-based
+/// backends.
+pub fn lock_range(fd: FileDesc, offset: u64, len: u64) -> Result<(), Error> {
+    record_range(fd, true, libc::F_WRLCK, offset, len)
+}
+
+/// Tries to lock a byte range of a file but returns as soon as possible if
+/// already locked.
+pub fn try_lock_range(
+    fd: FileDesc,
+    offset: u64,
+    len: u64,
+) -> Result<bool, Error> {
+    acquired(record_range(fd, false, libc::F_WRLCK, offset, len))
+}
+
+/// Unlocks a byte range of a file.
+pub fn unlock_range(fd: FileDesc, offset: u64, len: u64) -> Result<(), Error> {
+    record_range(fd, false, libc::F_UNLCK, offset, len)
+}
+
+/// Removes the file at the given path.
+pub fn remove(path: &OsStr) -> Result<(), Error> {
+    cvt(unsafe { libc::unlink(path.as_ptr()) })?;
+    Ok(())
+}
+
+/// Sets the access and modification times of the file at the given path to
+/// the current time, without opening it (closing a descriptor would release
+/// `fcntl` locks of this process).
+#[cfg(feature = "std")]
+pub fn touch(path: &OsStr) -> Result<(), Error> {
+    cvt(unsafe {
+        libc::utimensat(libc::AT_FDCWD, path.as_ptr(), ptr::null(), 0)
+    })?;
+    Ok(())
+}
+
+/// Returns the device and inode numbers of the file referenced by the given
+/// file descriptor.
+#[cfg(feature = "std")]
+pub fn file_id(fd: FileDesc) -> Result<(u64, u64), Error> {
+    let mut stat: libc::stat = unsafe { mem::zeroed() };
+    cvt(unsafe { libc::fstat(fd, &mut stat) })?;
+    Ok((stat.st_dev as u64, stat.st_ino as u64))
+}
+
+/// Returns whether the given path still names the file referenced by the
+/// given file descriptor.
+#[cfg(feature = "std")]
+pub fn same_file(fd: FileDesc, path: &OsStr) -> Result<bool, Error> {
+    let mut opened: libc::stat = unsafe { mem::zeroed() };
+    cvt(unsafe { libc::fstat(fd, &mut opened) })?;
+    let mut named: libc::stat = unsafe { mem::zeroed() };
+    match cvt(unsafe { libc::stat(path.as_ptr(), &mut named) }) {
+        Ok(_) => {
+            Ok(opened.st_dev == named.st_dev && opened.st_ino == named.st_ino)
+        },
+        Err(error) if is_code(&error, libc::ENOENT) => Ok(false),
+        Err(error) => Err(error),
     }
 }
 
+/// Signals treated as requests to terminate the process.
+const TERMINATION_SIGNALS: [libc::c_int; 2] = [libc::SIGINT, libc::SIGTERM];
+
+/// Storage for the actions replaced by [`on_termination`].
+struct PreviousActions(UnsafeCell<[MaybeUninit<libc::sigaction>; 2]>);
+
+// Safe because the actions are written once, before the handler that reads
+// them is installed.
+unsafe impl Sync for PreviousActions {}
+
+/// Actions replaced by [`on_termination`], restored before re-raising.
+static PREVIOUS_ACTIONS: PreviousActions =
+    PreviousActions(UnsafeCell::new([MaybeUninit::uninit(); 2]));
+
+/// Callback registered by [`on_termination`].
+static TERMINATION_CALLBACK: AtomicUsize = AtomicUsize::new(0);
+
+/// Runs the termination callback, then restores the default action of the
+/// signal and raises it again, so the process terminates as it would have. If
+/// the signal had a handler instead, the handler is called in turn, without
+/// running the callback, since the process may keep running.
+extern "C" fn handle_termination(
+    signal: libc::c_int,
+    info: *mut libc::siginfo_t,
+    context: *mut libc::c_void,
+) {
+    let Some(index) = TERMINATION_SIGNALS.iter().position(|&s| s == signal)
+    else {
+        return;
+    };
+    let previous = unsafe { (*PREVIOUS_ACTIONS.0.get())[index].as_ptr() };
+    let handler = unsafe { (*previous).sa_sigaction };
+    if handler != libc::SIG_DFL {
+        unsafe {
+            if (*previous).sa_flags & libc::SA_SIGINFO != 0 {
+                let handler: extern "C" fn(
+                    libc::c_int,
+                    *mut libc::siginfo_t,
+                    *mut libc::c_void,
+                ) = transmute(handler);
+                handler(signal, info, context);
+            } else {
+                let handler: extern "C" fn(libc::c_int) = transmute(handler);
+                handler(signal);
+            }
+        }
+        return;
+    }
+
+    let callback = TERMINATION_CALLBACK.load(Ordering::Acquire);
+    if callback != 0 {
+        let callback: fn() = unsafe { transmute(callback) };
+        callback();
+    }
+    unsafe {
+        libc::sigaction(signal, previous, ptr::null_mut());
+        libc::raise(signal);
+    }
+}
+
+/// Installs handlers calling `callback` when the process is asked to terminate
+/// by `SIGINT` or `SIGTERM` and would be terminated by default. Signals
+/// currently ignored are left alone, and those with a handler keep calling it
+/// instead of `callback`. The callback must be async-signal-safe. Must be
+/// called at most once.
+pub fn on_termination(callback: fn()) -> Result<(), Error> {
+    TERMINATION_CALLBACK.store(callback as usize, Ordering::Release);
+
+    for (index, &signal) in TERMINATION_SIGNALS.iter().enumerate() {
+        unsafe {
+            let previous = &mut (*PREVIOUS_ACTIONS.0.get())[index];
+            cvt(libc::sigaction(signal, ptr::null(), previous.as_mut_ptr()))?;
+            if previous.assume_init_ref().sa_sigaction == libc::SIG_IGN {
+                continue;
+            }
+
+            let mut action: libc::sigaction = mem::zeroed();
+            let handler: extern "C" fn(
+                libc::c_int,
+                *mut libc::siginfo_t,
+                *mut libc::c_void,
+            ) = handle_termination;
+            action.sa_sigaction = handler as usize;
+            action.sa_flags = libc::SA_RESTART | libc::SA_SIGINFO;
+            cvt(libc::sigemptyset(&mut action.sa_mask))?;
+            cvt(libc::sigaction(signal, &action, ptr::null_mut()))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Error reported when some fixed-capacity resource is exhausted.
+pub(crate) fn no_space() -> Error {
+    Error::from_raw_os_error(libc::ENOMEM)
+}
+
+/// An inotify instance watching a file for changes which may indicate that it
+/// was unlocked: being closed, modified or having its attributes changed.
+#[cfg(all(target_os = "linux", feature = "std"))]
+#[derive(Debug)]
+pub struct Watch {
+    /// The inotify instance.
+    inotify: FileDesc,
+}
+
+#[cfg(all(target_os = "linux", feature = "std"))]
+impl Watch {
+    /// Starts watching the given open file, through its `/proc` entry.
+    pub fn new(fd: FileDesc) -> Result<Self, Error> {
+        let inotify = cvt(unsafe {
+            libc::inotify_init1(libc::IN_CLOEXEC | libc::IN_NONBLOCK)
+        })?;
+        let watch = Self { inotify };
+
+        let mut path = [0u8; 32];
+        let prefix = b"/proc/self/fd/";
+        path[.. prefix.len()].copy_from_slice(prefix);
+        let mut digits = [0u8; 10];
+        let mut count = 0;
+        let mut rest = fd as u32;
+        loop {
+            digits[count] = b'0' + (rest % 10) as u8;
+            count += 1;
+            rest /= 10;
+            if rest == 0 {
+                break;
+            }
+        }
+        for (i, &digit) in digits[.. count].iter().rev().enumerate() {
+            path[prefix.len() + i] = digit;
+        }
+
+        let mask = libc::IN_CLOSE | libc::IN_MODIFY | libc::IN_ATTRIB;
+        cvt(unsafe {
+            libc::inotify_add_watch(
+                inotify,
+                path.as_ptr() as *const libc::c_char,
+                mask,
+            )
+        })?;
+        Ok(watch)
+    }
+
+    /// Blocks until the file changes or the timeout elapses, whichever comes
+    /// first.
+    pub fn wait(&self, timeout: core::time::Duration) -> Result<(), Error> {
+        let millis = timeout.as_millis().min(libc::c_int::MAX as u128);
+        let mut pollfd =
+            libc::pollfd { fd: self.inotify, events: libc::POLLIN, revents: 0 };
+        match cvt(unsafe { libc::poll(&mut pollfd, 1, millis as libc::c_int) })
+        {
+            Ok(_) => {},
+            Err(error) if is_code(&error, libc::EINTR) => return Ok(()),
+            Err(error) => return Err(error),
+        }
+
+        let mut events = [0u8; 1024];
+        loop {
+            let res = cvt(unsafe {
+                libc::read(
+                    self.inotify,
+                    events.as_mut_ptr() as *mut libc::c_void,
+                    events.len(),
+                )
+            });
+            match res {
+                Ok(_) => {},
+                Err(error) if is_code(&error, libc::EAGAIN) => return Ok(()),
+                Err(error) => return Err(error),
+            }
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "std"))]
+impl Drop for Watch {
+    fn drop(&mut self) {
+        close(self.inotify);
+    }
+}
+
+/// Duplicates the descriptor, referring to the same open file description.
+pub fn duplicate(fd: FileDesc) -> Result<FileDesc, Error> {
+    cvt(unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) })
+}
+
+/// Sets whether the descriptor is inherited by executed programs, clearing or
+/// setting `FD_CLOEXEC`. Async-signal-safe.
+#[cfg(feature = "std")]
+pub fn set_inheritable(fd: FileDesc, inheritable: bool) -> Result<(), Error> {
+    let flags = if inheritable { 0 } else { libc::FD_CLOEXEC };
+    cvt(unsafe { libc::fcntl(fd, libc::F_SETFD, flags) })?;
+    Ok(())
+}
+
 /// Closes the file.
 pub fn close(fd: FileDesc) {
     unsafe { libc::close(fd) };
@@ -364,3 +1197,30 @@ pub fn close(fd: FileDesc) {
 pub(crate) fn uninitialized_fd() -> FileDesc {
     -1
 }
+
+/// Takes the ownership of a file descriptor.
+///
+/// # Safety
+/// The descriptor must be open and owned by no one else.
+pub(crate) unsafe fn own(fd: FileDesc) -> OwnedDesc {
+    #[cfg(feature = "std")]
+    {
+        std::os::unix::io::FromRawFd::from_raw_fd(fd)
+    }
+    #[cfg(not(feature = "std"))]
+    {
+        fd
+    }
+}
+
+/// Gives up the ownership of a file descriptor, which is no longer closed.
+pub(crate) fn disown(fd: OwnedDesc) -> FileDesc {
+    #[cfg(feature = "std")]
+    {
+        std::os::unix::io::IntoRawFd::into_raw_fd(fd)
+    }
+    #[cfg(not(feature = "std"))]
+    {
+        fd
+    }
+}
@@ -0,0 +1,18 @@
+use crate::LockFile;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, RawFd};
+
+/// Borrow the descriptor of the [`LockFile`], such as to pass it to crates
+/// taking [`BorrowedFd`]. The descriptor is still owned by the handle; closing
+/// or locking it through other means confuses the state of the handle.
+impl AsFd for LockFile {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        unsafe { BorrowedFd::borrow_raw(self.raw()) }
+    }
+}
+
+/// Get the raw descriptor of the [`LockFile`], still owned by the handle.
+impl AsRawFd for LockFile {
+    fn as_raw_fd(&self) -> RawFd {
+        unsafe { self.raw() }
+    }
+}
@@ -8,8 +8,20 @@ use std::{fs::File, os::unix::io::FromRawFd as _};
 /// ```
 #[doc = include_str!("../../examples/lock_preserved.rs")]
 /// ```
-impl Into<File> for &mut LockFile {
-    fn into(self) -> File {
-        unsafe { File::from_raw_fd(self.raw()) }
+impl From<&mut LockFile> for File {
+    fn from(lock: &mut LockFile) -> File {
+        unsafe { File::from_raw_fd(lock.raw()) }
+    }
+}
+
+/// Turn the [`LockFile`] into a [`std::fs::File`] owning its descriptor. The
+/// lock owned by the handle, if any, stays held by the file until it is
+/// closed, and the file is neither truncated nor removed. With process-wide
+/// backends (`fcntl` and `lockf`), other handles of this process no longer
+/// wait for the lock, and closing any of them releases it.
+impl From<LockFile> for File {
+    fn from(mut lock: LockFile) -> File {
+        lock.unclaim();
+        unsafe { File::from_raw_fd(lock.take_desc()) }
     }
 }
@@ -0,0 +1,178 @@
+//! This module implements locks on POSIX named semaphores, which need no file
+//! in the file system.
+
+use super::{cvt, is_code};
+use crate::Error;
+use std::{ffi::CString, io::ErrorKind};
+
+/// A lock on a POSIX named semaphore (see `sem_overview(7)`), with an API
+/// mirroring [`crate::LockFile`], for when files cannot be created or
+/// written, such as on read-only root file systems or in restricted
+/// containers. The semaphore is created with a count of one and lives in the
+/// kernel (on Linux, in `/dev/shm`) until removed by
+/// [`NamedSemaphore::remove`].
+///
+/// Unlike file locks, the semaphore is NOT released if its owner exits
+/// without unlocking it, so a crashed owner leaves it locked until it is
+/// removed. Handles of the same process exclude each other, like those of
+/// other processes.
+///
+/// # Example
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// use fslock::NamedSemaphore;
+///
+/// let mut semaphore = NamedSemaphore::open("fslock-semaphore-doctest")?;
+/// semaphore.lock()?;
+/// do_stuff();
+/// semaphore.unlock()?;
+///
+/// # Ok(())
+/// # }
+/// # fn do_stuff() {
+/// #    // doing stuff here.
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct NamedSemaphore {
+    /// The opened semaphore.
+    sem: *mut libc::sem_t,
+    /// Name of the semaphore, including the leading slash.
+    name: String,
+    /// Whether this handle owns the semaphore.
+    locked: bool,
+}
+
+// Semaphores may be posted and waited on by any thread.
+unsafe impl Send for NamedSemaphore {}
+
+unsafe impl Sync for NamedSemaphore {}
+
+/// Returns the name of the semaphore with the given name as passed to libc.
+fn sem_name(name: &str) -> Result<CString, Error> {
+    if name.contains('\0') {
+        panic!("Semaphore names must not contain nul-bytes");
+    }
+    if name.is_empty() || name.contains('/') {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "semaphore names must be non-empty and contain no slashes",
+        ));
+    }
+    Ok(CString::new(format!("/{}", name)).expect("nul-bytes were checked"))
+}
+
+impl NamedSemaphore {
+    /// Opens the semaphore with the given name, creating it unlocked if it
+    /// does not exist. It is readable and writable by everyone, minus the bits
+    /// of the umask.
+    ///
+    /// # Panics
+    /// Panics if the name contains a nul-byte.
+    pub fn open(name: &str) -> Result<Self, Error> {
+        let c_name = sem_name(name)?;
+        let mode: libc::c_uint = 0o666;
+        let sem = unsafe {
+            libc::sem_open(
+                c_name.as_ptr(),
+                libc::O_CREAT,
+                mode,
+                1 as libc::c_uint,
+            )
+        };
+        if sem == libc::SEM_FAILED {
+            return Err(Error::last_os_error());
+        }
+        Ok(Self { sem, name: format!("/{}", name), locked: false })
+    }
+
+    /// Removes the semaphore with the given name, such as one left locked by
+    /// a crashed owner. Opened handles keep using the removed semaphore, while
+    /// handles opened later use a new one. Does nothing if it does not exist.
+    ///
+    /// # Panics
+    /// Panics if the name contains a nul-byte.
+    pub fn remove(name: &str) -> Result<(), Error> {
+        let c_name = sem_name(name)?;
+        match cvt(unsafe { libc::sem_unlink(c_name.as_ptr()) }) {
+            Err(error) if !is_code(&error, libc::ENOENT) => Err(error),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the name of the semaphore, including the leading slash.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns whether this handle owns the semaphore.
+    pub fn owns_lock(&self) -> bool {
+        self.locked
+    }
+
+    /// Locks the semaphore, blocking until it is unlocked by its owner.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns the semaphore.
+    pub fn lock(&mut self) -> Result<(), Error> {
+        if self.locked {
+            panic!("Cannot lock if already owning a lock");
+        }
+        loop {
+            match cvt(unsafe { libc::sem_wait(self.sem) }) {
+                Ok(_) => break,
+                Err(error) if is_code(&error, libc::EINTR) => {},
+                Err(error) => return Err(error),
+            }
+        }
+        self.locked = true;
+        Ok(())
+    }
+
+    /// Locks the semaphore if it is not locked. Does NOT block. Returns
+    /// whether it was locked.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns the semaphore.
+    pub fn try_lock(&mut self) -> Result<bool, Error> {
+        if self.locked {
+            panic!("Cannot lock if already owning a lock");
+        }
+        loop {
+            match cvt(unsafe { libc::sem_trywait(self.sem) }) {
+                Ok(_) => break,
+                Err(error) if is_code(&error, libc::EAGAIN) => {
+                    return Ok(false)
+                },
+                Err(error) if is_code(&error, libc::EINTR) => {},
+                Err(error) => return Err(error),
+            }
+        }
+        self.locked = true;
+        Ok(true)
+    }
+
+    /// Unlocks the semaphore.
+    ///
+    /// # Panics
+    /// Panics if this handle does not own the semaphore.
+    pub fn unlock(&mut self) -> Result<(), Error> {
+        if !self.locked {
+            panic!("Attempted to unlock already unlocked semaphore");
+        }
+        self.locked = false;
+        cvt(unsafe { libc::sem_post(self.sem) })?;
+        Ok(())
+    }
+}
+
+impl Drop for NamedSemaphore {
+    fn drop(&mut self) {
+        if self.locked {
+            let _ = self.unlock();
+        }
+        unsafe {
+            libc::sem_close(self.sem);
+        }
+    }
+}
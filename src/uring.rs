@@ -0,0 +1,556 @@
+//! This module implements an asynchronous backend on top of Linux's io_uring.
+//! All operations submitted to a [`Ring`] share a single completion thread,
+//! instead of occupying one blocking thread per pending operation.
+//!
+//! Locking itself has no io_uring opcode, so lock acquisition is done by
+//! polling non-blocking attempts, sleeping on an io_uring timeout in between.
+//!
+//! # Example
+//! ```
+//! # fn main() -> Result<(), fslock::Error> {
+//! use fslock::uring::Ring;
+//! use std::time::Duration;
+//!
+//! # let ring = match Ring::new(8) {
+//! #     Ok(ring) => ring,
+//! #     // Sandboxes and old kernels might not support io_uring.
+//! #     Err(_) => return Ok(()),
+//! # };
+//! # /*
+//! let ring = Ring::new(8)?;
+//! # */
+//! # block_on(async {
+//! let mut file = ring.open("testfiles/uring.lock").await?;
+//! ring.lock(&mut file, Duration::from_millis(10)).await?;
+//! ring.write(&file, b"hello").await?;
+//! ring.close(file).await?;
+//! # Ok::<(), fslock::Error>(())
+//! # })?;
+//!
+//! # Ok(())
+//! # }
+//! # fn block_on<F: std::future::Future>(future: F) -> F::Output {
+//! #     use std::{pin::pin, sync::Arc, task::{Context, Poll, Wake}, thread};
+//! #     struct Unpark(thread::Thread);
+//! #     impl Wake for Unpark {
+//! #         fn wake(self: Arc<Self>) { self.0.unpark() }
+//! #     }
+//! #     let waker = Arc::new(Unpark(thread::current())).into();
+//! #     let mut cx = Context::from_waker(&waker);
+//! #     let mut future = pin!(future);
+//! #     loop {
+//! #         match future.as_mut().poll(&mut cx) {
+//! #             Poll::Ready(output) => break output,
+//! #             Poll::Pending => thread::park(),
+//! #         }
+//! #     }
+//! # }
+//! ```
+
+use crate::{sys, Error, IntoOsString, LockFile, ToOsStr};
+use core::{
+    any::Any,
+    fmt,
+    future::Future,
+    mem,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+use io_uring::{opcode, squeue, types, IoUring, Probe};
+use std::{
+    ffi::{CStr, CString},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+        Mutex,
+        MutexGuard,
+    },
+    thread,
+};
+
+/// Flags used to open lock files, the same as the blocking backend's.
+const OPEN_FLAGS: libc::c_int = libc::O_RDWR | libc::O_CLOEXEC | libc::O_CREAT;
+
+/// Permissions of created lock files, the same as the blocking backend's.
+const OPEN_MODE: libc::mode_t =
+    libc::S_IRUSR | libc::S_IWUSR | libc::S_IRGRP | libc::S_IROTH;
+
+/// Converts a raw completion result into a proper result.
+fn cvt(res: i32) -> Result<i32, Error> {
+    if res < 0 {
+        Err(Error::from_raw_os_error(-res))
+    } else {
+        Ok(res)
+    }
+}
+
+/// Locks a mutex, ignoring poisoning, since slots are always left consistent.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poison| poison.into_inner())
+}
+
+/// A shared io_uring instance driving lock file operations. Cloning it is
+/// cheap and clones share the same ring and completion thread, which exits
+/// once every clone is dropped and every submitted operation is completed.
+#[derive(Clone)]
+pub struct Ring {
+    /// Handle keeping the completion thread alive.
+    handle: Arc<Handle>,
+}
+
+impl fmt::Debug for Ring {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Ring")
+            .field("eventfd", &self.handle.inner.eventfd)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Ring {
+    /// Creates a new ring with the given number of submission entries (which
+    /// should be a power of two) and spawns its completion thread.
+    pub fn new(entries: u32) -> Result<Self, Error> {
+        let ring = IoUring::new(entries)?;
+
+        let mut probe = Probe::new();
+        let ftruncate = ring.submitter().register_probe(&mut probe).is_ok()
+            && probe.is_supported(opcode::Ftruncate::CODE);
+
+        let eventfd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC) };
+        if eventfd < 0 {
+            return Err(Error::last_os_error());
+        }
+        let inner = Arc::new(Inner {
+            ring: Mutex::new(ring),
+            slots: Mutex::new(Slots::default()),
+            shutdown: AtomicBool::new(false),
+            eventfd,
+            ftruncate,
+        });
+        lock(&inner.ring).submitter().register_eventfd(eventfd)?;
+
+        let reaper = inner.clone();
+        thread::Builder::new()
+            .name("fslock-uring".into())
+            .spawn(move || reaper.reap())?;
+
+        Ok(Self { handle: Arc::new(Handle { inner }) })
+    }
+
+    /// Opens a file for locking, creating it if it does not exist, just like
+    /// [`LockFile::open`]. Since the file is opened in a single operation,
+    /// [`LockFile::was_created`] always returns `false` for it.
+    ///
+    /// # Panics
+    /// Panics if the path contains a nul-byte in a place other than the end.
+    pub async fn open<P>(&self, path: &P) -> Result<LockFile, Error>
+    where
+        P: ToOsStr + ?Sized,
+    {
+        let path = path.to_os_str()?.into_os_string()?;
+        let c_path = unsafe { CStr::from_ptr(path.as_ptr()) }.to_owned();
+        let entry =
+            opcode::OpenAt::new(types::Fd(libc::AT_FDCWD), c_path.as_ptr())
+                .flags(OPEN_FLAGS)
+                .mode(OPEN_MODE)
+                .build();
+        let keep: Box<CString> = Box::new(c_path);
+        let desc = cvt(self.submit(entry, Some(keep), true)?.await)?;
+        Ok(LockFile::new(path, desc))
+    }
+
+    /// Truncates the file and seeks it to the start. Falls back to a blocking
+    /// `ftruncate` on kernels without the io_uring truncate operation.
+    pub async fn truncate(&self, file: &LockFile) -> Result<(), Error> {
+        let res = unsafe { libc::lseek(file.desc, 0, libc::SEEK_SET) };
+        if res < 0 {
+            return Err(Error::last_os_error());
+        }
+        if !self.handle.inner.ftruncate {
+            return sys::truncate(file.desc);
+        }
+        let entry = opcode::Ftruncate::new(types::Fd(file.desc), 0).build();
+        cvt(self.submit(entry, None, false)?.await)?;
+        Ok(())
+    }
+
+    /// Writes all the given bytes at the current position of the file.
+    pub async fn write(
+        &self,
+        file: &LockFile,
+        bytes: &[u8],
+    ) -> Result<(), Error> {
+        let mut start = 0;
+        while start < bytes.len() {
+            let buf = bytes[start ..].to_vec().into_boxed_slice();
+            let entry = opcode::Write::new(
+                types::Fd(file.desc),
+                buf.as_ptr(),
+                buf.len() as u32,
+            )
+            .offset(u64::MAX)
+            .build();
+            let keep: Box<Box<[u8]>> = Box::new(buf);
+            match cvt(self.submit(entry, Some(keep), false)?.await)? {
+                0 => return Err(sys::write_zero()),
+                written => start += written as usize,
+            }
+        }
+        Ok(())
+    }
+
+    /// Flushes the file's data and metadata to the disk.
+    pub async fn fsync(&self, file: &LockFile) -> Result<(), Error> {
+        let entry = opcode::Fsync::new(types::Fd(file.desc)).build();
+        cvt(self.submit(entry, None, false)?.await)?;
+        Ok(())
+    }
+
+    /// Locks the file, polling non-blocking attempts at the given interval.
+    /// After locked, the file is unlocked by [`Ring::close`] or by the file
+    /// handle drop.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns the file.
+    pub async fn lock(
+        &self,
+        file: &mut LockFile,
+        interval: Duration,
+    ) -> Result<(), Error> {
+        while !file.try_lock()? {
+            self.sleep(interval).await?;
+        }
+        Ok(())
+    }
+
+    /// Locks the file like [`Ring::lock`] and writes this process's PID into
+    /// it, unlocking again if the write fails.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns the file.
+    pub async fn lock_with_pid(
+        &self,
+        file: &mut LockFile,
+        interval: Duration,
+    ) -> Result<(), Error> {
+        self.lock(file, interval).await?;
+        let pid = format!("{}\n", sys::pid());
+        let result = self.write(file, pid.as_bytes()).await;
+        if result.is_err() {
+            let _ = file.unlock();
+        }
+        result
+    }
+
+    /// Unlocks the file if locked (truncating or removing it if configured to,
+    /// like [`LockFile::unlock`]) and closes it, without blocking on the
+    /// close.
+    pub async fn close(&self, mut file: LockFile) -> Result<(), Error> {
+        let mut result = Ok(());
+        if file.locked {
+            result = match file.release_lock() {
+                Ok(true) => self.truncate(&file).await,
+                Ok(false) => Ok(()),
+                Err(error) => Err(error),
+            };
+        }
+        let desc = file.take_desc();
+        let entry = opcode::Close::new(types::Fd(desc)).build();
+        let closed = cvt(self.submit(entry, None, false)?.await).map(|_| ());
+        result.and(closed)
+    }
+
+    /// Sleeps for the given duration on an io_uring timeout.
+    async fn sleep(&self, duration: Duration) -> Result<(), Error> {
+        let timespec = Box::new(types::Timespec::from(duration));
+        let entry = opcode::Timeout::new(&*timespec).build();
+        match self.submit(entry, Some(timespec), false)?.await {
+            res if res == -libc::ETIME => Ok(()),
+            res => cvt(res).map(|_| ()),
+        }
+    }
+
+    /// Submits an entry, keeping the given allocation alive until the
+    /// operation completes, even if the returned future is dropped. If
+    /// `owns_fd`, a successful result is a descriptor closed if abandoned.
+    fn submit(
+        &self,
+        entry: squeue::Entry,
+        keep: Option<Box<dyn Any + Send>>,
+        owns_fd: bool,
+    ) -> Result<Op, Error> {
+        let inner = &self.handle.inner;
+        let mut slots = lock(&inner.slots);
+        if let Some(errno) = slots.failed {
+            return Err(Error::from_raw_os_error(errno));
+        }
+        let index =
+            slots.insert(Slot { state: State::Pending(None), keep, owns_fd });
+        drop(slots);
+        let entry = entry.user_data(index as u64);
+
+        let mut ring = lock(&inner.ring);
+        let mut result = Ok(());
+        while result.is_ok()
+            && unsafe { ring.submission().push(&entry) }.is_err()
+        {
+            result = ring.submit().map(|_| ());
+        }
+        let result = result.and_then(|_| ring.submit());
+        drop(ring);
+
+        match result {
+            Ok(_) => Ok(Op { ring: self.clone(), index, done: false }),
+            Err(error) => {
+                lock(&inner.slots).remove(index);
+                Err(error)
+            },
+        }
+    }
+}
+
+/// Signals the completion thread to shut down when dropped.
+struct Handle {
+    /// State shared with the completion thread.
+    inner: Arc<Inner>,
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        self.inner.shutdown.store(true, Ordering::Release);
+        self.inner.notify();
+    }
+}
+
+/// State shared between submitters and the completion thread.
+struct Inner {
+    /// The io_uring instance itself.
+    ring: Mutex<IoUring>,
+    /// In-flight operations, indexed by their user data.
+    slots: Mutex<Slots>,
+    /// Whether the completion thread should exit once idle.
+    shutdown: AtomicBool,
+    /// Event file notified by the kernel on completions.
+    eventfd: libc::c_int,
+    /// Whether the kernel supports `IORING_OP_FTRUNCATE`.
+    ftruncate: bool,
+}
+
+impl Inner {
+    /// Wakes the completion thread up.
+    fn notify(&self) {
+        let value = 1u64;
+        unsafe {
+            libc::write(
+                self.eventfd,
+                &value as *const u64 as *const libc::c_void,
+                8,
+            );
+        }
+    }
+
+    /// Body of the completion thread.
+    fn reap(&self) {
+        let mut completed = Vec::new();
+        loop {
+            let mut value = 0u64;
+            let res = unsafe {
+                libc::read(
+                    self.eventfd,
+                    &mut value as *mut u64 as *mut libc::c_void,
+                    8,
+                )
+            };
+            if res < 0 {
+                let errno = sys::errno();
+                if errno != libc::EINTR {
+                    lock(&self.slots).fail(errno);
+                    break;
+                }
+            }
+
+            completed.extend(
+                lock(&self.ring)
+                    .completion()
+                    .map(|entry| (entry.user_data() as usize, entry.result())),
+            );
+            let mut slots = lock(&self.slots);
+            for (index, res) in completed.drain(..) {
+                slots.complete(index, res);
+            }
+            if self.shutdown.load(Ordering::Acquire) && slots.pending == 0 {
+                break;
+            }
+        }
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        sys::close(self.eventfd);
+    }
+}
+
+/// Table of in-flight operations.
+#[derive(Default)]
+struct Slots {
+    /// Slots, possibly vacant.
+    entries: Vec<Option<Slot>>,
+    /// Indices of vacant slots.
+    free: Vec<usize>,
+    /// Number of occupied slots.
+    pending: usize,
+    /// Error which stopped the completion thread, if any.
+    failed: Option<i32>,
+}
+
+impl Slots {
+    /// Occupies a slot, returning its index.
+    fn insert(&mut self, slot: Slot) -> usize {
+        self.pending += 1;
+        match self.free.pop() {
+            Some(index) => {
+                self.entries[index] = Some(slot);
+                index
+            },
+            None => {
+                self.entries.push(Some(slot));
+                self.entries.len() - 1
+            },
+        }
+    }
+
+    /// Vacates a slot, returning its content.
+    fn remove(&mut self, index: usize) -> Option<Slot> {
+        let slot = self.entries.get_mut(index)?.take()?;
+        self.pending -= 1;
+        self.free.push(index);
+        Some(slot)
+    }
+
+    /// Fails every operation with the given error, since their completions
+    /// will no longer be reaped, and so will operations submitted later. The
+    /// allocations of operations are leaked, since the kernel may still use
+    /// them.
+    fn fail(&mut self, errno: i32) {
+        self.failed = Some(errno);
+        for index in 0 .. self.entries.len() {
+            if let Some(slot) = &mut self.entries[index] {
+                mem::forget(slot.keep.take());
+            }
+            self.complete(index, -errno);
+        }
+    }
+
+    /// Records the result of an operation, waking its future up.
+    fn complete(&mut self, index: usize, res: i32) {
+        let slot = match self.entries.get_mut(index) {
+            Some(Some(slot)) => slot,
+            _ => return,
+        };
+        match &mut slot.state {
+            State::Pending(waker) => {
+                if let Some(waker) = waker.take() {
+                    waker.wake();
+                }
+                slot.state = State::Done(res);
+            },
+            State::Abandoned => {
+                if let Some(slot) = self.remove(index) {
+                    slot.release(res);
+                }
+            },
+            State::Done(_) => (),
+        }
+    }
+}
+
+/// An in-flight operation.
+struct Slot {
+    /// Progress of the operation.
+    state: State,
+    /// Allocation that must outlive the operation.
+    keep: Option<Box<dyn Any + Send>>,
+    /// Whether a successful result is a descriptor owned by the operation.
+    owns_fd: bool,
+}
+
+impl Slot {
+    /// Releases resources of an operation nobody is waiting for.
+    fn release(self, res: i32) {
+        if self.owns_fd && res >= 0 {
+            sys::close(res);
+        }
+    }
+}
+
+/// Progress of an operation.
+enum State {
+    /// Not completed yet, possibly with a future waiting for it.
+    Pending(Option<Waker>),
+    /// Completed with the given raw result.
+    Done(i32),
+    /// Not completed yet, but its future was dropped.
+    Abandoned,
+}
+
+/// Future of a submitted operation, yielding its raw result.
+struct Op {
+    /// Ring the operation was submitted to.
+    ring: Ring,
+    /// Index of the operation's slot.
+    index: usize,
+    /// Whether the result was already yielded.
+    done: bool,
+}
+
+impl Future for Op {
+    type Output = i32;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<i32> {
+        let inner = self.ring.handle.inner.clone();
+        let mut slots = lock(&inner.slots);
+        let slot = match slots.entries.get_mut(self.index) {
+            Some(Some(slot)) => slot,
+            _ => panic!("Polled a completed io_uring operation"),
+        };
+        match &mut slot.state {
+            State::Done(res) => {
+                let res = *res;
+                slots.remove(self.index);
+                self.done = true;
+                Poll::Ready(res)
+            },
+            State::Pending(waker) => {
+                *waker = Some(cx.waker().clone());
+                Poll::Pending
+            },
+            State::Abandoned => unreachable!(),
+        }
+    }
+}
+
+impl Drop for Op {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        let mut slots = lock(&self.ring.handle.inner.slots);
+        let done = match slots.entries.get_mut(self.index) {
+            Some(Some(slot)) => match slot.state {
+                State::Done(res) => Some(res),
+                _ => {
+                    slot.state = State::Abandoned;
+                    None
+                },
+            },
+            _ => None,
+        };
+        if let Some(res) = done {
+            if let Some(slot) = slots.remove(self.index) {
+                slot.release(res);
+            }
+        }
+    }
+}
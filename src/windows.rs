@@ -1,47 +1,86 @@
 #[cfg(feature = "std")]
+mod as_handle;
+#[cfg(feature = "std")]
 mod into_file;
+#[cfg(feature = "std")]
+mod mutex;
+#[cfg(feature = "std")]
+pub use mutex::NamedMutex;
 
 #[cfg(not(feature = "std"))]
 use winapi::um::{
     winbase::{
         FormatMessageW,
-        FORMAT_MESSAGE_ALLOCATE_BUFFER,
         FORMAT_MESSAGE_FROM_SYSTEM,
         FORMAT_MESSAGE_IGNORE_INSERTS,
     },
-    winnt::{LANG_USER_DEFAULT, LPWSTR},
+    winnt::LANG_USER_DEFAULT,
+};
+
+#[cfg(feature = "std")]
+use winapi::{
+    shared::{
+        minwindef::FILETIME,
+        winerror::{
+            ERROR_ACCESS_DENIED,
+            ERROR_HANDLE_EOF,
+            ERROR_INVALID_PARAMETER,
+        },
+    },
+    um::{
+        fileapi::{ReadFile, SetFileTime},
+        minwinbase::STILL_ACTIVE,
+        processthreadsapi::{GetExitCodeProcess, GetProcessTimes, OpenProcess},
+        sysinfoapi::{GetSystemTimeAsFileTime, GetTickCount64},
+        winbase::{GetComputerNameW, FILE_CURRENT, FILE_FLAG_BACKUP_SEMANTICS},
+        winnt::{FILE_WRITE_ATTRIBUTES, PROCESS_QUERY_LIMITED_INFORMATION},
+    },
 };
 
 #[cfg(feature = "std")]
 use std::{ffi, os::windows::ffi::OsStrExt};
 
-use crate::{EitherOsStr, IntoOsString, ToOsStr};
+use crate::{EitherOsStr, IntoOsString, LockFileOptions, ToOsStr};
 use core::{
     convert::TryFrom,
     fmt,
-    mem::{transmute, MaybeUninit},
+    mem::{self, transmute, MaybeUninit},
     ptr::{self, NonNull},
     slice,
+    sync::atomic::{AtomicUsize, Ordering},
 };
 use winapi::{
     shared::{
-        minwindef::{DWORD, FALSE, LPCVOID, LPVOID, TRUE},
-        winerror::{ERROR_INVALID_DATA, ERROR_LOCK_VIOLATION},
+        minwindef::{BOOL, DWORD, FALSE, LPCVOID, LPVOID, TRUE},
+        winerror::{
+            ERROR_ALREADY_EXISTS,
+            ERROR_CANT_RESOLVE_FILENAME,
+            ERROR_INVALID_DATA,
+            ERROR_LOCK_VIOLATION,
+            ERROR_NOT_ENOUGH_MEMORY,
+        },
     },
     um::{
+        consoleapi::SetConsoleCtrlHandler,
         errhandlingapi::GetLastError,
         fileapi::{
             CreateFileW,
+            DeleteFileW,
             FlushFileBuffers,
+            GetFileInformationByHandle,
             LockFileEx,
             SetEndOfFile,
             SetFilePointer,
+            SetFilePointerEx,
             UnlockFileEx,
             WriteFile,
+            BY_HANDLE_FILE_INFORMATION,
+            CREATE_NEW,
             INVALID_SET_FILE_POINTER,
             OPEN_ALWAYS,
+            OPEN_EXISTING,
         },
-        handleapi::{CloseHandle, INVALID_HANDLE_VALUE},
+        handleapi::{CloseHandle, DuplicateHandle, INVALID_HANDLE_VALUE},
         minwinbase::{
             OVERLAPPED_u,
             LMEM_FIXED,
@@ -52,16 +91,33 @@ use winapi::{
             OVERLAPPED,
             SECURITY_ATTRIBUTES,
         },
-        processthreadsapi::GetCurrentProcessId,
+        processthreadsapi::{GetCurrentProcess, GetCurrentProcessId},
         synchapi::{CreateEventW, WaitForSingleObject},
-        winbase::{LocalAlloc, LocalFree, FILE_BEGIN, WAIT_FAILED},
+        winbase::{
+            LocalAlloc,
+            LocalFree,
+            FILE_BEGIN,
+            FILE_END,
+            FILE_FLAG_OPEN_REPARSE_POINT,
+            WAIT_FAILED,
+        },
+        wincon::{
+            CTRL_BREAK_EVENT,
+            CTRL_CLOSE_EVENT,
+            CTRL_C_EVENT,
+            CTRL_SHUTDOWN_EVENT,
+        },
         winnt::{
             RtlCopyMemory,
+            DUPLICATE_SAME_ACCESS,
+            FILE_ATTRIBUTE_REPARSE_POINT,
             FILE_SHARE_DELETE,
             FILE_SHARE_READ,
             FILE_SHARE_WRITE,
+            GENERIC_READ,
             GENERIC_WRITE,
             HANDLE,
+            LARGE_INTEGER,
             WCHAR,
         },
     },
@@ -70,6 +126,18 @@ use winapi::{
 /// A type representing file descriptor on Unix.
 pub type FileDesc = HANDLE;
 
+/// Sharing mode of lock files unless configured otherwise.
+pub(crate) const DEFAULT_SHARE_MODE: DWORD =
+    FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE;
+
+/// A type representing an owned file handle on Windows.
+#[cfg(feature = "std")]
+pub type OwnedDesc = std::os::windows::io::OwnedHandle;
+
+/// A type representing an owned file handle on Windows.
+#[cfg(not(feature = "std"))]
+pub type OwnedDesc = FileDesc;
+
 /// A type representing Process ID on Windows.
 pub type Pid = DWORD;
 
@@ -102,40 +170,42 @@ impl Error {
     }
 }
 
+/// Capacity of the buffer receiving error messages, in UTF-16 units.
+#[cfg(not(feature = "std"))]
+const MESSAGE_CAPACITY: usize = 512;
+
 #[cfg(not(feature = "std"))]
 impl fmt::Display for Error {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        let mut buf: LPWSTR = ptr::null_mut();
+        let mut buf = [0 as WCHAR; MESSAGE_CAPACITY];
         let res = unsafe {
             FormatMessageW(
-                FORMAT_MESSAGE_ALLOCATE_BUFFER
-                    | FORMAT_MESSAGE_FROM_SYSTEM
-                    | FORMAT_MESSAGE_IGNORE_INSERTS,
+                FORMAT_MESSAGE_FROM_SYSTEM | FORMAT_MESSAGE_IGNORE_INSERTS,
                 ptr::null_mut(),
                 self.code as DWORD,
                 LANG_USER_DEFAULT as DWORD,
-                &mut buf as *mut LPWSTR as LPWSTR,
-                0,
+                buf.as_mut_ptr(),
+                MESSAGE_CAPACITY as DWORD,
                 ptr::null_mut(),
             )
         };
 
         if res == 0 {
-            write!(fmt, "error getting error message")?;
-        } else {
-            {
-                let slice = unsafe {
-                    slice::from_raw_parts(buf as *const WCHAR, res as usize)
-                };
-                let str = unsafe { OsStr::from_slice(slice) };
-                write!(fmt, "{}", str)?;
-            }
-            unsafe {
-                LocalFree(buf as LPVOID);
-            }
+            return write!(fmt, "OS error {}", self.code);
         }
 
-        Ok(())
+        // System messages end with a line break.
+        let mut message = &buf[.. res as usize];
+        while let Some((&last, init)) = message.split_last() {
+            if last > 0x7F || !(last as u8).is_ascii_whitespace() {
+                break;
+            }
+            message = init;
+        }
+        for ch in char::decode_utf16(message.iter().copied()) {
+            write!(fmt, "{}", ch.unwrap_or(char::REPLACEMENT_CHARACTER))?;
+        }
+        write!(fmt, " (os error {})", self.code)
     }
 }
 
@@ -146,6 +216,12 @@ pub struct OsString {
     len: usize,
 }
 
+// Safe because the allocation is uniquely owned and never mutated.
+unsafe impl Send for OsString {}
+
+// Safe because the allocation is never mutated through shared references.
+unsafe impl Sync for OsString {}
+
 impl Drop for OsString {
     fn drop(&mut self) {
         let ptr = self.alloc.as_ptr() as LPVOID;
@@ -167,24 +243,36 @@ impl AsRef<OsStr> for OsString {
 }
 
 /// Borrowed allocation of an OS-native string.
+#[repr(transparent)]
 pub struct OsStr {
     chars: [WCHAR],
 }
 
 impl OsStr {
-    /// Unsafe cause sequence needs to end with 0.
+    /// Unsafe cause sequence needs to be followed by 0, which is not part of
+    /// the slice.
     unsafe fn from_slice(slice: &[WCHAR]) -> &Self {
         transmute(slice)
     }
 
-    fn chars(&self) -> Chars {
-        Chars { inner: self.chars.iter() }
+    /// Converts into a standard library path.
+    #[cfg(feature = "std")]
+    pub(crate) fn to_path_buf(&self) -> std::path::PathBuf {
+        use std::os::windows::ffi::OsStringExt;
+        ffi::OsString::from_wide(&self.chars).into()
+    }
+
+    /// Decodes the UTF-16 sequence, replacing unpaired surrogates with
+    /// `U+FFFD`.
+    fn chars(&self) -> impl Iterator<Item = char> + '_ {
+        char::decode_utf16(self.chars.iter().copied())
+            .map(|res| res.unwrap_or(char::REPLACEMENT_CHARACTER))
     }
 }
 
 impl fmt::Debug for OsStr {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        let mut first = false;
+        let mut first = true;
         write!(fmt, "[")?;
 
         for ch in self.chars() {
@@ -211,22 +299,23 @@ impl fmt::Display for OsStr {
     }
 }
 
-impl<'str> IntoOsString for &'str OsStr {
+impl IntoOsString for &OsStr {
     fn into_os_string(self) -> Result<OsString, Error> {
         let len = self.chars.len();
         let alloc = unsafe { LocalAlloc(LMEM_FIXED, len * 2 + 2) };
         let alloc = match NonNull::new(alloc as *mut WCHAR) {
             Some(alloc) => alloc,
             None => {
-                return Err(Error::last_os_error());
+                return Err(last_error());
             },
         };
         unsafe {
             RtlCopyMemory(
                 alloc.as_ptr() as LPVOID,
                 self.chars.as_ptr() as _,
-                len * 2 + 2,
+                len * 2,
             );
+            *alloc.as_ptr().add(len) = 0;
         }
 
         Ok(OsString { alloc, len })
@@ -234,7 +323,7 @@ impl<'str> IntoOsString for &'str OsStr {
 }
 
 impl ToOsStr for str {
-    fn to_os_str(&self) -> Result<EitherOsStr, Error> {
+    fn to_os_str(&self) -> Result<EitherOsStr<'_>, Error> {
         let res = unsafe { make_os_string(|| self.encode_utf16()) };
         res.map(EitherOsStr::Owned)
     }
@@ -242,7 +331,7 @@ impl ToOsStr for str {
 
 #[cfg(feature = "std")]
 impl ToOsStr for ffi::OsStr {
-    fn to_os_str(&self) -> Result<EitherOsStr, Error> {
+    fn to_os_str(&self) -> Result<EitherOsStr<'_>, Error> {
         let res = unsafe { make_os_string(|| self.encode_wide()) };
         res.map(EitherOsStr::Owned)
     }
@@ -271,7 +360,7 @@ where
     let alloc = match NonNull::new(alloc as *mut WCHAR) {
         Some(alloc) => alloc,
         None => {
-            return Err(Error::last_os_error());
+            return Err(last_error());
         },
     };
 
@@ -284,31 +373,6 @@ where
     Ok(OsString { alloc, len })
 }
 
-#[derive(Debug)]
-struct Chars<'str> {
-    inner: slice::Iter<'str, WCHAR>,
-}
-
-impl<'str> Iterator for Chars<'str> {
-    type Item = char;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        let curr = *self.inner.next()?;
-        if curr <= 0xD7FF || curr >= 0xE000 {
-            let ch = char::try_from(curr as u32)
-                .expect("Inconsistent char implementation");
-            Some(ch)
-        } else {
-            let next = *self.inner.next()?;
-            let high = curr as u32 - 0xD800;
-            let low = next as u32 - 0xDC00;
-            let ch = char::try_from((high << 10 | low) + 0x10000)
-                .expect("Inconsistent char implementation");
-            Some(ch)
-        }
-    }
-}
-
 /// Helper to auto-drop a HANDLE.
 #[derive(Debug)]
 struct DropHandle {
@@ -324,6 +388,33 @@ impl Drop for DropHandle {
     }
 }
 
+/// Captures the calling thread's last error code as an error.
+fn last_error() -> Error {
+    Error::from_raw_os_error(unsafe { GetLastError() } as i32)
+}
+
+/// Checks the return value of a Windows API call returning `BOOL`. On failure,
+/// the last error code is captured right away, before anything else (such as
+/// closing helper handles) gets the chance to overwrite it, and the error is
+/// returned by value.
+fn cvt(res: BOOL) -> Result<(), Error> {
+    if res == FALSE {
+        Err(last_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Checks a HANDLE returned by a Windows API call, capturing the last error
+/// code right away on failure.
+fn cvt_handle(handle: HANDLE, invalid: HANDLE) -> Result<HANDLE, Error> {
+    if handle == invalid {
+        Err(last_error())
+    } else {
+        Ok(handle)
+    }
+}
+
 /// Creates an event to be used by this implementation.
 fn make_event() -> Result<HANDLE, Error> {
     let mut security = make_security_attributes();
@@ -335,34 +426,38 @@ fn make_event() -> Result<HANDLE, Error> {
             ptr::null_mut(),
         )
     };
-
-    if res != INVALID_HANDLE_VALUE {
-        Ok(res)
-    } else {
-        Err(Error::last_os_error())
-    }
+    // Unlike CreateFileW, CreateEventW reports failure with a null handle.
+    cvt_handle(res, ptr::null_mut())
 }
 
 /// Creates security attributes to be used with this implementation.
 fn make_security_attributes() -> SECURITY_ATTRIBUTES {
     SECURITY_ATTRIBUTES {
-        nLength: 0,
+        nLength: mem::size_of::<SECURITY_ATTRIBUTES>() as DWORD,
         lpSecurityDescriptor: ptr::null_mut(),
         bInheritHandle: FALSE,
     }
 }
 
-/// Creates an overlapped struct to be used with this implementation.
-fn make_overlapped() -> Result<OVERLAPPED, Error> {
+/// Offset of the region locked by whole-file locks, far beyond any data.
+const WHOLE_FILE_OFFSET: u64 =
+    (DWORD::MAX - 1) as u64 | ((DWORD::MAX - 1) as u64) << 32;
+
+/// Length of the region locked by whole-file locks.
+const WHOLE_FILE_LEN: u64 = 1 | 1 << 32;
+
+/// Creates an overlapped struct to be used with this implementation, pointing
+/// at the given offset.
+fn make_overlapped(offset: u64) -> Result<OVERLAPPED, Error> {
     Ok(OVERLAPPED {
         Internal: 0,
         InternalHigh: 0,
         u: {
             let mut uninit = MaybeUninit::<OVERLAPPED_u>::uninit();
             unsafe {
-                let mut refer = (&mut *uninit.as_mut_ptr()).s_mut();
-                refer.Offset = DWORD::max_value() - 1;
-                refer.OffsetHigh = DWORD::max_value() - 1;
+                let refer = (*uninit.as_mut_ptr()).s_mut();
+                refer.Offset = offset as DWORD;
+                refer.OffsetHigh = (offset >> 32) as DWORD;
                 uninit.assume_init()
             }
         },
@@ -370,164 +465,568 @@ fn make_overlapped() -> Result<OVERLAPPED, Error> {
     })
 }
 
+/// Waits for an overlapped operation started by a call which returned `res`,
+/// capturing errors of the call and of the wait right away.
+fn finish_overlapped(res: BOOL, overlapped: &OVERLAPPED) -> Result<(), Error> {
+    cvt(res)?;
+    let res = unsafe { WaitForSingleObject(overlapped.hEvent, 0) };
+    if res == WAIT_FAILED {
+        Err(last_error())
+    } else {
+        Ok(())
+    }
+}
+
 /// Returns the ID of the current process.
 pub fn pid() -> Pid {
     unsafe { GetCurrentProcessId() }
 }
 
-/// Opens a file with only purpose of locking it. Creates it if it does not
-/// exist. Path must not contain a nul-byte in the middle, but a nul-byte in the
-/// end (and only in the end) is allowed, which in this case no extra allocation
-/// will be made. Otherwise, an extra allocation is made.
-pub fn open(path: &OsStr) -> Result<FileDesc, Error> {
+/// Returns the NetBIOS name of the machine.
+#[cfg(feature = "std")]
+pub fn hostname() -> Result<String, Error> {
+    let mut buf = [0u16; 256];
+    let mut len = buf.len() as DWORD;
+    cvt(unsafe { GetComputerNameW(buf.as_mut_ptr(), &mut len) })?;
+    Ok(String::from_utf16_lossy(&buf[.. len as usize]))
+}
+
+/// Returns whether a process with the given PID exists and has not exited.
+#[cfg(feature = "std")]
+pub fn process_alive(pid: u32) -> Result<bool, Error> {
+    let handle =
+        unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, FALSE, pid) };
+    if handle.is_null() {
+        let error = last_error();
+        return match error.raw_os_error() {
+            Some(code) if code == ERROR_ACCESS_DENIED as i32 => Ok(true),
+            Some(code) if code == ERROR_INVALID_PARAMETER as i32 => Ok(false),
+            _ => Err(error),
+        };
+    }
+    let _drop_handle = DropHandle { handle };
+    let mut code: DWORD = 0;
+    cvt(unsafe { GetExitCodeProcess(handle, &mut code) })?;
+    Ok(code == STILL_ACTIVE)
+}
+
+/// Returns the creation time of the process with the given PID, in 100
+/// nanosecond intervals since 1601, if it exists and may be queried.
+#[cfg(feature = "std")]
+pub fn process_start_time(pid: u32) -> Option<u64> {
+    let handle =
+        unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, FALSE, pid) };
+    if handle.is_null() {
+        return None;
+    }
+    let _drop_handle = DropHandle { handle };
+    let mut times: [FILETIME; 4] = unsafe { mem::zeroed() };
+    let [creation, exit, kernel, user] = &mut times;
+    cvt(unsafe { GetProcessTimes(handle, creation, exit, kernel, user) })
+        .ok()?;
+    Some((creation.dwHighDateTime as u64) << 32 | creation.dwLowDateTime as u64)
+}
+
+/// Returns an identifier of the current boot of the machine: the boot time,
+/// in seconds since the Unix epoch.
+#[cfg(feature = "std")]
+pub fn boot_id() -> Option<String> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()?;
+    let uptime = core::time::Duration::from_millis(unsafe { GetTickCount64() });
+    Some(now.checked_sub(uptime)?.as_secs().to_string())
+}
+
+/// Returns whether the given boot identifiers refer to the same boot. Boot
+/// times are computed from the clock and the uptime, so they may drift a bit
+/// between calls.
+#[cfg(feature = "std")]
+pub fn same_boot(recorded: &str, current: &str) -> bool {
+    match (recorded.parse::<u64>(), current.parse::<u64>()) {
+        (Ok(recorded), Ok(current)) => recorded.abs_diff(current) <= 2,
+        _ => recorded == current,
+    }
+}
+
+/// Opens a file with only purpose of locking it, creating it if it does not
+/// exist and the options ask to. Path must not contain a nul-byte in the
+/// middle, but a nul-byte in the end (and only in the end) is allowed, which in
+/// this case no extra allocation will be made. Otherwise, an extra allocation
+/// is made. Returns the descriptor and whether this call created the file.
+pub fn open(
+    path: &OsStr,
+    options: &LockFileOptions,
+) -> Result<(FileDesc, bool), Error> {
     let mut security = make_security_attributes();
+    let security = match options.security_attributes {
+        0 => &mut security as LPSECURITY_ATTRIBUTES,
+        address => address as LPSECURITY_ATTRIBUTES,
+    };
+    let mut access = 0;
+    if options.read || !options.write {
+        access |= GENERIC_READ;
+    }
+    if options.write {
+        access |= GENERIC_WRITE;
+    }
+    let disposition = if options.create_new {
+        CREATE_NEW
+    } else if options.create {
+        OPEN_ALWAYS
+    } else {
+        OPEN_EXISTING
+    };
     let handle = unsafe {
         CreateFileW(
             path.chars.as_ptr(),
-            GENERIC_WRITE,
-            FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
-            &mut security as LPSECURITY_ATTRIBUTES,
-            OPEN_ALWAYS,
-            0,
+            access,
+            options.share_mode,
+            security,
+            disposition,
+            if options.follow_symlinks {
+                0
+            } else {
+                FILE_FLAG_OPEN_REPARSE_POINT
+            },
             ptr::null_mut(),
         )
     };
+    let handle = cvt_handle(handle, INVALID_HANDLE_VALUE)?;
+    let created = match disposition {
+        CREATE_NEW => true,
+        OPEN_ALWAYS => unsafe { GetLastError() != ERROR_ALREADY_EXISTS },
+        _ => false,
+    };
+    if !options.follow_symlinks {
+        if let Err(error) = reject_reparse_point(handle) {
+            close(handle);
+            return Err(error);
+        }
+    }
+    Ok((handle, created))
+}
 
-    if handle != INVALID_HANDLE_VALUE {
-        Ok(handle)
+/// Fails if the given handle was opened on a reparse point, such as a symbolic
+/// link or a junction.
+fn reject_reparse_point(handle: FileDesc) -> Result<(), Error> {
+    let mut info = unsafe { mem::zeroed::<BY_HANDLE_FILE_INFORMATION>() };
+    cvt(unsafe { GetFileInformationByHandle(handle, &mut info) })?;
+    if info.dwFileAttributes & FILE_ATTRIBUTE_REPARSE_POINT != 0 {
+        Err(Error::from_raw_os_error(ERROR_CANT_RESOLVE_FILENAME as i32))
     } else {
-        Err(Error::last_os_error())
+        Ok(())
     }
 }
 
 /// Writes data into the given open file.
-pub fn write(handle: FileDesc, bytes: &[u8]) -> Result<(), Error> {
-    let result = unsafe {
-        WriteFile(
+pub fn write(handle: FileDesc, mut bytes: &[u8]) -> Result<(), Error> {
+    while !bytes.is_empty() {
+        let mut written: DWORD = 0;
+        cvt(unsafe {
+            WriteFile(
+                handle,
+                bytes.as_ptr() as LPCVOID,
+                DWORD::try_from(bytes.len()).unwrap_or(DWORD::MAX),
+                &mut written,
+                ptr::null_mut(),
+            )
+        })?;
+        if written == 0 {
+            return Err(write_zero());
+        }
+        bytes = &bytes[written as usize ..];
+    }
+    Ok(())
+}
+
+/// Error reported when a write makes no progress.
+#[cfg(feature = "std")]
+fn write_zero() -> Error {
+    Error::new(std::io::ErrorKind::WriteZero, "failed to write whole buffer")
+}
+
+/// Error reported when a write makes no progress.
+#[cfg(not(feature = "std"))]
+fn write_zero() -> Error {
+    Error::from_raw_os_error(winapi::shared::winerror::ERROR_WRITE_FAULT as i32)
+}
+
+/// Flushes the data and metadata of the file to the disk.
+pub fn fsync(handle: FileDesc) -> Result<(), Error> {
+    cvt(unsafe { FlushFileBuffers(handle) })
+}
+
+/// Flushes the data of the file to the disk, which on Windows also flushes
+/// its metadata.
+pub fn sync_data(handle: FileDesc) -> Result<(), Error> {
+    fsync(handle)
+}
+
+/// Flushes the directory entry of the file at the given path to the disk. NTFS
+/// journals directory entries by itself, so nothing is done.
+pub fn sync_parent(_path: &OsStr) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Seeks the file referenced by the given HANDLE to its end.
+pub fn seek_end(handle: FileDesc) -> Result<(), Error> {
+    let distance: LARGE_INTEGER = unsafe { mem::zeroed() };
+    cvt(unsafe {
+        SetFilePointerEx(handle, distance, ptr::null_mut(), FILE_END)
+    })
+}
+
+/// Reads data from the given offset of the file referenced by the given
+/// HANDLE, keeping its current position. Returns the number of bytes read,
+/// which is zero at the end of the file.
+#[cfg(feature = "std")]
+pub fn read_at(
+    handle: FileDesc,
+    buf: &mut [u8],
+    offset: u64,
+) -> Result<usize, Error> {
+    let zero: LARGE_INTEGER = unsafe { mem::zeroed() };
+    let mut position: LARGE_INTEGER = unsafe { mem::zeroed() };
+    cvt(unsafe {
+        SetFilePointerEx(handle, zero, &mut position, FILE_CURRENT)
+    })?;
+
+    let mut overlapped = make_overlapped(offset)?;
+    let _drop_handle = DropHandle { handle: overlapped.hEvent };
+    let mut read: DWORD = 0;
+    let res = cvt(unsafe {
+        ReadFile(
             handle,
-            bytes.as_ptr() as LPCVOID,
-            bytes.len() as DWORD,
-            ptr::null_mut(),
-            ptr::null_mut(),
+            buf.as_mut_ptr() as LPVOID,
+            DWORD::try_from(buf.len()).unwrap_or(DWORD::MAX),
+            &mut read,
+            &mut overlapped as LPOVERLAPPED,
         )
-    };
-    if result == 0 {
-        Err(Error::last_os_error())
-    } else {
-        Ok(())
+    });
+    cvt(unsafe {
+        SetFilePointerEx(handle, position, ptr::null_mut(), FILE_BEGIN)
+    })?;
+    match res {
+        Ok(()) => Ok(read as usize),
+        Err(error) if error.raw_os_error() == Some(ERROR_HANDLE_EOF as i32) => {
+            Ok(0)
+        },
+        Err(error) => Err(error),
     }
 }
 
-pub fn fsync(handle: FileDesc) -> Result<(), Error> {
-    let result = unsafe { FlushFileBuffers(handle) };
-    if result == 0 {
-        Err(Error::last_os_error())
-    } else {
-        Ok(())
+/// Reads data from the current position of the file referenced by the given
+/// HANDLE. Returns the number of bytes read, which is zero at the end of the
+/// file.
+#[cfg(feature = "std")]
+pub fn read(handle: FileDesc, buf: &mut [u8]) -> Result<usize, Error> {
+    let mut read: DWORD = 0;
+    let res = cvt(unsafe {
+        ReadFile(
+            handle,
+            buf.as_mut_ptr() as LPVOID,
+            DWORD::try_from(buf.len()).unwrap_or(DWORD::MAX),
+            &mut read,
+            ptr::null_mut(),
+        )
+    });
+    match res {
+        Ok(()) => Ok(read as usize),
+        Err(error) if error.raw_os_error() == Some(ERROR_HANDLE_EOF as i32) => {
+            Ok(0)
+        },
+        Err(error) => Err(error),
     }
 }
 
+/// Seeks the file referenced by the given HANDLE, returning the new position
+/// from its start.
+#[cfg(feature = "std")]
+pub fn seek(handle: FileDesc, pos: std::io::SeekFrom) -> Result<u64, Error> {
+    let (offset, method) = match pos {
+        std::io::SeekFrom::Start(offset) => (offset as i64, FILE_BEGIN),
+        std::io::SeekFrom::Current(offset) => (offset, FILE_CURRENT),
+        std::io::SeekFrom::End(offset) => (offset, FILE_END),
+    };
+    let mut distance: LARGE_INTEGER = unsafe { mem::zeroed() };
+    unsafe { *distance.QuadPart_mut() = offset };
+    let mut position: LARGE_INTEGER = unsafe { mem::zeroed() };
+    cvt(unsafe { SetFilePointerEx(handle, distance, &mut position, method) })?;
+    Ok(unsafe { *position.QuadPart() } as u64)
+}
+
 /// Truncates the file referenced by the given HANDLE and seeks it to the start.
 pub fn truncate(handle: FileDesc) -> Result<(), Error> {
     let res = unsafe { SetFilePointer(handle, 0, ptr::null_mut(), FILE_BEGIN) };
     if res == INVALID_SET_FILE_POINTER {
-        return Err(Error::last_os_error());
+        return Err(last_error());
     }
+    cvt(unsafe { SetEndOfFile(handle) })
+}
 
-    let res = unsafe { SetEndOfFile(handle) };
-    if res == 0 {
-        Err(Error::last_os_error())
-    } else {
-        Ok(())
-    }
+/// Returns the PID of a process owning a lock on the file which conflicts with
+/// an exclusive lock. Windows does not report owners of byte-range locks, so
+/// `None` is always returned.
+#[cfg(feature = "std")]
+pub fn lock_owner(
+    _handle: FileDesc,
+    _backend: LockBackend,
+) -> Result<Option<u32>, Error> {
+    Ok(None)
+}
+
+/// Mechanism used to lock whole files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum LockBackend {
+    /// `LockFileEx` locks, held by the file handle.
+    #[default]
+    LockFileEx,
 }
 
 /// Tries to lock a file and blocks until it is possible to lock.
-pub fn lock(handle: FileDesc) -> Result<(), Error> {
-    let mut overlapped = make_overlapped()?;
-    let drop_handle = DropHandle { handle: overlapped.hEvent };
+pub fn lock(handle: FileDesc, backend: LockBackend) -> Result<(), Error> {
+    let LockBackend::LockFileEx = backend;
+    lock_file(
+        handle,
+        LOCKFILE_EXCLUSIVE_LOCK,
+        WHOLE_FILE_OFFSET,
+        WHOLE_FILE_LEN,
+    )
+}
+
+/// Tries to lock a file but returns as soon as possible if already locked.
+pub fn try_lock(handle: FileDesc, backend: LockBackend) -> Result<bool, Error> {
+    let LockBackend::LockFileEx = backend;
+    try_lock_file(
+        handle,
+        LOCKFILE_EXCLUSIVE_LOCK,
+        WHOLE_FILE_OFFSET,
+        WHOLE_FILE_LEN,
+    )
+}
+
+/// Tries to lock a file in shared mode and blocks until it is possible to
+/// lock.
+pub fn lock_shared(
+    handle: FileDesc,
+    backend: LockBackend,
+) -> Result<(), Error> {
+    let LockBackend::LockFileEx = backend;
+    lock_file(handle, 0, WHOLE_FILE_OFFSET, WHOLE_FILE_LEN)
+}
+
+/// Tries to lock a file in shared mode but returns as soon as possible if
+/// already exclusively locked.
+pub fn try_lock_shared(
+    handle: FileDesc,
+    backend: LockBackend,
+) -> Result<bool, Error> {
+    let LockBackend::LockFileEx = backend;
+    try_lock_file(handle, 0, WHOLE_FILE_OFFSET, WHOLE_FILE_LEN)
+}
+
+/// Unlocks the file.
+pub fn unlock(handle: FileDesc, backend: LockBackend) -> Result<(), Error> {
+    let LockBackend::LockFileEx = backend;
+    unlock_range(handle, WHOLE_FILE_OFFSET, WHOLE_FILE_LEN)
+}
+
+/// Tries to lock a byte range of a file and blocks until it is possible to
+/// lock. Range locks do not interact with whole-file locks.
+pub fn lock_range(
+    handle: FileDesc,
+    offset: u64,
+    len: u64,
+) -> Result<(), Error> {
+    lock_file(handle, LOCKFILE_EXCLUSIVE_LOCK, offset, len)
+}
+
+/// Tries to lock a byte range of a file but returns as soon as possible if
+/// already locked.
+pub fn try_lock_range(
+    handle: FileDesc,
+    offset: u64,
+    len: u64,
+) -> Result<bool, Error> {
+    try_lock_file(handle, LOCKFILE_EXCLUSIVE_LOCK, offset, len)
+}
+
+/// Unlocks a byte range of a file, which must match a locked range exactly.
+pub fn unlock_range(
+    handle: FileDesc,
+    offset: u64,
+    len: u64,
+) -> Result<(), Error> {
+    let mut overlapped = make_overlapped(offset)?;
+    let _drop_handle = DropHandle { handle: overlapped.hEvent };
     let res = unsafe {
-        LockFileEx(
+        UnlockFileEx(
             handle,
-            LOCKFILE_EXCLUSIVE_LOCK,
             0,
-            1,
-            1,
+            len as DWORD,
+            (len >> 32) as DWORD,
             &mut overlapped as LPOVERLAPPED,
         )
     };
-
-    let ret = if res == TRUE {
-        let res = unsafe { WaitForSingleObject(overlapped.hEvent, 0) };
-        if res != WAIT_FAILED {
-            Ok(())
-        } else {
-            Err(Error::last_os_error())
-        }
-    } else {
-        Err(Error::last_os_error())
-    };
-
-    drop(drop_handle);
-    ret
+    finish_overlapped(res, &overlapped)
 }
 
-/// Tries to lock a file but returns as soon as possible if already locked.
-pub fn try_lock(handle: FileDesc) -> Result<bool, Error> {
-    let mut overlapped = make_overlapped()?;
-    let drop_handle = DropHandle { handle: overlapped.hEvent };
+/// Locks the given region with `LockFileEx` and the given flags.
+fn lock_file(
+    handle: FileDesc,
+    flags: DWORD,
+    offset: u64,
+    len: u64,
+) -> Result<(), Error> {
+    let mut overlapped = make_overlapped(offset)?;
+    let _drop_handle = DropHandle { handle: overlapped.hEvent };
     let res = unsafe {
         LockFileEx(
             handle,
-            LOCKFILE_EXCLUSIVE_LOCK | LOCKFILE_FAIL_IMMEDIATELY,
+            flags,
             0,
-            1,
-            1,
+            len as DWORD,
+            (len >> 32) as DWORD,
             &mut overlapped as LPOVERLAPPED,
         )
     };
+    finish_overlapped(res, &overlapped)
+}
 
-    let ret = if res == TRUE {
-        let res = unsafe { WaitForSingleObject(overlapped.hEvent, 0) };
-        if res != WAIT_FAILED {
-            Ok(true)
-        } else {
-            Err(Error::last_os_error())
-        }
-    } else {
-        let err = unsafe { GetLastError() };
-        if err == ERROR_LOCK_VIOLATION {
+/// Like [`lock_file`], but fails immediately, returning whether the lock was
+/// acquired.
+fn try_lock_file(
+    handle: FileDesc,
+    flags: DWORD,
+    offset: u64,
+    len: u64,
+) -> Result<bool, Error> {
+    match lock_file(handle, flags | LOCKFILE_FAIL_IMMEDIATELY, offset, len) {
+        Ok(()) => Ok(true),
+        Err(error)
+            if error.raw_os_error() == Some(ERROR_LOCK_VIOLATION as i32) =>
+        {
             Ok(false)
-        } else {
-            Err(Error::from_raw_os_error(err as i32))
-        }
-    };
+        },
+        Err(error) => Err(error),
+    }
+}
 
-    drop(drop_handle);
-    ret
+/// Removes the file at the given path.
+pub fn remove(path: &OsStr) -> Result<(), Error> {
+    cvt(unsafe { DeleteFileW(path.chars.as_ptr()) })
 }
 
-/// Unlocks the file.
-pub fn unlock(handle: FileDesc) -> Result<(), Error> {
-    let mut overlapped = make_overlapped()?;
-    let drop_handle = DropHandle { handle: overlapped.hEvent };
-    let res = unsafe {
-        UnlockFileEx(handle, 0, 1, 1, &mut overlapped as LPOVERLAPPED)
+/// Sets the modification time of the file at the given path to the current
+/// time.
+#[cfg(feature = "std")]
+pub fn touch(path: &OsStr) -> Result<(), Error> {
+    let handle = unsafe {
+        CreateFileW(
+            path.chars.as_ptr(),
+            FILE_WRITE_ATTRIBUTES,
+            DEFAULT_SHARE_MODE,
+            ptr::null_mut(),
+            OPEN_EXISTING,
+            0,
+            ptr::null_mut(),
+        )
     };
+    let handle = cvt_handle(handle, INVALID_HANDLE_VALUE)?;
+    let _drop_handle = DropHandle { handle };
+    let mut now: FILETIME = unsafe { mem::zeroed() };
+    unsafe { GetSystemTimeAsFileTime(&mut now) };
+    cvt(unsafe { SetFileTime(handle, ptr::null(), ptr::null(), &now) })
+}
 
-    let ret = if res == TRUE {
-        let res = unsafe { WaitForSingleObject(overlapped.hEvent, 0) };
-        if res != WAIT_FAILED {
-            Ok(())
-        } else {
-            Err(Error::last_os_error())
-        }
-    } else {
-        Err(Error::last_os_error())
+/// Returns the volume serial number and file index of the given handle.
+#[cfg(feature = "std")]
+fn file_index(handle: FileDesc) -> Result<(DWORD, DWORD, DWORD), Error> {
+    let mut info = unsafe { mem::zeroed::<BY_HANDLE_FILE_INFORMATION>() };
+    cvt(unsafe { GetFileInformationByHandle(handle, &mut info) })?;
+    Ok((info.dwVolumeSerialNumber, info.nFileIndexHigh, info.nFileIndexLow))
+}
+
+/// Whether the given handle refers to the file currently at the given path,
+/// which is not the case if the file was deleted or replaced.
+#[cfg(feature = "std")]
+pub fn same_file(handle: FileDesc, path: &OsStr) -> Result<bool, Error> {
+    let named = unsafe {
+        CreateFileW(
+            path.chars.as_ptr(),
+            0,
+            DEFAULT_SHARE_MODE,
+            ptr::null_mut(),
+            OPEN_EXISTING,
+            FILE_FLAG_BACKUP_SEMANTICS,
+            ptr::null_mut(),
+        )
+    };
+    let named = match cvt_handle(named, INVALID_HANDLE_VALUE) {
+        Ok(named) => named,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(false)
+        },
+        Err(error) => return Err(error),
     };
+    let _drop_handle = DropHandle { handle: named };
+    Ok(file_index(handle)? == file_index(named)?)
+}
+
+/// Callback registered by [`on_termination`].
+static TERMINATION_CALLBACK: AtomicUsize = AtomicUsize::new(0);
 
-    drop(drop_handle);
-    ret
+/// Runs the termination callback on console control events which terminate
+/// the process, and lets the next handler in the chain run.
+unsafe extern "system" fn handle_termination(event: DWORD) -> BOOL {
+    match event {
+        CTRL_C_EVENT | CTRL_BREAK_EVENT | CTRL_CLOSE_EVENT
+        | CTRL_SHUTDOWN_EVENT => {
+            let callback = TERMINATION_CALLBACK.load(Ordering::Acquire);
+            if callback != 0 {
+                let callback: fn() = transmute(callback);
+                callback();
+            }
+        },
+        _ => (),
+    }
+    FALSE
+}
+
+/// Installs a console control handler calling `callback` when the process is
+/// asked to terminate (Ctrl-C, Ctrl-Break, console close or shutdown). Must
+/// be called at most once.
+pub fn on_termination(callback: fn()) -> Result<(), Error> {
+    TERMINATION_CALLBACK.store(callback as usize, Ordering::Release);
+    cvt(unsafe { SetConsoleCtrlHandler(Some(handle_termination), TRUE) })
+}
+
+/// Error reported when some fixed-capacity resource is exhausted.
+pub(crate) fn no_space() -> Error {
+    Error::from_raw_os_error(ERROR_NOT_ENOUGH_MEMORY as i32)
+}
+
+/// Duplicates the handle, referring to the same file object.
+pub fn duplicate(handle: FileDesc) -> Result<FileDesc, Error> {
+    let mut duplicate = ptr::null_mut();
+    cvt(unsafe {
+        DuplicateHandle(
+            GetCurrentProcess(),
+            handle,
+            GetCurrentProcess(),
+            &mut duplicate,
+            0,
+            FALSE,
+            DUPLICATE_SAME_ACCESS,
+        )
+    })?;
+    Ok(duplicate)
+}
+
+/// System calls are never interrupted by signals here.
+pub(crate) fn is_interrupted(_error: &Error) -> bool {
+    false
 }
 
 /// Closes the file.
@@ -540,3 +1039,30 @@ pub fn close(handle: FileDesc) {
 pub(crate) fn uninitialized_fd() -> FileDesc {
     ptr::null_mut()
 }
+
+/// Takes the ownership of a file handle.
+///
+/// # Safety
+/// The handle must be open and owned by no one else.
+pub(crate) unsafe fn own(handle: FileDesc) -> OwnedDesc {
+    #[cfg(feature = "std")]
+    {
+        std::os::windows::io::FromRawHandle::from_raw_handle(handle.cast())
+    }
+    #[cfg(not(feature = "std"))]
+    {
+        handle
+    }
+}
+
+/// Gives up the ownership of a file handle, which is no longer closed.
+pub(crate) fn disown(handle: OwnedDesc) -> FileDesc {
+    #[cfg(feature = "std")]
+    {
+        std::os::windows::io::IntoRawHandle::into_raw_handle(handle).cast()
+    }
+    #[cfg(not(feature = "std"))]
+    {
+        handle
+    }
+}
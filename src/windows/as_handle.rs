@@ -0,0 +1,18 @@
+use crate::LockFile;
+use std::os::windows::io::{AsHandle, AsRawHandle, BorrowedHandle, RawHandle};
+
+/// Borrow the handle of the [`LockFile`], such as to pass it to crates taking
+/// [`BorrowedHandle`]. The handle is still owned by the [`LockFile`]; closing
+/// or locking it through other means confuses the state of the latter.
+impl AsHandle for LockFile {
+    fn as_handle(&self) -> BorrowedHandle<'_> {
+        unsafe { BorrowedHandle::borrow_raw(self.as_raw_handle()) }
+    }
+}
+
+/// Get the raw handle of the [`LockFile`], still owned by it.
+impl AsRawHandle for LockFile {
+    fn as_raw_handle(&self) -> RawHandle {
+        unsafe { self.raw().cast() }
+    }
+}
@@ -9,8 +9,17 @@ use crate::LockFile;
 /// ```
 #[doc = include_str!("../../examples/lock_preserved.rs")]
 /// ```
-impl Into<File> for &mut LockFile {
-    fn into(self) -> File {
-        unsafe { File::from_raw_handle(self.raw() as *mut c_void) }
+impl From<&mut LockFile> for File {
+    fn from(lock: &mut LockFile) -> File {
+        unsafe { File::from_raw_handle(lock.raw() as *mut c_void) }
+    }
+}
+
+/// Turn the [`LockFile`] into a [`std::fs::File`] owning its handle. The lock
+/// owned by the handle, if any, stays held by the file until it is closed, and
+/// the file is neither truncated nor removed.
+impl From<LockFile> for File {
+    fn from(mut lock: LockFile) -> File {
+        unsafe { File::from_raw_handle(lock.take_desc() as *mut c_void) }
     }
 }
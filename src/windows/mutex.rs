@@ -0,0 +1,159 @@
+//! This module implements locks on named mutexes of the Windows kernel, which
+//! need no file at all.
+
+use crate::Error;
+use std::{iter, ptr};
+use winapi::{
+    shared::{minwindef::FALSE, winerror::WAIT_TIMEOUT},
+    um::{
+        handleapi::CloseHandle,
+        synchapi::{CreateMutexW, ReleaseMutex, WaitForSingleObject},
+        winbase::{INFINITE, WAIT_ABANDONED, WAIT_OBJECT_0},
+        winnt::HANDLE,
+    },
+};
+
+/// A lock on a named mutex of the Windows kernel, with an API mirroring
+/// [`crate::LockFile`], for when a lock file is undesirable: sandboxed
+/// applications may be unable to write to shared directories, and cleanup
+/// services may delete lock files from under their owners. The mutex lives as
+/// long as any handle to it is open, and is released by the kernel if its
+/// owner exits without unlocking it.
+///
+/// Mutexes are owned by threads rather than handles, so a handle must be
+/// unlocked by the thread which locked it, and is thus neither [`Send`] nor
+/// [`Sync`]. Handles locked by the same thread do not exclude each other.
+///
+/// # Example
+/// ```
+/// # fn main() -> Result<(), fslock::Error> {
+/// use fslock::NamedMutex;
+///
+/// let mut mutex = NamedMutex::open("fslock-mutex-doctest")?;
+/// mutex.lock()?;
+/// do_stuff();
+/// mutex.unlock()?;
+///
+/// # Ok(())
+/// # }
+/// # fn do_stuff() {
+/// #    // doing stuff here.
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct NamedMutex {
+    /// Handle to the mutex.
+    handle: HANDLE,
+    /// Name of the mutex, including its namespace.
+    name: String,
+    /// Whether this handle owns the mutex.
+    locked: bool,
+}
+
+impl NamedMutex {
+    /// Opens the mutex with the given name in the `Global\` namespace, shared
+    /// by all sessions, creating it if it does not exist. Creating global
+    /// mutexes from sessions other than the services' one requires the
+    /// `SeCreateGlobalPrivilege`; see [`NamedMutex::open_local`] otherwise.
+    ///
+    /// # Panics
+    /// Panics if the name contains a nul-byte.
+    pub fn open(name: &str) -> Result<Self, Error> {
+        Self::open_in("Global", name)
+    }
+
+    /// Opens the mutex with the given name in the `Local\` namespace, private
+    /// to the session of this process, creating it if it does not exist.
+    ///
+    /// # Panics
+    /// Panics if the name contains a nul-byte.
+    pub fn open_local(name: &str) -> Result<Self, Error> {
+        Self::open_in("Local", name)
+    }
+
+    /// Opens the mutex with the given name in the given namespace.
+    fn open_in(namespace: &str, name: &str) -> Result<Self, Error> {
+        if name.contains('\0') {
+            panic!("Mutex names must not contain nul-bytes");
+        }
+        let name = format!("{}\\{}", namespace, name);
+        let wide: Vec<u16> = name.encode_utf16().chain(iter::once(0)).collect();
+        let handle =
+            unsafe { CreateMutexW(ptr::null_mut(), FALSE, wide.as_ptr()) };
+        if handle.is_null() {
+            return Err(Error::last_os_error());
+        }
+        Ok(Self { handle, name, locked: false })
+    }
+
+    /// Returns the name of the mutex, including its namespace.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns whether this handle owns the mutex.
+    pub fn owns_lock(&self) -> bool {
+        self.locked
+    }
+
+    /// Waits on the mutex for the given number of milliseconds, returning
+    /// whether it was acquired. A mutex abandoned by an owner which exited is
+    /// acquired as well.
+    fn wait(&mut self, millis: u32) -> Result<bool, Error> {
+        if self.locked {
+            panic!("Cannot lock if already owning a lock");
+        }
+        match unsafe { WaitForSingleObject(self.handle, millis) } {
+            WAIT_OBJECT_0 | WAIT_ABANDONED => {
+                self.locked = true;
+                Ok(true)
+            },
+            WAIT_TIMEOUT => Ok(false),
+            _ => Err(Error::last_os_error()),
+        }
+    }
+
+    /// Locks the mutex, blocking until it is released by its owner.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns the mutex.
+    pub fn lock(&mut self) -> Result<(), Error> {
+        self.wait(INFINITE)?;
+        Ok(())
+    }
+
+    /// Locks the mutex if it is not owned by another thread. Does NOT block.
+    /// Returns whether it was locked.
+    ///
+    /// # Panics
+    /// Panics if this handle already owns the mutex.
+    pub fn try_lock(&mut self) -> Result<bool, Error> {
+        self.wait(0)
+    }
+
+    /// Unlocks the mutex.
+    ///
+    /// # Panics
+    /// Panics if this handle does not own the mutex.
+    pub fn unlock(&mut self) -> Result<(), Error> {
+        if !self.locked {
+            panic!("Attempted to unlock already unlocked mutex");
+        }
+        self.locked = false;
+        if unsafe { ReleaseMutex(self.handle) } == FALSE {
+            return Err(Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl Drop for NamedMutex {
+    fn drop(&mut self) {
+        if self.locked {
+            let _ = self.unlock();
+        }
+        unsafe {
+            CloseHandle(self.handle);
+        }
+    }
+}